@@ -0,0 +1,103 @@
+use std::collections::BTreeMap;
+
+use crate::{YamlNode, YamlValue};
+
+/// Fluent construction of a mapping; later entries replace earlier ones
+/// with the same key.
+#[derive(Debug, Clone, Default)]
+pub struct MapBuilder {
+    entries: BTreeMap<String, YamlNode>,
+}
+
+impl MapBuilder {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn str(self, key: impl Into<String>, value: impl Into<String>) -> Self {
+        self.value(key, YamlValue::Str(value.into()))
+    }
+
+    pub fn seq(self, key: impl Into<String>, build: impl FnOnce(SeqBuilder) -> SeqBuilder) -> Self {
+        self.value(key, build(SeqBuilder::new()).build())
+    }
+
+    pub fn map(self, key: impl Into<String>, build: impl FnOnce(MapBuilder) -> MapBuilder) -> Self {
+        self.value(key, build(MapBuilder::new()).build())
+    }
+
+    pub fn value(self, key: impl Into<String>, value: YamlValue) -> Self {
+        self.node(key, YamlNode::new(value))
+    }
+
+    /// Adds a prepared node, e.g. one carrying comments or a tag.
+    pub fn node(mut self, key: impl Into<String>, node: YamlNode) -> Self {
+        self.entries.insert(key.into(), node);
+        self
+    }
+
+    pub fn build(self) -> YamlValue {
+        YamlValue::Map(self.entries)
+    }
+}
+
+/// Fluent construction of a sequence, in push order.
+#[derive(Debug, Clone, Default)]
+pub struct SeqBuilder {
+    items: Vec<YamlNode>,
+}
+
+impl SeqBuilder {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn str(self, value: impl Into<String>) -> Self {
+        self.value(YamlValue::Str(value.into()))
+    }
+
+    pub fn seq(self, build: impl FnOnce(SeqBuilder) -> SeqBuilder) -> Self {
+        self.value(build(SeqBuilder::new()).build())
+    }
+
+    pub fn map(self, build: impl FnOnce(MapBuilder) -> MapBuilder) -> Self {
+        self.value(build(MapBuilder::new()).build())
+    }
+
+    pub fn value(self, value: YamlValue) -> Self {
+        self.node(YamlNode::new(value))
+    }
+
+    pub fn node(mut self, node: YamlNode) -> Self {
+        self.items.push(node);
+        self
+    }
+
+    pub fn build(self) -> YamlValue {
+        YamlValue::Seq(self.items)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{dump_naay, parse_naay};
+
+    #[test]
+    fn builds_a_dumpable_document() {
+        let value = MapBuilder::new()
+            .str("_naay_version", "1.0")
+            .str("name", "demo")
+            .seq("hosts", |b| {
+                b.str("a").map(|m| m.str("host", "b").str("port", "80"))
+            })
+            .map("empty", |m| m)
+            .build();
+
+        let dumped = dump_naay(&value).expect("dump should succeed");
+        let expected = "_naay_version: \"1.0\"\nempty: {}\nhosts:\n  - \"a\"\n  -\n    host: \"b\"\n    port: \"80\"\nname: \"demo\"\n";
+        assert_eq!(dumped, expected);
+        let reparsed = parse_naay(&dumped).expect("parse should succeed");
+        assert!(reparsed.semantic_eq(&value));
+    }
+}
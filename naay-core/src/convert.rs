@@ -0,0 +1,285 @@
+use std::collections::BTreeMap;
+
+use crate::{YamlNode, YamlValue};
+
+#[derive(Debug, thiserror::Error)]
+pub enum ConvertError {
+    #[error("conflicting path '{path}': {message}")]
+    Conflict { path: String, message: String },
+    #[error("cannot convert '{path}': {message}")]
+    Unsupported { path: String, message: String },
+    #[error("invalid INI at line {line}: {message}")]
+    Ini { line: usize, message: String },
+}
+
+pub fn from_env_pairs(
+    pairs: &[(String, String)],
+    separator: &str,
+) -> Result<YamlValue, ConvertError> {
+    let mut root = BTreeMap::new();
+    for (name, value) in pairs {
+        let segments: Vec<&str> = if separator.is_empty() {
+            vec![name.as_str()]
+        } else {
+            name.split(separator).collect()
+        };
+        if segments.iter().any(|s| s.is_empty()) {
+            return Err(ConvertError::Conflict {
+                path: name.clone(),
+                message: "empty path segment".to_string(),
+            });
+        }
+        insert_env_path(&mut root, &segments, value, name)?;
+    }
+    Ok(YamlValue::Map(root))
+}
+
+/// Writes a two-level document as INI: root scalars first, then one
+/// `[section]` per root mapping of scalars.
+pub fn to_ini(value: &YamlValue) -> Result<String, ConvertError> {
+    let root = value.as_map().ok_or_else(|| ConvertError::Unsupported {
+        path: "/".to_string(),
+        message: "root must be a mapping".to_string(),
+    })?;
+    let mut out = String::new();
+    let mut sections = Vec::new();
+    for (key, node) in root {
+        match &node.value {
+            YamlValue::Map(section) => sections.push((key, section)),
+            _ => push_ini_entry(&mut out, "", key, &node.value)?,
+        }
+    }
+    for (name, section) in sections {
+        if name.contains([']', '\n']) {
+            return Err(unsupported(
+                &format!("/{name}"),
+                "section names cannot contain ']' or newlines",
+            ));
+        }
+        if !out.is_empty() {
+            out.push('\n');
+        }
+        out.push('[');
+        out.push_str(name);
+        out.push_str("]\n");
+        for (key, node) in section {
+            push_ini_entry(&mut out, name, key, &node.value)?;
+        }
+    }
+    Ok(out)
+}
+
+fn push_ini_entry(
+    out: &mut String,
+    section: &str,
+    key: &str,
+    value: &YamlValue,
+) -> Result<(), ConvertError> {
+    let path = if section.is_empty() {
+        format!("/{key}")
+    } else {
+        format!("/{section}/{key}")
+    };
+    let YamlValue::Str(s) = value else {
+        return Err(unsupported(
+            &path,
+            &format!("INI values must be scalars, found {}", value.type_name()),
+        ));
+    };
+    if key.is_empty() || key.contains(['=', '\n']) || key.starts_with(['[', ';', '#']) {
+        return Err(unsupported(&path, "key cannot be written as an INI key"));
+    }
+    if s.contains('\n') {
+        return Err(unsupported(&path, "INI values cannot span lines"));
+    }
+    out.push_str(key);
+    out.push_str(" = ");
+    out.push_str(s);
+    out.push('\n');
+    Ok(())
+}
+
+fn unsupported(path: &str, message: &str) -> ConvertError {
+    ConvertError::Unsupported {
+        path: path.to_string(),
+        message: message.to_string(),
+    }
+}
+
+/// Reads INI into a two-level document: keys before the first section go
+/// to the root, each `[section]` becomes a mapping. `;` and `#` start
+/// comment lines.
+pub fn from_ini(input: &str) -> Result<YamlValue, ConvertError> {
+    let mut root: BTreeMap<String, YamlNode> = BTreeMap::new();
+    let mut section: Option<String> = None;
+    for (idx, raw) in input.lines().enumerate() {
+        let line = idx + 1;
+        let trimmed = raw.trim();
+        if trimmed.is_empty() || trimmed.starts_with([';', '#']) {
+            continue;
+        }
+        if let Some(name) = trimmed.strip_prefix('[') {
+            let name = name.strip_suffix(']').ok_or_else(|| ConvertError::Ini {
+                line,
+                message: "unterminated section header".to_string(),
+            })?;
+            let name = name.trim().to_string();
+            let node = root
+                .entry(name.clone())
+                .or_insert_with(|| YamlNode::new(YamlValue::Map(BTreeMap::new())));
+            if !node.value.is_map() {
+                return Err(ConvertError::Conflict {
+                    path: format!("/{name}"),
+                    message: "section name is already a root key".to_string(),
+                });
+            }
+            section = Some(name);
+            continue;
+        }
+        let (key, value) = trimmed.split_once('=').ok_or_else(|| ConvertError::Ini {
+            line,
+            message: "expected 'key = value'".to_string(),
+        })?;
+        let key = key.trim().to_string();
+        let value = YamlNode::new(YamlValue::Str(value.trim().to_string()));
+        let target = match &section {
+            Some(name) => match root.get_mut(name).map(|node| &mut node.value) {
+                Some(YamlValue::Map(map)) => map,
+                _ => unreachable!("sections are inserted as mappings"),
+            },
+            None => &mut root,
+        };
+        if target.get(&key).is_some_and(|node| node.value.is_map()) {
+            return Err(ConvertError::Conflict {
+                path: format!("/{key}"),
+                message: "key is already a section".to_string(),
+            });
+        }
+        target.insert(key, value);
+    }
+    Ok(YamlValue::Map(root))
+}
+
+fn insert_env_path(
+    root: &mut BTreeMap<String, YamlNode>,
+    segments: &[&str],
+    value: &str,
+    name: &str,
+) -> Result<(), ConvertError> {
+    let (last, parents) = segments
+        .split_last()
+        .expect("split always yields at least one segment");
+    let mut current = root;
+    for segment in parents {
+        let node = current
+            .entry(segment.to_string())
+            .or_insert_with(|| YamlNode::new(YamlValue::Map(BTreeMap::new())));
+        current = match &mut node.value {
+            YamlValue::Map(map) => map,
+            _ => {
+                return Err(ConvertError::Conflict {
+                    path: name.to_string(),
+                    message: format!("'{segment}' is already a scalar value"),
+                })
+            }
+        };
+    }
+    if let Some(existing) = current.get(*last) {
+        if matches!(existing.value, YamlValue::Map(_)) {
+            return Err(ConvertError::Conflict {
+                path: name.to_string(),
+                message: format!("'{last}' is already a mapping"),
+            });
+        }
+    }
+    current.insert(
+        last.to_string(),
+        YamlNode::new(YamlValue::Str(value.to_string())),
+    );
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn pairs(items: &[(&str, &str)]) -> Vec<(String, String)> {
+        items
+            .iter()
+            .map(|(k, v)| (k.to_string(), v.to_string()))
+            .collect()
+    }
+
+    #[test]
+    fn builds_nested_document_from_env_pairs() {
+        let value = from_env_pairs(
+            &pairs(&[
+                ("DB__HOST", "localhost"),
+                ("DB__PORT", "5432"),
+                ("DEBUG", "1"),
+            ]),
+            "__",
+        )
+        .expect("conversion should succeed");
+
+        let YamlValue::Map(root) = &value else {
+            panic!("root should be a map");
+        };
+        assert_eq!(root["DEBUG"].value, YamlValue::Str("1".to_string()));
+        let YamlValue::Map(db) = &root["DB"].value else {
+            panic!("DB should be a map");
+        };
+        assert_eq!(db["HOST"].value, YamlValue::Str("localhost".to_string()));
+        assert_eq!(db["PORT"].value, YamlValue::Str("5432".to_string()));
+    }
+
+    #[test]
+    fn rejects_value_and_submap_at_same_key() {
+        let err = from_env_pairs(&pairs(&[("DB", "x"), ("DB__HOST", "y")]), "__")
+            .expect_err("conflict should error");
+        assert!(err.to_string().contains("DB__HOST"));
+
+        let err = from_env_pairs(&pairs(&[("DB__HOST", "y"), ("DB", "x")]), "__")
+            .expect_err("conflict should error");
+        assert!(err.to_string().contains("already a mapping"));
+    }
+
+    #[test]
+    fn round_trips_two_sections_through_ini() {
+        let input = r#"_naay_version: "1.0"
+database:
+  host: "localhost"
+  port: "5432"
+server:
+  name: "web 1"
+"#;
+        let parsed = crate::parse_naay(input).expect("parse should succeed");
+        let ini = to_ini(&parsed).expect("conversion should succeed");
+        assert_eq!(
+            ini,
+            "_naay_version = 1.0\n\n[database]\nhost = localhost\nport = 5432\n\n[server]\nname = web 1\n"
+        );
+        let back = from_ini(&ini).expect("INI should parse");
+        assert!(back.semantic_eq(&parsed));
+    }
+
+    #[test]
+    fn to_ini_rejects_deeper_nesting() {
+        let nested = "_naay_version: \"1.0\"\nserver:\n  tls:\n    cert: \"a.pem\"\n";
+        let parsed = crate::parse_naay(nested).expect("parse should succeed");
+        let err = to_ini(&parsed).expect_err("nested map should error");
+        assert_eq!(
+            err.to_string(),
+            "cannot convert '/server/tls': INI values must be scalars, found map"
+        );
+        let list = "_naay_version: \"1.0\"\nlist:\n  - \"a\"\n";
+        let parsed = crate::parse_naay(list).expect("parse should succeed");
+        assert!(to_ini(&parsed).is_err());
+
+        let err = from_ini("[open\n").expect_err("bad header should error");
+        assert_eq!(
+            err.to_string(),
+            "invalid INI at line 1: unterminated section header"
+        );
+    }
+}
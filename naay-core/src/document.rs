@@ -0,0 +1,197 @@
+use std::borrow::Cow;
+
+use crate::inspect::lookup_pointer;
+use crate::{
+    dump_naay_with, parse_naay, CommentLine, DumpError, DumpOptions, LineEnding, ParseError,
+    YamlNode, YamlValue,
+};
+
+const VERSION_KEY: &str = "_naay_version";
+
+/// A parsed naay document whose root has passed the `_naay_version` check.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Document {
+    root: YamlValue,
+    shebang: Option<String>,
+}
+
+/// Comments attached to the `_naay_version` entry.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct VersionComments<'a> {
+    pub leading: &'a [CommentLine],
+    pub inline: Option<&'a str>,
+}
+
+impl Document {
+    /// A `#!` first line is kept as the document's shebang rather than as a
+    /// comment, and `dump` writes it back as the first line.
+    pub fn parse(input: &str) -> Result<Self, ParseError> {
+        let first = input.split_inclusive('\n').next().unwrap_or_default();
+        let (shebang, body) = match first.starts_with("#!") {
+            // Blanked rather than cut, so reported lines still match.
+            true => (
+                Some(first.trim_end().to_string()),
+                Cow::Owned(format!("\n{}", &input[first.len()..])),
+            ),
+            false => (None, Cow::Borrowed(input)),
+        };
+        parse_naay(&body).map(|root| Self { root, shebang })
+    }
+
+    /// The `#!` line the input started with, if any.
+    pub fn shebang(&self) -> Option<&str> {
+        self.shebang.as_deref()
+    }
+
+    pub fn dump(&self) -> Result<String, DumpError> {
+        self.dump_with(&DumpOptions::default())
+    }
+
+    /// Dumps the root with `options`, after the shebang line if there is one.
+    pub fn dump_with(&self, options: &DumpOptions) -> Result<String, DumpError> {
+        let mut out = String::new();
+        if let Some(shebang) = &self.shebang {
+            out.push_str(shebang);
+            out.push_str(match options.line_ending {
+                LineEnding::Lf => "\n",
+                LineEnding::CrLf => "\r\n",
+            });
+        }
+        out.push_str(&dump_naay_with(&self.root, options)?);
+        Ok(out)
+    }
+
+    pub fn root(&self) -> &YamlValue {
+        &self.root
+    }
+
+    pub fn into_root(self) -> YamlValue {
+        self.root
+    }
+
+    pub fn version(&self) -> &str {
+        match self.version_node().map(|n| &n.value) {
+            Some(YamlValue::Str(ver)) => ver.trim(),
+            _ => unreachable!("parse_naay validated _naay_version"),
+        }
+    }
+
+    pub fn version_comments(&self) -> VersionComments<'_> {
+        let node = self.version_node();
+        VersionComments {
+            leading: node.map(|n| n.leading_comments.as_slice()).unwrap_or(&[]),
+            inline: node.and_then(|n| n.inline_comment.as_deref()),
+        }
+    }
+
+    /// Leading comments of the node at a pointer path (e.g. `/server/port`),
+    /// stripped of their `#` markers and joined line by line, for tools that
+    /// treat comments as field documentation.
+    pub fn doc_for(&self, path: &str) -> Option<String> {
+        let node = lookup_pointer(&self.root, path)?;
+        if node.leading_comments.is_empty() {
+            return None;
+        }
+        let lines: Vec<&str> = node
+            .leading_comments
+            .iter()
+            .map(|c| {
+                let text = c.text.strip_prefix('#').unwrap_or(&c.text);
+                text.strip_prefix(' ').unwrap_or(text).trim_end()
+            })
+            .collect();
+        Some(lines.join("\n"))
+    }
+
+    fn version_node(&self) -> Option<&YamlNode> {
+        match &self.root {
+            YamlValue::Map(map) => map.get(VERSION_KEY),
+            _ => None,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn exposes_version_line_comments() {
+        let input = r#"# schema header
+_naay_version: "1.0" # force version
+name: "demo"
+"#;
+
+        let doc = Document::parse(input).expect("parse should succeed");
+        assert_eq!(doc.version(), "1.0");
+        let comments = doc.version_comments();
+        assert_eq!(comments.inline, Some("# force version"));
+        assert_eq!(comments.leading.len(), 1);
+        assert_eq!(comments.leading[0].text, "# schema header");
+    }
+
+    #[test]
+    fn doc_for_joins_leading_comment_lines() {
+        let input = r#"_naay_version: "1.0"
+server:
+  # Port the HTTP listener binds to.
+  # Must be above 1024 when not running as root.
+  port: "8080"
+  host: "localhost"
+  tls:
+    cert: "a.pem"
+    #   indented note
+    key: "a.key"
+"#;
+
+        let doc = Document::parse(input).expect("parse should succeed");
+        assert_eq!(
+            doc.doc_for("/server/port").as_deref(),
+            Some("Port the HTTP listener binds to.\nMust be above 1024 when not running as root.")
+        );
+        assert_eq!(doc.doc_for("/server/host"), None);
+        assert_eq!(doc.doc_for("/server/tls/key").as_deref(), Some("  indented note"));
+        assert_eq!(doc.doc_for("/missing"), None);
+    }
+
+    #[test]
+    fn shebang_line_stays_first() {
+        let input = r#"#!/usr/bin/env naay
+# regular comment
+Zeta: "sorted before the version"
+_naay_version: "1.0"
+"#;
+        let doc = Document::parse(input).expect("parse should succeed");
+        assert_eq!(doc.shebang(), Some("#!/usr/bin/env naay"));
+        let options = DumpOptions {
+            header_comment: Some("generated".to_string()),
+            version_first: false,
+            ..DumpOptions::default()
+        };
+        let dumped = doc.dump_with(&options).expect("dump should succeed");
+        let mut lines = dumped.lines();
+        assert_eq!(lines.next(), Some("#!/usr/bin/env naay"));
+        assert_eq!(lines.next(), Some("# generated"));
+        assert_eq!(lines.next(), Some("# regular comment"));
+        assert_eq!(dumped.matches("#!").count(), 1);
+
+        let plain = doc.dump().expect("dump should succeed");
+        let reparsed = Document::parse(&plain).expect("reparse should succeed");
+        assert_eq!(reparsed.dump().expect("dump should succeed"), plain);
+        assert!(plain.starts_with("#!/usr/bin/env naay\n"));
+    }
+
+    #[test]
+    fn shebang_is_only_read_from_the_first_line() {
+        let input = "_naay_version: \"1.0\"\nname: demo\n#!/not/a/shebang\nlast: x\n";
+        let doc = Document::parse(input).expect("parse should succeed");
+        assert_eq!(doc.shebang(), None);
+        let dumped = doc.dump().expect("dump should succeed");
+        assert!(dumped.starts_with("_naay_version"), "{dumped}");
+        assert!(dumped.contains("name: demo\n#!/not/a/shebang\nlast: x\n"), "{dumped}");
+
+        let err = Document::parse("#!/usr/bin/env naay\n_naay_version: \"1.0\"\nbad\n").unwrap_err();
+        let ParseError::Generic { line, .. } = err;
+        assert_eq!(line, 3);
+    }
+}
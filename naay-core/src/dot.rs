@@ -0,0 +1,102 @@
+use std::fmt::Write;
+
+use crate::YamlValue;
+
+const MAX_LABEL_CHARS: usize = 24;
+
+/// Renders the structure of `value` as a Graphviz DOT digraph. Collections
+/// are labeled with their key (or index) and kind, scalars become leaves
+/// showing a truncated value.
+pub fn to_dot(value: &YamlValue) -> String {
+    let mut out = String::from("digraph naay {\n    node [shape=box];\n");
+    let mut next_id = 0usize;
+    // (parent id, label prefix, value)
+    let mut stack = vec![(None, "root".to_string(), value)];
+    while let Some((parent, name, value)) = stack.pop() {
+        let id = next_id;
+        next_id += 1;
+        let label = match value {
+            YamlValue::Null => format!("{name}: ~"),
+            YamlValue::Str(s) => format!("{name}: {}", truncate(s)),
+            YamlValue::Seq(seq) => format!("{name} (seq, {})", seq.len()),
+            YamlValue::Map(map) => format!("{name} (map, {})", map.len()),
+        };
+        let shape = if value.is_str() || value.is_null() {
+            ", shape=ellipse"
+        } else {
+            ""
+        };
+        let _ = writeln!(out, "    n{id} [label=\"{}\"{shape}];", escape(&label));
+        if let Some(parent) = parent {
+            let _ = writeln!(out, "    n{parent} -> n{id};");
+        }
+        // Pushed in reverse so children are emitted in document order.
+        match value {
+            YamlValue::Null | YamlValue::Str(_) => {}
+            YamlValue::Seq(seq) => stack.extend(
+                seq.iter()
+                    .enumerate()
+                    .rev()
+                    .map(|(i, node)| (Some(id), format!("[{i}]"), &node.value)),
+            ),
+            YamlValue::Map(map) => stack.extend(
+                map.iter()
+                    .rev()
+                    .map(|(k, node)| (Some(id), k.clone(), &node.value)),
+            ),
+        }
+    }
+    out.push_str("}\n");
+    out
+}
+
+fn truncate(s: &str) -> String {
+    let first_line = s.lines().next().unwrap_or("");
+    if first_line.chars().count() > MAX_LABEL_CHARS || first_line.len() < s.len() {
+        let head: String = first_line.chars().take(MAX_LABEL_CHARS).collect();
+        format!("{}...", head.trim_end())
+    } else {
+        first_line.to_string()
+    }
+}
+
+fn escape(label: &str) -> String {
+    label.replace('\\', "\\\\").replace('"', "\\\"")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::parse_naay;
+
+    #[test]
+    fn emits_nodes_and_nesting_edges() {
+        let input = r#"_naay_version: "1.0"
+server:
+  host: "localhost"
+  tags:
+    - 'a "quoted" tag'
+notes: "a value that is far too long to show in full"
+"#;
+        let parsed = parse_naay(input).expect("parse should succeed");
+        let dot = to_dot(&parsed);
+        assert!(dot.starts_with("digraph naay {\n"));
+        assert!(dot.ends_with("}\n"));
+        assert!(dot.contains("n0 [label=\"root (map, 3)\"];"));
+        assert!(dot.contains("n1 [label=\"_naay_version: 1.0\", shape=ellipse];"));
+        assert!(dot.contains("n2 [label=\"notes: a value that is far too...\", shape=ellipse];"));
+        assert!(dot.contains("n3 [label=\"server (map, 2)\"];"));
+        assert!(dot.contains("n4 [label=\"host: localhost\", shape=ellipse];"));
+        assert!(dot.contains("n5 [label=\"tags (seq, 1)\"];"));
+        assert!(dot.contains("n6 [label=\"[0]: a \\\"quoted\\\" tag\", shape=ellipse];"));
+        for edge in [
+            "n0 -> n1;",
+            "n0 -> n3;",
+            "n3 -> n4;",
+            "n3 -> n5;",
+            "n5 -> n6;",
+        ] {
+            assert!(dot.contains(edge), "missing edge {edge}");
+        }
+    }
+}
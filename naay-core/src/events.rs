@@ -0,0 +1,410 @@
+use std::collections::{BTreeMap, HashSet, VecDeque};
+use std::mem;
+
+use crate::inspect::{lookup_pointer, unescape_segment};
+use crate::{
+    check_version, preprocess, root_not_a_map, Frame, ParseError, ParseMachine, ParseOptions,
+    TabPolicy, YamlNode, YamlValue,
+};
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Event {
+    MapStart,
+    MapKey(String),
+    SeqStart,
+    Scalar(String),
+    /// An empty value, as in `key:`.
+    Null,
+    /// Closes the innermost `MapStart` or `SeqStart`.
+    End,
+}
+
+/// Pull parser yielding a document as events. It drives the same frame
+/// machine as `parse_naay`, but hands each top-level entry off as soon as it
+/// is complete, so only one entry is held in memory at a time. Comments,
+/// spans and tags are not reported.
+///
+/// Root keys are reported once, with the value `parse_naay` would give them:
+/// a `<<` merge never re-emits a key that is already out. A key repeated
+/// after it was streamed cannot be taken back, so it is an error here, where
+/// `parse_naay` would keep the last value.
+pub struct Parser<'a> {
+    machine: Option<ParseMachine<'a>>,
+    queue: VecDeque<Event>,
+    emitted: HashSet<String>,
+    version: Option<YamlValue>,
+    first_line: usize,
+    done: bool,
+}
+
+enum Pending<'v> {
+    Value(&'v YamlValue),
+    Key(&'v str),
+    End,
+}
+
+impl<'a> Parser<'a> {
+    /// `TabPolicy::ConvertToSpaces` rewrites the input up front, which a
+    /// borrowing parser cannot do; expand tabs before streaming instead.
+    pub fn new(input: &'a str, options: &'a ParseOptions) -> Result<Self, ParseError> {
+        if let TabPolicy::ConvertToSpaces(_) = options.tab_policy {
+            return Err(ParseError::Generic {
+                line: 1,
+                column: 1,
+                message: "the streaming parser does not support TabPolicy::ConvertToSpaces"
+                    .to_string(),
+            });
+        }
+        let lines = preprocess(input, options)?;
+        let first_line = lines.first().map_or(1, |l| l.line_no);
+        let machine = ParseMachine::new(lines, options)?;
+        if !matches!(machine.stack.first(), Some(Frame::Map(_))) {
+            return Err(root_not_a_map(first_line));
+        }
+        Ok(Self {
+            machine: Some(machine),
+            queue: VecDeque::from([Event::MapStart]),
+            emitted: HashSet::new(),
+            version: None,
+            first_line,
+            done: false,
+        })
+    }
+
+    fn emit_entries(&mut self, entries: BTreeMap<String, YamlNode>) {
+        for (key, node) in document_order(&entries) {
+            if key == "_naay_version" {
+                self.version = Some(node.value.clone());
+            }
+            self.queue.push_back(Event::MapKey(key.clone()));
+            emit_value(&mut self.queue, &node.value);
+        }
+    }
+
+    // Steps the machine until the root frame holds completed entries and
+    // takes them. Returns `None` once the document is exhausted.
+    fn next_entries(&mut self) -> Result<Option<BTreeMap<String, YamlNode>>, ParseError> {
+        loop {
+            let Some(machine) = self.machine.as_mut() else {
+                return Ok(None);
+            };
+            match machine.step_once() {
+                Ok(Some(root)) => {
+                    self.machine = None;
+                    let YamlValue::Map(mut rest) = root else {
+                        return Ok(None);
+                    };
+                    let rest = take_completed(&mut rest, &mut self.emitted)?;
+                    return Ok((!rest.is_empty()).then_some(rest));
+                }
+                Ok(None) => {
+                    // Back at the root frame: everything it holds is complete.
+                    if let [Frame::Map(root)] = machine.stack.as_mut_slice() {
+                        let completed = take_completed(&mut root.entries, &mut self.emitted)?;
+                        if !completed.is_empty() {
+                            return Ok(Some(completed));
+                        }
+                    }
+                }
+                Err(err) => {
+                    self.machine = None;
+                    return Err(err);
+                }
+            }
+        }
+    }
+}
+
+impl Iterator for Parser<'_> {
+    type Item = Result<Event, ParseError>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        loop {
+            if let Some(event) = self.queue.pop_front() {
+                return Some(Ok(event));
+            }
+            if self.done {
+                return None;
+            }
+            match self.next_entries() {
+                Ok(Some(entries)) => self.emit_entries(entries),
+                Ok(None) => {
+                    self.done = true;
+                    if let Err(err) = check_version(self.version.as_ref(), self.first_line) {
+                        return Some(Err(err));
+                    }
+                    self.queue.push_back(Event::End);
+                }
+                Err(err) => {
+                    self.done = true;
+                    self.queue.clear();
+                    return Some(Err(err));
+                }
+            }
+        }
+    }
+}
+
+/// Looks up one pointer path (e.g. `/server/tls/cert`) while parsing, and
+/// stops as soon as the top-level entry containing it is complete. Nothing
+/// after that entry is read, so the version check is skipped as well.
+pub fn stream_value(input: &str, path: &str) -> Result<Option<YamlValue>, ParseError> {
+    let Some(root_key) = path
+        .strip_prefix('/')
+        .map(|p| p.split('/').next().unwrap_or(p))
+    else {
+        return Ok(None);
+    };
+    let root_key = unescape_segment(root_key);
+    let options = ParseOptions::default();
+    let mut parser = Parser::new(input, &options)?;
+    while let Some(entries) = parser.next_entries()? {
+        if entries.contains_key(&root_key) {
+            let root = YamlValue::Map(entries);
+            return Ok(lookup_pointer(&root, path).map(|node| node.value.clone()));
+        }
+    }
+    Ok(None)
+}
+
+// Takes the root entries completed since the last call. Each emitted key
+// keeps a span-less `Null` placeholder in the root frame, so a later `<<`
+// finds the slot taken just as it would in `parse_naay`, without the frame
+// holding on to the value.
+fn take_completed(
+    entries: &mut BTreeMap<String, YamlNode>,
+    emitted: &mut HashSet<String>,
+) -> Result<BTreeMap<String, YamlNode>, ParseError> {
+    let mut completed = BTreeMap::new();
+    for (key, node) in entries.iter_mut() {
+        if emitted.contains(key) {
+            match node.span {
+                None => continue,
+                Some(span) => {
+                    return Err(ParseError::Generic {
+                        line: span.line,
+                        column: span.column,
+                        message: format!(
+                            "top-level key '{key}' repeats after it was already streamed"
+                        ),
+                    });
+                }
+            }
+        }
+        emitted.insert(key.clone());
+        let node = mem::replace(node, YamlNode::new(YamlValue::Null));
+        completed.insert(key.clone(), node);
+    }
+    Ok(completed)
+}
+
+// Map entries in the order they were written, as `dump_naay` writes them;
+// entries with neither span nor order keep their sorted place at the end.
+fn document_order(map: &BTreeMap<String, YamlNode>) -> Vec<(&String, &YamlNode)> {
+    let mut entries: Vec<_> = map.iter().collect();
+    entries.sort_by_key(|(_, node)| {
+        node.span.map_or((usize::MAX, node.order.unwrap_or(usize::MAX)), |s| (s.line, s.column))
+    });
+    entries
+}
+
+fn emit_value(queue: &mut VecDeque<Event>, value: &YamlValue) {
+    let mut pending = vec![Pending::Value(value)];
+    while let Some(item) = pending.pop() {
+        match item {
+            Pending::End => queue.push_back(Event::End),
+            Pending::Key(key) => queue.push_back(Event::MapKey(key.to_string())),
+            Pending::Value(YamlValue::Null) => queue.push_back(Event::Null),
+            Pending::Value(YamlValue::Str(s)) => queue.push_back(Event::Scalar(s.clone())),
+            Pending::Value(YamlValue::Seq(seq)) => {
+                queue.push_back(Event::SeqStart);
+                pending.push(Pending::End);
+                pending.extend(seq.iter().rev().map(|node| Pending::Value(&node.value)));
+            }
+            Pending::Value(YamlValue::Map(map)) => {
+                queue.push_back(Event::MapStart);
+                pending.push(Pending::End);
+                for (key, node) in document_order(map).into_iter().rev() {
+                    pending.push(Pending::Value(&node.value));
+                    pending.push(Pending::Key(key));
+                }
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn streams_events_for_nested_document() {
+        let input = r#"_naay_version: "1.0"
+server:
+  host: "localhost"
+  ports:
+    - "80"
+    - "443"
+name: "demo"
+"#;
+        let options = ParseOptions::default();
+        let events: Vec<Event> = Parser::new(input, &options)
+            .expect("parser should start")
+            .collect::<Result<_, _>>()
+            .expect("stream should succeed");
+        let key = |k: &str| Event::MapKey(k.to_string());
+        let scalar = |s: &str| Event::Scalar(s.to_string());
+        assert_eq!(
+            events,
+            vec![
+                Event::MapStart,
+                key("_naay_version"),
+                scalar("1.0"),
+                key("server"),
+                Event::MapStart,
+                key("host"),
+                scalar("localhost"),
+                key("ports"),
+                Event::SeqStart,
+                scalar("80"),
+                scalar("443"),
+                Event::End,
+                Event::End,
+                key("name"),
+                scalar("demo"),
+                Event::End,
+            ]
+        );
+    }
+
+    #[test]
+    fn reports_errors_in_the_stream() {
+        let options = ParseOptions::default();
+        let mut parser = Parser::new("name: \"demo\"\n", &options).expect("parser should start");
+        let mut next = || parser.next().map(|r| r.ok());
+        assert_eq!(next(), Some(Some(Event::MapStart)));
+        assert_eq!(next(), Some(Some(Event::MapKey("name".to_string()))));
+        assert_eq!(next(), Some(Some(Event::Scalar("demo".to_string()))));
+        assert_eq!(next(), Some(None));
+        assert_eq!(next(), None);
+    }
+
+    #[test]
+    fn stream_value_stops_after_the_target_entry() {
+        let input = r#"_naay_version: "1.0"
+server:
+  tls:
+    cert: "server.pem"
+  port: "443"
+broken:
+  this line has no colon
+"#;
+        assert!(crate::parse_naay(input).is_err());
+        let cert = stream_value(input, "/server/tls/cert").expect("lookup should succeed");
+        assert_eq!(cert, Some(YamlValue::Str("server.pem".to_string())));
+        assert_eq!(
+            stream_value(input, "/server/missing").expect("lookup should succeed"),
+            None
+        );
+        assert_eq!(
+            stream_value(input, "/_naay_version").expect("lookup should succeed"),
+            Some(YamlValue::Str("1.0".to_string()))
+        );
+    }
+
+    // Rebuilds a value from a stream so it can be compared with `parse_naay`.
+    fn collect(first: Event, events: &mut dyn Iterator<Item = Event>) -> YamlValue {
+        match first {
+            Event::MapStart => {
+                let mut map = BTreeMap::new();
+                while let Some(Event::MapKey(key)) = events.next() {
+                    let first = events.next().expect("a key is followed by a value");
+                    map.insert(key, YamlNode::new(collect(first, events)));
+                }
+                YamlValue::Map(map)
+            }
+            Event::SeqStart => {
+                let mut seq = Vec::new();
+                while let Some(event) = events.next().filter(|e| *e != Event::End) {
+                    seq.push(YamlNode::new(collect(event, events)));
+                }
+                YamlValue::Seq(seq)
+            }
+            Event::Scalar(s) => YamlValue::Str(s),
+            Event::Null | Event::MapKey(_) | Event::End => YamlValue::Null,
+        }
+    }
+
+    #[test]
+    fn root_merges_stream_like_parse_naay() {
+        let input = r#"_naay_version: "1.0"
+a: &a
+  host: "a"
+  port: "1"
+b: &b
+  host: "b"
+  user: "b"
+host: "local"
+<<: *b
+<<: [*a, *b]
+"#;
+        let options = ParseOptions::default();
+        let events: Vec<Event> = Parser::new(input, &options)
+            .expect("parser should start")
+            .collect::<Result<_, _>>()
+            .expect("stream should succeed");
+        let keys: Vec<&Event> = events
+            .iter()
+            .filter(|e| matches!(e, Event::MapKey(k) if k == "host" || k == "user"))
+            .collect();
+        assert_eq!(keys.len(), 5, "root host and user are reported once each");
+
+        let mut events = events.into_iter();
+        let first = events.next().expect("stream should start");
+        let streamed = collect(first, &mut events);
+        assert!(streamed.semantic_eq(&crate::parse_naay(input).expect("should parse")));
+    }
+
+    #[test]
+    fn repeated_root_key_is_an_error_once_streamed() {
+        let options = ParseOptions::default();
+        let input = "_naay_version: \"1.0\"\nname: \"a\"\nname: \"b\"\n";
+        let result: Result<Vec<Event>, _> = Parser::new(input, &options)
+            .expect("parser should start")
+            .collect();
+        let err = result.expect_err("a repeated root key should fail");
+        assert!(err.to_string().contains("'name'"), "{err}");
+    }
+
+    #[test]
+    fn events_rebuild_what_parse_naay_gives() {
+        let input = r#"_naay_version: "1.0"
+z:
+  b: "x"
+  a: "y"
+list:
+  - k: "1"
+    j: "2"
+  - "plain"
+empty:
+"#;
+        let options = ParseOptions::default();
+        let events: Vec<Event> = Parser::new(input, &options)
+            .expect("parser should start")
+            .collect::<Result<_, _>>()
+            .expect("stream should succeed");
+        let keys: Vec<&str> = events
+            .iter()
+            .filter_map(|e| match e {
+                Event::MapKey(k) => Some(k.as_str()),
+                _ => None,
+            })
+            .collect();
+        assert_eq!(keys, ["_naay_version", "z", "b", "a", "list", "k", "j", "empty"]);
+
+        let mut events = events.into_iter();
+        let first = events.next().expect("stream should start");
+        let rebuilt = collect(first, &mut events);
+        assert!(rebuilt.semantic_eq(&crate::parse_naay(input).expect("should parse")));
+    }
+}
@@ -0,0 +1,136 @@
+use std::collections::BTreeMap;
+
+use crate::{dump_naay_with, parse_naay, DumpOptions, Error};
+
+/// Infers the dominant indentation step (e.g. 2 or 4) of a document by
+/// counting how far each nested line steps in from its parent. Returns
+/// `None` when nothing is nested.
+pub fn detect_indent(input: &str) -> Option<usize> {
+    let mut steps: BTreeMap<usize, usize> = BTreeMap::new();
+    let mut stack: Vec<usize> = Vec::new();
+    for raw in input.lines() {
+        let content = raw.trim_start_matches(' ');
+        let trimmed = content.trim();
+        if trimmed.is_empty() || trimmed.starts_with('#') {
+            continue;
+        }
+        let indent = raw.len() - content.len();
+        while stack.last().is_some_and(|&top| top > indent) {
+            stack.pop();
+        }
+        match stack.last() {
+            Some(&top) if top == indent => {}
+            Some(&top) => {
+                *steps.entry(indent - top).or_insert(0) += 1;
+                stack.push(indent);
+            }
+            None => stack.push(indent),
+        }
+    }
+    // Most frequent step wins; BTreeMap order breaks ties toward the smaller step.
+    steps
+        .into_iter()
+        .fold(
+            None,
+            |best: Option<(usize, usize)>, (step, count)| match best {
+                Some((_, best_count)) if best_count >= count => best,
+                _ => Some((step, count)),
+            },
+        )
+        .map(|(step, _)| step)
+}
+
+/// Canonical form for comparing dumps, e.g. against golden files: trailing
+/// whitespace is stripped from every line and the text ends in exactly one
+/// newline (none when it is empty).
+pub fn normalize_dump(text: &str) -> String {
+    let mut out = String::with_capacity(text.len());
+    for line in text.lines() {
+        out.push_str(line.trim_end());
+        out.push('\n');
+    }
+    out.truncate(out.trim_end_matches('\n').len());
+    if !out.is_empty() {
+        out.push('\n');
+    }
+    out
+}
+
+/// Parses `input` and dumps it again with `to` spaces per nesting level;
+/// comments move with the keys they sit above.
+pub fn reindent(input: &str, to: usize) -> Result<String, Error> {
+    let value = parse_naay(input)?;
+    let options = DumpOptions {
+        indent: to,
+        reindent_comments: true,
+        ..DumpOptions::default()
+    };
+    Ok(dump_naay_with(&value, &options)?)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn detects_two_and_four_space_steps() {
+        let two = "_naay_version: \"1.0\"\nouter:\n  inner:\n    leaf: \"x\"\n  list:\n    - a\n";
+        assert_eq!(detect_indent(two), Some(2));
+
+        let four = "_naay_version: \"1.0\"\nouter:\n    # note\n    inner:\n        leaf: \"x\"\n    other: \"y\"\n";
+        assert_eq!(detect_indent(four), Some(4));
+    }
+
+    #[test]
+    fn normalize_dump_ignores_trailing_whitespace() {
+        let golden = "_naay_version: \"1.0\"\nitems:\n  -\n    name: a\n";
+        let noisy = "_naay_version: \"1.0\"  \r\nitems:\n  - \n    name: a\t\n\n\n";
+        assert_eq!(normalize_dump(noisy), golden);
+        assert_eq!(normalize_dump(golden), golden);
+        assert_eq!(normalize_dump("\n\n"), "");
+
+        let value = parse_naay(golden).expect("parse should succeed");
+        let dumped = crate::dump_naay(&value).expect("dump should succeed");
+        assert_eq!(normalize_dump(&dumped), dumped);
+    }
+
+    #[test]
+    fn flat_document_has_no_step() {
+        assert_eq!(detect_indent("_naay_version: \"1.0\"\nname: \"x\"\n"), None);
+    }
+
+    #[test]
+    fn reindents_four_spaces_to_two() {
+        let four = r#"_naay_version: "1.0"
+server:
+    hosts:
+        - "a"
+        -
+            name: "b"
+    motd: |
+        line one
+        line two
+"#;
+        let two = reindent(four, 2).expect("reindent should succeed");
+        assert_eq!(detect_indent(&two), Some(2));
+        assert_eq!(
+            two,
+            "_naay_version: \"1.0\"\nserver:\n  hosts:\n    - \"a\"\n    -\n      name: \"b\"\n  motd: |\n    line one\n    line two\n"
+        );
+        let before = parse_naay(four).expect("parse should succeed");
+        let after = parse_naay(&two).expect("parse should succeed");
+        assert!(before.semantic_eq(&after));
+        assert_eq!(reindent(&two, 4).expect("reindent should succeed"), four);
+    }
+
+    #[test]
+    fn reindent_moves_comments_with_their_keys() {
+        let four = "_naay_version: \"1.0\"\nouter:\n    # note\n    inner:\n        leaf: \"x\"\n";
+        let two = reindent(four, 2).expect("reindent should succeed");
+        assert_eq!(
+            two,
+            "_naay_version: \"1.0\"\nouter:\n  # note\n  inner:\n    leaf: \"x\"\n"
+        );
+        assert_eq!(reindent(&two, 4).expect("reindent should succeed"), four);
+    }
+}
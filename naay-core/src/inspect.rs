@@ -0,0 +1,444 @@
+use std::collections::{btree_map, BTreeMap, BTreeSet};
+use std::slice;
+
+use crate::{push_pointer_segment, YamlNode, YamlValue};
+
+/// Deepest collection nesting in `value`: scalars are 0 and every map or
+/// sequence level adds one. Walks with an explicit stack so very deep trees
+/// cannot overflow.
+pub fn max_depth(value: &YamlValue) -> usize {
+    let mut deepest = 0;
+    let mut stack = vec![(value, 0usize)];
+    while let Some((value, depth)) = stack.pop() {
+        match value {
+            YamlValue::Null | YamlValue::Str(_) => deepest = deepest.max(depth),
+            YamlValue::Seq(seq) => {
+                deepest = deepest.max(depth + 1);
+                stack.extend(seq.iter().map(|node| (&node.value, depth + 1)));
+            }
+            YamlValue::Map(map) => {
+                deepest = deepest.max(depth + 1);
+                stack.extend(map.values().map(|node| (&node.value, depth + 1)));
+            }
+        }
+    }
+    deepest
+}
+
+/// Whether every element of a sequence has the same kind (see
+/// `YamlValue::type_name`); an empty sequence counts. `None` for non-sequences.
+pub fn is_homogeneous_seq(value: &YamlValue) -> Option<bool> {
+    let seq = value.as_seq()?;
+    let mut kinds = seq.iter().map(|node| node.value.type_name());
+    let first = kinds.next();
+    Some(kinds.all(|kind| Some(kind) == first))
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub enum PathSegment {
+    Key(String),
+    Index(usize),
+}
+
+impl YamlValue {
+    /// Depth-first, pre-order walk over every node below this value, each
+    /// paired with its path from here. Children are only expanded as the
+    /// walk reaches them.
+    pub fn iter_nodes(&self) -> Nodes<'_> {
+        let mut nodes = Nodes { stack: Vec::new() };
+        nodes.push_children(&[], self);
+        nodes
+    }
+
+    /// Structural equality that ignores comments, spans and quoting hints.
+    /// Tags still count, as they change what a scalar means.
+    pub fn semantic_eq(&self, other: &Self) -> bool {
+        let mut stack = vec![(self, other)];
+        while let Some((a, b)) = stack.pop() {
+            match (a, b) {
+                (YamlValue::Null, YamlValue::Null) => {}
+                (YamlValue::Str(a), YamlValue::Str(b)) if a == b => {}
+                (YamlValue::Seq(a), YamlValue::Seq(b)) if a.len() == b.len() => {
+                    for (x, y) in a.iter().zip(b) {
+                        if x.tag != y.tag {
+                            return false;
+                        }
+                        stack.push((&x.value, &y.value));
+                    }
+                }
+                (YamlValue::Map(a), YamlValue::Map(b)) if a.len() == b.len() => {
+                    for ((ka, x), (kb, y)) in a.iter().zip(b) {
+                        if ka != kb || x.tag != y.tag {
+                            return false;
+                        }
+                        stack.push((&x.value, &y.value));
+                    }
+                }
+                _ => return false,
+            }
+        }
+        true
+    }
+}
+
+pub struct Nodes<'a> {
+    stack: Vec<(Vec<PathSegment>, &'a YamlNode)>,
+}
+
+impl<'a> Nodes<'a> {
+    fn push_children(&mut self, path: &[PathSegment], value: &'a YamlValue) {
+        let child_path = |segment| {
+            let mut child = path.to_vec();
+            child.push(segment);
+            child
+        };
+        match value {
+            YamlValue::Null | YamlValue::Str(_) => {}
+            YamlValue::Seq(seq) => self.stack.extend(
+                seq.iter()
+                    .enumerate()
+                    .rev()
+                    .map(|(i, node)| (child_path(PathSegment::Index(i)), node)),
+            ),
+            YamlValue::Map(map) => self.stack.extend(
+                map.iter()
+                    .rev()
+                    .map(|(k, node)| (child_path(PathSegment::Key(k.clone())), node)),
+            ),
+        }
+    }
+}
+
+impl<'a> Iterator for Nodes<'a> {
+    type Item = (Vec<PathSegment>, &'a YamlNode);
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let (path, node) = self.stack.pop()?;
+        self.push_children(&path, &node.value);
+        Some((path, node))
+    }
+}
+
+/// Pointer path (e.g. `/a/b/2`) of the first place, depth-first, where the
+/// two values differ; `/` when the roots themselves differ. Comments, spans
+/// and quoting are ignored, as in `YamlValue::semantic_eq`.
+pub fn first_diff(a: &YamlValue, b: &YamlValue) -> Option<String> {
+    let mut stack = vec![(String::new(), Some(a), Some(b), false)];
+    while let Some((path, a, b, tags_differ)) = stack.pop() {
+        let at = |path: String| {
+            Some(if path.is_empty() {
+                "/".to_string()
+            } else {
+                path
+            })
+        };
+        if tags_differ {
+            return at(path);
+        }
+        let mut children = Vec::new();
+        match (a, b) {
+            (Some(YamlValue::Null), Some(YamlValue::Null)) => {}
+            (Some(YamlValue::Str(x)), Some(YamlValue::Str(y))) if x == y => {}
+            (Some(YamlValue::Seq(x)), Some(YamlValue::Seq(y))) => {
+                for i in 0..x.len().max(y.len()) {
+                    children.push((i.to_string(), x.get(i), y.get(i)));
+                }
+            }
+            (Some(YamlValue::Map(x)), Some(YamlValue::Map(y))) => {
+                let keys: BTreeSet<&String> = x.keys().chain(y.keys()).collect();
+                for key in keys {
+                    children.push((key.clone(), x.get(key), y.get(key)));
+                }
+            }
+            _ => return at(path),
+        }
+        for (segment, x, y) in children.into_iter().rev() {
+            let mut child = path.clone();
+            push_pointer_segment(&mut child, &segment);
+            let tags_differ = matches!((x, y), (Some(x), Some(y)) if x.tag != y.tag);
+            stack.push((child, x.map(|n| &n.value), y.map(|n| &n.value), tags_differ));
+        }
+    }
+    None
+}
+
+// Resolves a JSON-pointer style path (`/key/0/other`) below `root`.
+pub(crate) fn lookup_pointer<'v>(root: &'v YamlValue, path: &str) -> Option<&'v YamlNode> {
+    let mut segments = path.strip_prefix('/')?.split('/');
+    let first = unescape_segment(segments.next()?);
+    let mut node = root.as_map()?.get(&first)?;
+    for segment in segments {
+        let segment = unescape_segment(segment);
+        node = match &node.value {
+            YamlValue::Map(map) => map.get(&segment)?,
+            YamlValue::Seq(seq) => seq.get(segment.parse::<usize>().ok()?)?,
+            YamlValue::Null | YamlValue::Str(_) => return None,
+        };
+    }
+    Some(node)
+}
+
+pub(crate) fn unescape_segment(segment: &str) -> String {
+    segment.replace("~1", "/").replace("~0", "~")
+}
+
+/// Deep clone of `value` reduced to what it means: keys in sorted order and
+/// nodes stripped of comments, spans and quoting hints. Tags are kept. Two
+/// documents that differ only in layout canonicalize to equal trees.
+pub fn canonicalize(value: &YamlValue) -> YamlValue {
+    let mut stack: Vec<Open<'_>> = Vec::new();
+    let mut completed: Option<YamlNode> = None;
+    let mut visit = Some((value, None));
+    loop {
+        if let Some((value, tag)) = visit.take() {
+            match value {
+                YamlValue::Null => completed = Some(bare(YamlValue::Null, tag)),
+                YamlValue::Str(s) => completed = Some(bare(YamlValue::Str(s.clone()), tag)),
+                YamlValue::Seq(seq) => stack.push(Open::Seq {
+                    out: Vec::with_capacity(seq.len()),
+                    rest: seq.iter(),
+                    tag,
+                }),
+                YamlValue::Map(map) => stack.push(Open::Map {
+                    out: BTreeMap::new(),
+                    rest: map.iter(),
+                    key: None,
+                    tag,
+                }),
+            }
+        }
+        let Some(top) = stack.last_mut() else {
+            return completed
+                .map(|node| node.value)
+                .unwrap_or_else(|| value.clone());
+        };
+        if let Some(node) = completed.take() {
+            top.attach(node);
+        }
+        match top.next_child() {
+            Some(child) => visit = Some((&child.value, child.tag.clone())),
+            None => completed = stack.pop().map(Open::finish),
+        }
+    }
+}
+
+enum Open<'v> {
+    Seq {
+        out: Vec<YamlNode>,
+        rest: slice::Iter<'v, YamlNode>,
+        tag: Option<String>,
+    },
+    Map {
+        out: BTreeMap<String, YamlNode>,
+        rest: btree_map::Iter<'v, String, YamlNode>,
+        key: Option<String>,
+        tag: Option<String>,
+    },
+}
+
+impl<'v> Open<'v> {
+    fn next_child(&mut self) -> Option<&'v YamlNode> {
+        match self {
+            Open::Seq { rest, .. } => rest.next(),
+            Open::Map { rest, key, .. } => rest.next().map(|(k, node)| {
+                *key = Some(k.clone());
+                node
+            }),
+        }
+    }
+
+    fn attach(&mut self, node: YamlNode) {
+        match self {
+            Open::Seq { out, .. } => out.push(node),
+            Open::Map { out, key, .. } => {
+                out.insert(key.take().expect("child follows its key"), node);
+            }
+        }
+    }
+
+    fn finish(self) -> YamlNode {
+        match self {
+            Open::Seq { out, tag, .. } => bare(YamlValue::Seq(out), tag),
+            Open::Map { out, tag, .. } => bare(YamlValue::Map(out), tag),
+        }
+    }
+}
+
+fn bare(value: YamlValue, tag: Option<String>) -> YamlNode {
+    let mut node = YamlNode::new(value);
+    node.tag = tag;
+    node
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{parse_naay, YamlNode};
+
+    #[test]
+    fn reports_deepest_nesting() {
+        let input = r#"_naay_version: "1.0"
+flat: "x"
+outer:
+  inner:
+    - "a"
+    -
+      deep:
+        leaf: "y"
+"#;
+        let parsed = parse_naay(input).expect("parse should succeed");
+        assert_eq!(max_depth(&parsed), 5);
+        assert_eq!(max_depth(&YamlValue::Str("x".to_string())), 0);
+        assert_eq!(max_depth(&YamlValue::Seq(Vec::new())), 1);
+    }
+
+    #[test]
+    fn handles_very_deep_trees() {
+        let mut value = YamlValue::Str("leaf".to_string());
+        for _ in 0..100_000 {
+            value = YamlValue::Seq(vec![YamlNode::new(value)]);
+        }
+        assert_eq!(max_depth(&value), 100_000);
+        // Unwind iteratively too; the derived Drop would recurse.
+        while let YamlValue::Seq(mut seq) = value {
+            value = match seq.pop() {
+                Some(node) => node.value,
+                None => break,
+            };
+        }
+    }
+
+    #[test]
+    fn canonicalize_ignores_order_and_presentation() {
+        let first = r#"_naay_version: "1.0"
+# server settings
+server:
+  port: '8080'
+  host: localhost # inline
+list:
+  - "a"
+  - b
+"#;
+        let second = r#"_naay_version: "1.0"
+list:
+  - a
+  - 'b'
+server:
+  host: "localhost"
+  port: "8080"
+"#;
+        let a = parse_naay(first).expect("parse should succeed");
+        let b = parse_naay(second).expect("parse should succeed");
+        assert_ne!(a, b);
+        let canonical = canonicalize(&a);
+        assert_eq!(canonical, canonicalize(&b));
+        let YamlValue::Map(root) = &canonical else {
+            panic!("root should be a map");
+        };
+        assert!(root["server"].leading_comments.is_empty());
+        assert_eq!(root["server"].span, None);
+    }
+
+    #[test]
+    fn iter_nodes_walks_depth_first_with_paths() {
+        let input = r#"_naay_version: "1.0"
+server:
+  hosts:
+    - "a"
+    - "b"
+  port: "80"
+"#;
+        let parsed = parse_naay(input).expect("parse should succeed");
+        let paths: Vec<Vec<PathSegment>> = parsed.iter_nodes().map(|(path, _)| path).collect();
+        assert_eq!(paths.len(), 6);
+        let key = |k: &str| PathSegment::Key(k.to_string());
+        assert_eq!(paths[1], vec![key("server")]);
+        assert_eq!(
+            paths[3],
+            vec![key("server"), key("hosts"), PathSegment::Index(0)]
+        );
+
+        let (_, node) = parsed
+            .iter_nodes()
+            .find(|(path, _)| path == &[key("server"), key("hosts"), PathSegment::Index(1)])
+            .expect("path should be visited");
+        assert_eq!(node.value, YamlValue::Str("b".to_string()));
+    }
+
+    #[test]
+    fn detects_homogeneous_sequences() {
+        let input = r#"_naay_version: "1.0"
+names:
+  - "a"
+  - "b"
+mixed:
+  - "a"
+  - key: "v"
+empty: []
+"#;
+        let parsed = parse_naay(input).expect("parse should succeed");
+        let root = parsed.as_map().expect("root should be a map");
+        assert_eq!(is_homogeneous_seq(&root["names"].value), Some(true));
+        assert_eq!(is_homogeneous_seq(&root["mixed"].value), Some(false));
+        assert_eq!(is_homogeneous_seq(&root["empty"].value), Some(true));
+        assert_eq!(is_homogeneous_seq(&parsed), None);
+        assert_eq!(root["mixed"].value.type_name(), "seq");
+    }
+
+    #[test]
+    fn semantic_eq_ignores_comments() {
+        let first = r#"_naay_version: "1.0"
+# listener
+port: "80" # http
+hosts:
+  - "a"
+"#;
+        let second = r#"_naay_version: "1.0"
+port: '80'
+hosts:
+  - a # primary
+"#;
+        let a = parse_naay(first).expect("parse should succeed");
+        let b = parse_naay(second).expect("parse should succeed");
+        assert_ne!(a, b);
+        assert!(a.semantic_eq(&b));
+
+        let c = parse_naay("_naay_version: \"1.0\"\nport: \"81\"\nhosts:\n  - \"a\"\n")
+            .expect("parse should succeed");
+        assert!(!a.semantic_eq(&c));
+    }
+
+    #[test]
+    fn first_diff_reports_the_earliest_differing_path() {
+        let first = r#"_naay_version: "1.0"
+a:
+  b:
+    - "x"
+    - "y"
+    - "z" # trailing comment
+  c: "same"
+d: "1"
+"#;
+        let second = r#"_naay_version: "1.0"
+a:
+  b:
+    - x
+    - 'y'
+    - "Z"
+  c: "same"
+d: "2"
+"#;
+        let a = parse_naay(first).expect("parse should succeed");
+        let b = parse_naay(second).expect("parse should succeed");
+        assert_eq!(first_diff(&a, &b).as_deref(), Some("/a/b/2"));
+        assert_eq!(first_diff(&a, &a), None);
+
+        let shorter = parse_naay("_naay_version: \"1.0\"\na:\n  b:\n    - \"x\"\n")
+            .expect("parse should succeed");
+        assert_eq!(first_diff(&a, &shorter).as_deref(), Some("/a/b/1"));
+        assert_eq!(
+            first_diff(&a, &YamlValue::Str("x".to_string())).as_deref(),
+            Some("/")
+        );
+    }
+}
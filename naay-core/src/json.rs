@@ -0,0 +1,107 @@
+use std::collections::BTreeMap;
+
+use serde_json::Value;
+
+use crate::{ConvertError, YamlNode, YamlValue};
+
+/// Comments and formatting hints are dropped; scalars become JSON strings.
+impl From<YamlValue> for Value {
+    fn from(value: YamlValue) -> Self {
+        match value {
+            YamlValue::Null => Value::Null,
+            YamlValue::Str(s) => Value::String(s),
+            YamlValue::Seq(seq) => {
+                Value::Array(seq.into_iter().map(|node| node.value.into()).collect())
+            }
+            YamlValue::Map(map) => Value::Object(
+                map.into_iter()
+                    .map(|(key, node)| (key, node.value.into()))
+                    .collect(),
+            ),
+        }
+    }
+}
+
+/// Numbers and booleans become plain scalars holding their JSON text.
+/// Only a mapping can stand as a document root.
+impl TryFrom<Value> for YamlValue {
+    type Error = ConvertError;
+
+    fn try_from(value: Value) -> Result<Self, Self::Error> {
+        if !value.is_object() {
+            return Err(ConvertError::Unsupported {
+                path: "/".to_string(),
+                message: "root must be an object".to_string(),
+            });
+        }
+        Ok(from_json(value))
+    }
+}
+
+fn from_json(value: Value) -> YamlValue {
+    match value {
+        Value::Null => YamlValue::Null,
+        Value::Bool(b) => YamlValue::Str(b.to_string()),
+        Value::Number(n) => YamlValue::Str(n.to_string()),
+        Value::String(s) => YamlValue::Str(s),
+        Value::Array(items) => YamlValue::Seq(
+            items
+                .into_iter()
+                .map(|item| YamlNode::new(from_json(item)))
+                .collect(),
+        ),
+        Value::Object(map) => YamlValue::Map(
+            map.into_iter()
+                .map(|(key, item)| (key, YamlNode::new(from_json(item))))
+                .collect::<BTreeMap<_, _>>(),
+        ),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{dump_naay, parse_naay};
+
+    #[test]
+    fn round_trips_through_json() {
+        let input = r#"_naay_version: "1.0"
+# dropped on the way through JSON
+name: demo
+empty:
+ports:
+  - "80"
+  - "443"
+server:
+  host: localhost
+"#;
+        let value = parse_naay(input).expect("parse should succeed");
+        let json = Value::from(value.clone());
+        assert_eq!(json["ports"][1], "443");
+        assert_eq!(json["empty"], Value::Null);
+        assert_eq!(json["server"]["host"], "localhost");
+
+        let back = YamlValue::try_from(json.clone()).expect("object root converts");
+        let dumped = dump_naay(&back).expect("dump should succeed");
+        let reparsed = parse_naay(&dumped).expect("dump should parse");
+        assert_eq!(Value::from(reparsed), json);
+    }
+
+    #[test]
+    fn json_numbers_and_bools_become_scalars() {
+        let json = serde_json::json!({
+            "_naay_version": "1.0",
+            "port": 8080,
+            "ratio": 0.5,
+            "debug": true,
+        });
+        let value = YamlValue::try_from(json).expect("object root converts");
+        let view = value.view();
+        assert_eq!(view.get("port").and_then(|v| v.as_i64()), Some(8080));
+        assert_eq!(view.get("ratio").and_then(|v| v.as_str()), Some("0.5"));
+        assert_eq!(view.get("debug").and_then(|v| v.as_bool()), Some(true));
+
+        let err = YamlValue::try_from(serde_json::json!(["a"])).expect_err("array root");
+        assert!(err.to_string().contains("root must be an object"));
+    }
+}
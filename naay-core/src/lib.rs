@@ -1,12 +1,44 @@
-use std::collections::{BTreeMap, HashMap};
+use std::borrow::Cow;
+use std::collections::btree_map::Entry;
+use std::collections::{BTreeMap, HashMap, HashSet};
+use std::fmt::Write as _;
+use std::io;
 use std::marker::PhantomData;
 use std::mem;
 use tailcall::trampoline::{self, Next};
 
+mod builder;
+mod convert;
+mod document;
+mod dot;
+mod events;
+mod format;
+mod inspect;
+#[cfg(feature = "json")]
+mod json;
+mod schema;
+mod units;
+mod view;
+mod visit;
+
+pub use builder::{MapBuilder, SeqBuilder};
+pub use convert::{from_env_pairs, from_ini, to_ini, ConvertError};
+pub use document::{Document, VersionComments};
+pub use dot::to_dot;
+pub use events::{stream_value, Event, Parser};
+pub use format::{detect_indent, normalize_dump, reindent};
+pub use inspect::{canonicalize, first_diff, is_homogeneous_seq, max_depth, Nodes, PathSegment};
+pub use schema::{require_keys, Field, Schema, ValidationError};
+pub use units::Quantity;
+pub use view::YamlView;
+pub use visit::{walk_mut, Visitor};
+
 const REQUIRED_VERSION: &str = "1.0";
 
 #[derive(Debug, Clone, PartialEq)]
 pub enum YamlValue {
+    /// No value at all, as in `key:` or a bare `-`; `""` stays a `Str`.
+    Null,
     Str(String),
     Seq(Vec<YamlNode>),
     Map(BTreeMap<String, YamlNode>),
@@ -17,6 +49,49 @@ pub struct YamlNode {
     pub value: YamlValue,
     pub leading_comments: Vec<CommentLine>,
     pub inline_comment: Option<String>,
+    pub span: Option<Span>,
+    pub tag: Option<String>,
+    pub quote: QuoteHint,
+    /// Comments after the last element of a sequence or mapping value. The
+    /// document's last root entry also holds the comments ending the file.
+    pub trailing_comments: Vec<CommentLine>,
+    /// Comments between a key (or bare `-`) and its nested block that are
+    /// indented less than the block itself.
+    pub inner_leading_comments: Vec<CommentLine>,
+    /// Set by `set_value`; see `DumpOptions::annotate_changes`.
+    pub changed: bool,
+    /// Filled in by `ParseOptions::parse_durations` and `parse_sizes`.
+    pub quantity: Option<Quantity>,
+    /// The `&name` a mapping was defined with; dumped back when a `<<`
+    /// merge refers to it.
+    pub anchor: Option<String>,
+    /// On an entry a `<<` merge brought in, that merge's aliases in order,
+    /// so the dumper can write the `<<` line back instead of the entry.
+    pub merged_from: Vec<String>,
+    /// Position among its siblings for an unsorted dump when there is no
+    /// span, e.g. the insertion order of a map built from a Python dict.
+    /// Entries with a span come first, in source order.
+    pub order: Option<usize>,
+}
+
+/// How a scalar was written in the source, so an unchanged value can be
+/// dumped back the same way.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum QuoteHint {
+    #[default]
+    None,
+    Single,
+    Double,
+    Plain,
+    Literal,
+    Folded,
+}
+
+/// 1-based source position of the line a node was parsed from.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Span {
+    pub line: usize,
+    pub column: usize,
 }
 
 #[derive(Debug, Clone, PartialEq)]
@@ -25,14 +100,139 @@ pub struct CommentLine {
     pub text: String,
 }
 
+impl YamlValue {
+    pub fn as_str(&self) -> Option<&str> {
+        match self {
+            YamlValue::Str(s) => Some(s),
+            _ => None,
+        }
+    }
+
+    pub fn as_seq(&self) -> Option<&[YamlNode]> {
+        match self {
+            YamlValue::Seq(seq) => Some(seq),
+            _ => None,
+        }
+    }
+
+    pub fn as_map(&self) -> Option<&BTreeMap<String, YamlNode>> {
+        match self {
+            YamlValue::Map(map) => Some(map),
+            _ => None,
+        }
+    }
+
+    pub fn is_null(&self) -> bool {
+        matches!(self, YamlValue::Null)
+    }
+
+    pub fn is_str(&self) -> bool {
+        matches!(self, YamlValue::Str(_))
+    }
+
+    pub fn is_seq(&self) -> bool {
+        matches!(self, YamlValue::Seq(_))
+    }
+
+    pub fn is_map(&self) -> bool {
+        matches!(self, YamlValue::Map(_))
+    }
+
+    /// Number of items or entries; 0 for scalars, whatever their text.
+    pub fn len(&self) -> usize {
+        match self {
+            YamlValue::Seq(seq) => seq.len(),
+            YamlValue::Map(map) => map.len(),
+            YamlValue::Null | YamlValue::Str(_) => 0,
+        }
+    }
+
+    /// True for empty collections and for every scalar.
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// Kind of value for messages: `"null"`, `"str"`, `"seq"` or `"map"`.
+    pub fn type_name(&self) -> &'static str {
+        match self {
+            YamlValue::Null => "null",
+            YamlValue::Str(_) => "str",
+            YamlValue::Seq(_) => "seq",
+            YamlValue::Map(_) => "map",
+        }
+    }
+
+    // Scalars stay strings, so the numeric/boolean accessors parse on demand.
+    pub fn as_i64(&self) -> Option<i64> {
+        self.as_str().and_then(|s| s.trim().parse().ok())
+    }
+
+    pub fn as_f64(&self) -> Option<f64> {
+        self.as_str().and_then(|s| s.trim().parse().ok())
+    }
+
+    /// `true` or `false` only; see `as_bool_with` for `yes`/`no`/`on`/`off`.
+    pub fn as_bool(&self) -> Option<bool> {
+        self.as_bool_with(BoolWords::CoreOnly)
+    }
+
+    pub fn as_bool_with(&self, words: BoolWords) -> Option<bool> {
+        words.parse(self.as_str()?)
+    }
+}
+
+/// Same as `parse_naay`, so the text must declare `_naay_version`; use
+/// `parse_naay_with` for other options.
+impl std::str::FromStr for YamlValue {
+    type Err = ParseError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        parse_naay(s)
+    }
+}
+
+/// Writes the value as `dump_naay` would.
+impl std::fmt::Display for YamlValue {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(&dump_naay(self).map_err(|_| std::fmt::Error)?)
+    }
+}
+
 impl YamlNode {
     pub fn new(value: YamlValue) -> Self {
         Self {
             value,
             leading_comments: Vec::new(),
             inline_comment: None,
+            span: None,
+            tag: None,
+            quote: QuoteHint::None,
+            trailing_comments: Vec::new(),
+            inner_leading_comments: Vec::new(),
+            changed: false,
+            quantity: None,
+            anchor: None,
+            merged_from: Vec::new(),
+            order: None,
         }
     }
+
+    /// Replaces the value, returning the old one, and marks the node changed.
+    /// `quantity` described the old value and is cleared.
+    pub fn set_value(&mut self, value: YamlValue) -> YamlValue {
+        self.changed = true;
+        self.quantity = None;
+        std::mem::replace(&mut self.value, value)
+    }
+
+    /// Leading comments split into blank-line separated groups. Groups are
+    /// only recorded when parsing with `ParseOptions::comment_groups`.
+    pub fn comment_groups(&self) -> Vec<&[CommentLine]> {
+        self.leading_comments
+            .split(|c| c.text.is_empty())
+            .filter(|group| !group.is_empty())
+            .collect()
+    }
 }
 
 #[derive(Debug, thiserror::Error)]
@@ -45,51 +245,375 @@ pub enum ParseError {
     },
 }
 
+impl ParseError {
+    /// rustc-style report: the message, the offending source line and a caret
+    /// under the column. Falls back to the location alone when `line` is not
+    /// in `source`.
+    pub fn render(&self, source: &str) -> String {
+        let ParseError::Generic {
+            line,
+            column,
+            message,
+        } = self;
+        let mut out = format!("error: {message}\n --> {line}:{column}\n");
+        let Some(text) = line.checked_sub(1).and_then(|i| source.lines().nth(i)) else {
+            return out;
+        };
+        let gutter = " ".repeat(line.to_string().len());
+        // Keep tabs in the padding so the caret lines up with the source.
+        let pad: String = text
+            .chars()
+            .take(column.saturating_sub(1))
+            .map(|c| if c == '\t' { '\t' } else { ' ' })
+            .collect();
+        let _ = write!(out, "{gutter} |\n{line} | {text}\n{gutter} | {pad}^\n");
+        out
+    }
+}
+
+/// Non-fatal diagnostic collected while parsing.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ParseWarning {
+    pub line: usize,
+    pub column: usize,
+    pub message: String,
+}
+
+/// An anchor definition: its name, where `&name` was written, and how many
+/// aliases used it.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct AnchorInfo {
+    pub name: String,
+    pub line: usize,
+    pub column: usize,
+    pub uses: usize,
+}
+
+pub type TagHandler = Box<dyn Fn(&str) -> Result<YamlValue, String>>;
+
+pub struct ParseOptions {
+    /// Handlers keyed by the full tag text (e.g. `"!date"`); unregistered
+    /// tags are kept verbatim on the node.
+    pub tag_handlers: HashMap<String, TagHandler>,
+    /// Longest scalar, in bytes, accepted before parsing fails.
+    pub max_scalar_len: Option<usize>,
+    pub tab_policy: TabPolicy,
+    /// Warn when a local key overrides one provided by a `<<` merge.
+    pub warn_shadowed_merges: bool,
+    /// Deepest block nesting accepted; the root mapping is level 1.
+    /// Unlimited by default.
+    pub max_depth: usize,
+    /// Most nodes (scalars and collections, aliases counted once per copy)
+    /// a document may hold. Unlimited by default.
+    pub max_nodes: usize,
+    /// Largest input accepted, in bytes. Unlimited by default.
+    pub max_input_bytes: usize,
+    /// Let `key:` take a plain scalar from the line right after it, even at
+    /// the same indentation, instead of reading that line as a sibling.
+    pub next_line_values: bool,
+    /// Keep blank lines between comment lines as empty `CommentLine`
+    /// separators, so `YamlNode::comment_groups` can tell paragraphs apart.
+    pub comment_groups: bool,
+    /// Resolve `*alias` in key position to the anchored scalar instead of
+    /// reading it as a key literally named `*alias`.
+    pub alias_keys: bool,
+    /// Decode backslash escapes such as `\"` and `\n` in double-quoted
+    /// scalars. Off by default: `"C:\Users\me"` is read verbatim, as the
+    /// pure-Python parser reads it.
+    pub decode_escapes: bool,
+    /// What a double-quoted scalar does with an escape it does not know,
+    /// when `decode_escapes` is on.
+    pub unknown_escape: UnknownEscape,
+    /// Record plain scalars such as `30s` or `1h30m` as
+    /// `Quantity::Duration` on their node; the text is kept.
+    pub parse_durations: bool,
+    /// Record plain scalars such as `10KB` or `4Gi` as `Quantity::Bytes`
+    /// on their node; the text is kept.
+    pub parse_sizes: bool,
+    /// Reject a plain scalar with a `:` followed by a space or the end of
+    /// the line, as in `key: value extra: stuff`. Quoted scalars may.
+    pub strict_scalars: bool,
+    /// Markers that start a comment, e.g. `[Hash, DoubleSlash]`. Comment
+    /// text keeps the marker it was written with, so dumping writes the same
+    /// one back. An empty list reads every line as content.
+    pub comment_prefixes: Vec<CommentPrefix>,
+    /// Words read as booleans by `YamlValue::as_bool_with` and views made
+    /// with `YamlView::with_bool_words`; values stay strings either way.
+    pub bool_words: BoolWords,
+}
+
+impl Default for ParseOptions {
+    fn default() -> Self {
+        Self {
+            tag_handlers: HashMap::new(),
+            max_scalar_len: None,
+            tab_policy: TabPolicy::default(),
+            warn_shadowed_merges: false,
+            max_depth: usize::MAX,
+            max_nodes: usize::MAX,
+            max_input_bytes: usize::MAX,
+            next_line_values: false,
+            comment_groups: false,
+            alias_keys: false,
+            decode_escapes: false,
+            unknown_escape: UnknownEscape::default(),
+            parse_durations: false,
+            parse_sizes: false,
+            strict_scalars: false,
+            comment_prefixes: vec![CommentPrefix::default()],
+            bool_words: BoolWords::default(),
+        }
+    }
+}
+
+/// Handling of a backslash escape in a double-quoted scalar that is not
+/// one of YAML's.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum UnknownEscape {
+    /// Fail the parse, as strict YAML does.
+    #[default]
+    Error,
+    /// Keep the backslash and the character after it.
+    Literal,
+    /// Drop the backslash and keep the character after it.
+    Strip,
+}
+
+/// Marker that starts a comment, both for whole comment lines and inline
+/// after whitespace. Parsing accepts a list of them; dumping writes one.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum CommentPrefix {
+    #[default]
+    Hash,
+    Semicolon,
+    DoubleSlash,
+}
+
+impl CommentPrefix {
+    pub fn as_str(self) -> &'static str {
+        match self {
+            CommentPrefix::Hash => "#",
+            CommentPrefix::Semicolon => ";",
+            CommentPrefix::DoubleSlash => "//",
+        }
+    }
+}
+
+/// Which plain words read as booleans. `Extended` adds YAML 1.1's `yes`,
+/// `no`, `on` and `off`; the default keeps them strings, so `country: no`
+/// is the country code rather than `false`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum BoolWords {
+    /// Only `true` and `false`.
+    #[default]
+    CoreOnly,
+    /// `true`/`yes`/`on` and `false`/`no`/`off`.
+    Extended,
+}
+
+impl BoolWords {
+    pub fn parse(self, s: &str) -> Option<bool> {
+        match (self, s.trim()) {
+            (_, "true") | (BoolWords::Extended, "yes" | "on") => Some(true),
+            (_, "false") | (BoolWords::Extended, "no" | "off") => Some(false),
+            _ => None,
+        }
+    }
+}
+
+/// Where tab characters may appear. Tabs used as indentation are an error
+/// under every policy.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum TabPolicy {
+    /// No tabs anywhere, not even inside quoted or block scalars.
+    Reject,
+    /// Tabs are kept inside quoted scalars and block scalar content.
+    #[default]
+    AllowInScalars,
+    /// Every tab after a line's indentation becomes this many spaces before
+    /// parsing.
+    ConvertToSpaces(usize),
+}
+
 #[derive(Debug, thiserror::Error)]
 pub enum DumpError {
     #[error("io error: {0}")]
     Io(#[from] std::fmt::Error),
+    #[error("write error: {0}")]
+    Write(#[from] std::io::Error),
+}
+
+/// Failure of an operation that both parses and dumps.
+#[derive(Debug, thiserror::Error)]
+pub enum Error {
+    #[error(transparent)]
+    Parse(#[from] ParseError),
+    #[error(transparent)]
+    Dump(#[from] DumpError),
 }
 
-#[derive(Debug, Clone, Copy)]
+// Borrowed from the input for `parse_naay`; owned when read a line at a
+// time by `parse_naay_reader`.
+#[derive(Debug, Clone)]
 struct Line<'a> {
     indent: usize,
-    content: &'a str,
+    content: Cow<'a, str>,
     line_no: usize,
 }
 
-fn preprocess(input: &str) -> Result<Vec<Line<'_>>, ParseError> {
+impl<'a> Line<'a> {
+    // The rest of the line from byte `start`, read as if it were indented
+    // to `indent`.
+    fn tail(&self, start: usize, indent: usize) -> Line<'a> {
+        let content = match &self.content {
+            Cow::Borrowed(content) => Cow::Borrowed(&content[start..]),
+            Cow::Owned(content) => Cow::Owned(content[start..].to_string()),
+        };
+        Line {
+            indent,
+            content,
+            line_no: self.line_no,
+        }
+    }
+
+    fn into_owned(self) -> Line<'static> {
+        Line {
+            indent: self.indent,
+            content: Cow::Owned(self.content.into_owned()),
+            line_no: self.line_no,
+        }
+    }
+
+    fn span(&self) -> Span {
+        Span {
+            line: self.line_no,
+            column: self.indent + 1,
+        }
+    }
+}
+
+fn preprocess<'i>(input: &'i str, options: &ParseOptions) -> Result<Vec<Line<'i>>, ParseError> {
+    if input.len() > options.max_input_bytes {
+        let fits = &input.as_bytes()[..options.max_input_bytes];
+        let line_start = fits.iter().rposition(|&b| b == b'\n').map_or(0, |p| p + 1);
+        return Err(ParseError::Generic {
+            line: fits.iter().filter(|&&b| b == b'\n').count() + 1,
+            column: fits.len() - line_start + 1,
+            message: format!(
+                "input exceeds maximum size of {} bytes",
+                options.max_input_bytes
+            ),
+        });
+    }
     let mut out = Vec::new();
+    // Editors such as Notepad prefix UTF-8 files with a byte order mark.
+    let input = input.strip_prefix('\u{feff}').unwrap_or(input);
     for (idx, raw) in input.lines().enumerate() {
-        let line_no = idx + 1;
+        out.extend(preprocess_line(raw, idx + 1)?);
+    }
+    Ok(out)
+}
 
-        if raw.contains('\t') {
-            return Err(ParseError::Generic {
-                line: line_no,
-                column: 1,
-                message: "tabs are not allowed; use spaces for indentation".to_string(),
-            });
-        }
+// `None` for a blank line.
+fn preprocess_line(raw: &str, line_no: usize) -> Result<Option<Line<'_>>, ParseError> {
+    // Tabs are only rejected once a line is read as structure (see
+    // `reject_tabs`), so block scalar content may keep them verbatim.
+    // `expand_tabs` has already run for `TabPolicy::ConvertToSpaces`.
+    // `lines()` already drops the `\r` of a CRLF pair; strip a stray one
+    // explicitly so the tab and indentation checks never see it.
+    let raw = raw.strip_suffix('\r').unwrap_or(raw);
+    let trimmed = raw.trim_end();
+    let content_trimmed = trimmed.trim_start_matches(' ');
 
-        let trimmed = raw.trim_end();
-        let content_trimmed = trimmed.trim_start();
+    // Skip blank lines but retain comments for later processing
+    if content_trimmed.is_empty() {
+        return Ok(None);
+    }
 
-        // Skip blank lines but retain comments for later processing
-        if content_trimmed.is_empty() {
-            continue;
+    let indent = trimmed.chars().take_while(|c| *c == ' ').count();
+    // A mark anywhere else usually means two files were concatenated.
+    if content_trimmed.starts_with('\u{feff}') {
+        return Err(ParseError::Generic {
+            line: line_no,
+            column: indent + 1,
+            message: "byte order mark is only allowed at the start of the input".to_string(),
+        });
+    }
+    Ok(Some(Line {
+        indent,
+        content: Cow::Borrowed(content_trimmed),
+        line_no,
+    }))
+}
+
+fn reject_tabs(line: &Line<'_>, policy: TabPolicy) -> Result<(), ParseError> {
+    let allow_quoted = policy != TabPolicy::Reject;
+    let mut quote = None;
+    let mut prev = ' ';
+    let mut escaped = false;
+    for (pos, ch) in line.content.char_indices() {
+        match (quote, ch) {
+            (Some(_), '\t') if allow_quoted => {}
+            (_, '\t') => {
+                let message = if pos == 0 {
+                    "tabs are not allowed; use spaces for indentation"
+                } else if allow_quoted {
+                    "tabs are only allowed inside quoted or block scalars"
+                } else {
+                    "tabs are not allowed"
+                };
+                return Err(ParseError::Generic {
+                    line: line.line_no,
+                    column: line.indent + pos + 1,
+                    message: message.to_string(),
+                });
+            }
+            (Some('"'), '\\') if !escaped => {
+                escaped = true;
+                prev = ch;
+                continue;
+            }
+            (Some(q), _) if ch == q && !escaped => quote = None,
+            (None, '"' | '\'') if matches!(prev, ' ' | '[' | '{' | ',') => quote = Some(ch),
+            _ => {}
         }
+        escaped = false;
+        prev = ch;
+    }
+    Ok(())
+}
 
-        let indent = trimmed.chars().take_while(|c| *c == ' ').count();
-        out.push(Line {
-            indent,
-            content: content_trimmed,
-            line_no,
-        });
+// `TabPolicy::ConvertToSpaces`: rewrites the input with tabs expanded, still
+// rejecting any tab that is part of a line's indentation.
+fn expand_tabs(input: &str, width: usize) -> Result<String, ParseError> {
+    let mut out = String::with_capacity(input.len());
+    for (idx, raw) in input.split_inclusive('\n').enumerate() {
+        let body = raw.trim_start_matches([' ', '\t']);
+        if let Some(pos) = raw[..raw.len() - body.len()].find('\t') {
+            if !body.trim().is_empty() {
+                return Err(ParseError::Generic {
+                    line: idx + 1,
+                    column: pos + 1,
+                    message: "tabs are not allowed; use spaces for indentation".to_string(),
+                });
+            }
+        }
+        for ch in raw.chars() {
+            if ch == '\t' {
+                out.extend(std::iter::repeat_n(' ', width));
+            } else {
+                out.push(ch);
+            }
+        }
     }
     Ok(out)
 }
 
-fn split_inline_comment(line: &str) -> (&str, Option<&str>) {
+fn starts_with_any(s: &str, prefixes: &[CommentPrefix]) -> bool {
+    prefixes.iter().any(|p| s.starts_with(p.as_str()))
+}
+
+fn split_inline_comment<'s>(line: &'s str, prefixes: &[CommentPrefix]) -> (&'s str, Option<&'s str>) {
     let mut in_single = false;
     let mut in_double = false;
     let mut escaped = false;
@@ -106,12 +630,11 @@ fn split_inline_comment(line: &str) -> (&str, Option<&str>) {
                     in_double = true;
                 }
             }
-            '#' if !in_single && !in_double => {
+            _ if !in_single && !in_double && starts_with_any(&line[idx..], prefixes) => {
                 let prev_is_space = idx == 0
                     || line[..idx]
                         .chars()
-                        .rev()
-                        .next()
+                        .next_back()
                         .map(|c| c.is_whitespace())
                         .unwrap_or(true);
                 if prev_is_space {
@@ -133,167 +656,730 @@ fn split_inline_comment(line: &str) -> (&str, Option<&str>) {
 }
 
 pub fn parse_naay(input: &str) -> Result<YamlValue, ParseError> {
-    let lines = preprocess(input)?;
-    if lines.is_empty() {
-        // empty document -> empty map (but will fail version check)
-        return Ok(YamlValue::Map(BTreeMap::new()));
-    }
+    parse_naay_with(input, &ParseOptions::default())
+}
 
-    let machine = ParseMachine::new(&lines)?;
-    let value = run_parse_machine(machine)?;
+pub fn parse_naay_with(input: &str, options: &ParseOptions) -> Result<YamlValue, ParseError> {
+    parse_naay_with_warnings(input, options).map(|(value, _)| value)
+}
 
-    // Enforce root is a map with a valid _naay_version
-    let line_no = lines[0].line_no;
-    match &value {
-        YamlValue::Map(map) => match map.get("_naay_version").map(|n| &n.value) {
-            Some(YamlValue::Str(ver)) => {
-                if ver.trim() != REQUIRED_VERSION {
-                    return Err(ParseError::Generic {
-                        line: line_no,
-                        column: 1,
-                        message: format!(
-                            "unsupported _naay_version '{ver}', expected {REQUIRED_VERSION}"
-                        ),
-                    });
-                }
-            }
-            Some(_) => {
-                return Err(ParseError::Generic {
-                    line: line_no,
-                    column: 1,
-                    message: "_naay_version must be a string scalar".to_string(),
-                });
-            }
-            None => {
-                return Err(ParseError::Generic {
-                    line: line_no,
-                    column: 1,
-                    message: "missing required _naay_version at root (Semantic Date Versioning)"
-                        .to_string(),
-                });
-            }
-        },
-        _ => {
-            return Err(ParseError::Generic {
-                line: line_no,
-                column: 1,
-                message: "root of document must be a mapping".to_string(),
-            });
-        }
-    }
+/// Like `parse_naay`, also returning the validated `_naay_version` (trimmed).
+pub fn parse_naay_versioned(input: &str) -> Result<(String, YamlValue), ParseError> {
+    let value = parse_naay(input)?;
+    let version = match value.as_map().and_then(|map| map.get("_naay_version")) {
+        Some(YamlNode {
+            value: YamlValue::Str(ver),
+            ..
+        }) => ver.trim().to_string(),
+        _ => unreachable!("parse_naay validated _naay_version"),
+    };
+    Ok((version, value))
+}
 
-    Ok(value)
+pub fn parse_naay_with_warnings(
+    input: &str,
+    options: &ParseOptions,
+) -> Result<(YamlValue, Vec<ParseWarning>), ParseError> {
+    parse_document(input, options).map(|(value, report)| (value, report.warnings))
 }
-struct ParseMachine<'a> {
-    env: ParseEnv<'a>,
-    stack: Vec<Frame<'a>>,
+
+/// Every anchor defined in `input`, in source order, with how many aliases
+/// refer to it. A redefined name is listed once per definition, and each
+/// alias counts toward the definition it resolved to.
+pub fn anchor_report(input: &str) -> Result<Vec<AnchorInfo>, ParseError> {
+    parse_document(input, &ParseOptions::default()).map(|(_, report)| report.anchor_defs)
 }
 
-impl<'a> ParseMachine<'a> {
-    fn new(lines: &'a [Line<'a>]) -> Result<Self, ParseError> {
-        let first = lines.first().ok_or_else(|| ParseError::Generic {
-            line: 1,
-            column: 1,
-            message: "document must contain at least one line".to_string(),
-        })?;
-        let kind = detect_block_kind(first);
-        let env = ParseEnv {
-            lines,
-            index: 0,
-            anchors: HashMap::new(),
-        };
-        let mut stack = Vec::new();
-        stack.push(Frame::new(kind, first.indent));
-        Ok(Self { env, stack })
-    }
+/// Anchors no `*alias` or `<<` merge refers to, in source order, with
+/// where each was defined.
+pub fn unused_anchors(input: &str) -> Result<Vec<(String, Span)>, ParseError> {
+    Ok(anchor_report(input)?
+        .into_iter()
+        .filter(|anchor| anchor.uses == 0)
+        .map(|anchor| {
+            let at = Span {
+                line: anchor.line,
+                column: anchor.column,
+            };
+            (anchor.name, at)
+        })
+        .collect())
+}
 
-    fn run_step(&mut self) -> Result<Option<YamlValue>, ParseError> {
+/// Like `parse_naay`, also returning each anchor name with the value it
+/// stands for. A redefined anchor maps to its last definition.
+pub fn parse_naay_with_anchors(
+    input: &str,
+) -> Result<(YamlValue, HashMap<String, YamlValue>), ParseError> {
+    parse_document(input, &ParseOptions::default()).map(|(value, report)| (value, report.anchors))
+}
+
+/// Parses a document read line by line from `reader`. Lines are handed to
+/// the parser as it asks for them and released once parsed, so only the
+/// lines of the block being read stay in memory, not the whole input. A
+/// failed read, including invalid UTF-8, is reported at the line being read.
+pub fn parse_naay_reader<R: io::BufRead>(reader: R) -> Result<YamlValue, ParseError> {
+    parse_naay_reader_with(reader, &ParseOptions::default())
+}
+
+pub fn parse_naay_reader_with<R: io::BufRead>(
+    reader: R,
+    options: &ParseOptions,
+) -> Result<YamlValue, ParseError> {
+    let lines = ReaderLines {
+        reader,
+        options,
+        buf: String::new(),
+        line_no: 0,
+        total: 0,
+    };
+    let machine = ParseMachine::streaming(Box::new(lines), options)?;
+    parse_document_machine(machine, options).map(|(value, _)| value)
+}
+
+// The non-blank lines of a reader, prepared as `preprocess` prepares those
+// of a string.
+struct ReaderLines<'o, R> {
+    reader: R,
+    options: &'o ParseOptions,
+    buf: String,
+    line_no: usize,
+    total: usize,
+}
+
+impl<R: io::BufRead> ReaderLines<'_, R> {
+    fn read_line(&mut self) -> Result<Option<Line<'static>>, ParseError> {
         loop {
-            let frame = self.stack.last_mut().ok_or_else(|| ParseError::Generic {
-                line: 1,
+            self.line_no += 1;
+            let line_no = self.line_no;
+            self.buf.clear();
+            let read = self.reader.read_line(&mut self.buf).map_err(|err| ParseError::Generic {
+                line: line_no,
                 column: 1,
-                message: "unexpected empty parser stack".to_string(),
+                message: format!("failed to read input: {err}"),
             })?;
-
-            match frame.step(&mut self.env)? {
-                FrameStep::Continue => continue,
-                FrameStep::NeedChild { indent } => {
-                    let line = self.env.peek_line().ok_or_else(|| ParseError::Generic {
-                        line: 1,
-                        column: 1,
-                        message: "expected nested block".to_string(),
-                    })?;
-                    let kind = detect_block_kind(line);
-                    self.stack.push(Frame::new(kind, indent));
-                }
-                FrameStep::Return(value) => {
-                    self.stack.pop();
-                    if let Some(parent) = self.stack.last_mut() {
-                        parent.handle_child(value, &mut self.env)?;
-                    } else {
-                        return Ok(Some(value));
+            if read == 0 {
+                return Ok(None);
+            }
+            self.total += read;
+            let max = self.options.max_input_bytes;
+            if self.total > max {
+                return Err(ParseError::Generic {
+                    line: line_no,
+                    column: max - (self.total - read) + 1,
+                    message: format!("input exceeds maximum size of {max} bytes"),
+                });
+            }
+            let mut raw = self.buf.strip_suffix('\n').unwrap_or(&self.buf);
+            if line_no == 1 {
+                raw = raw.strip_prefix('\u{feff}').unwrap_or(raw);
+            }
+            let expanded;
+            if let TabPolicy::ConvertToSpaces(width) = self.options.tab_policy {
+                expanded = expand_tabs(raw, width).map_err(|ParseError::Generic { column, message, .. }| {
+                    ParseError::Generic {
+                        line: line_no,
+                        column,
+                        message,
                     }
-                }
+                })?;
+                raw = &expanded;
+            }
+            if let Some(line) = preprocess_line(raw, line_no)? {
+                return Ok(Some(line.into_owned()));
             }
         }
     }
+}
 
-    fn step(mut self) -> Next<Self, Result<YamlValue, ParseError>> {
-        match self.run_step() {
-            Ok(Some(value)) => Next::Finish(Ok(value)),
-            Ok(None) => Next::Recurse(self),
-            Err(err) => Next::Finish(Err(err)),
-        }
+impl<R: io::BufRead> Iterator for ReaderLines<'_, R> {
+    type Item = Result<Line<'static>, ParseError>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        self.read_line().transpose()
     }
 }
 
-fn run_parse_machine<'a>(machine: ParseMachine<'a>) -> Result<YamlValue, ParseError> {
-    trampoline::run(ParseMachine::step, machine)
+/// Parses a fragment of a document: the root may be a mapping, a sequence
+/// or a single value (`"text"`, `[a, b]`, a `|` block), and `_naay_version`
+/// is not required. `parse_naay` is the strict form for whole documents.
+pub fn parse_fragment(input: &str) -> Result<YamlValue, ParseError> {
+    let options = ParseOptions::default();
+    let mut machine = ParseMachine::new(preprocess(input, &options)?, &options)?;
+    let mut value = match machine.root_value()? {
+        Some(value) => value,
+        None => run_parse_machine(machine)?.0,
+    };
+    units::annotate_quantities(&mut value, &options);
+    Ok(value)
 }
 
-struct ParseEnv<'a> {
-    lines: &'a [Line<'a>],
-    index: usize,
-    anchors: HashMap<String, YamlValue>,
+fn parse_document(
+    input: &str,
+    options: &ParseOptions,
+) -> Result<(YamlValue, ParseReport), ParseError> {
+    let expanded;
+    let input = match options.tab_policy {
+        TabPolicy::ConvertToSpaces(width) => {
+            expanded = expand_tabs(input, width)?;
+            expanded.as_str()
+        }
+        _ => input,
+    };
+    parse_lines(preprocess(input, options)?, options)
 }
 
-impl<'a> ParseEnv<'a> {
-    fn peek_line(&self) -> Option<&Line<'a>> {
-        self.lines.get(self.index)
+fn parse_lines<'a>(
+    lines: Vec<Line<'a>>,
+    options: &'a ParseOptions,
+) -> Result<(YamlValue, ParseReport), ParseError> {
+    if lines.is_empty() {
+        return Err(empty_document());
     }
-}
 
-enum Frame<'a> {
-    Seq(SeqFrame<'a>),
-    Map(MapFrame<'a>),
+    parse_document_machine(ParseMachine::new(lines, options)?, options)
 }
 
-impl<'a> Frame<'a> {
-    fn new(kind: BlockKind, base_indent: usize) -> Self {
-        match kind {
-            BlockKind::Seq => Frame::Seq(SeqFrame::new(base_indent)),
-            BlockKind::Map => Frame::Map(MapFrame::new(base_indent)),
-        }
-    }
+fn parse_document_machine<'a>(
+    machine: ParseMachine<'a>,
+    options: &'a ParseOptions,
+) -> Result<(YamlValue, ParseReport), ParseError> {
+    // Nothing has been released yet, so this is the document's first line.
+    let line_no = machine.env.lines[0].line_no;
+    let (mut value, report) = run_parse_machine(machine)?;
 
-    fn step(&mut self, env: &mut ParseEnv<'a>) -> Result<FrameStep, ParseError> {
-        match self {
-            Frame::Seq(seq) => seq.step(env),
-            Frame::Map(map) => map.step(env),
+    // Enforce root is a map with a valid _naay_version
+    match &value {
+        YamlValue::Map(map) => {
+            check_version(map.get("_naay_version").map(|n| &n.value), line_no)?
         }
+        _ => return Err(root_not_a_map(line_no)),
     }
+    units::annotate_quantities(&mut value, options);
 
-    fn handle_child(&mut self, value: YamlValue, env: &mut ParseEnv<'a>) -> Result<(), ParseError> {
-        match self {
-            Frame::Seq(seq) => seq.handle_child(value, env),
-            Frame::Map(map) => map.handle_child(value, env),
-        }
-    }
+    Ok((value, report))
 }
 
-enum FrameStep {
-    Continue,
-    NeedChild { indent: usize },
+fn check_version(version: Option<&YamlValue>, line_no: usize) -> Result<(), ParseError> {
+    match version {
+        Some(YamlValue::Str(ver)) => {
+            if ver.trim() != REQUIRED_VERSION {
+                return Err(ParseError::Generic {
+                    line: line_no,
+                    column: 1,
+                    message: format!(
+                        "unsupported _naay_version '{ver}', expected {REQUIRED_VERSION}"
+                    ),
+                });
+            }
+            Ok(())
+        }
+        Some(_) => Err(ParseError::Generic {
+            line: line_no,
+            column: 1,
+            message: "_naay_version must be a string scalar".to_string(),
+        }),
+        None => Err(ParseError::Generic {
+            line: line_no,
+            column: 1,
+            message: "missing required _naay_version at root (Semantic Date Versioning)"
+                .to_string(),
+        }),
+    }
+}
+
+fn empty_document() -> ParseError {
+    ParseError::Generic {
+        line: 1,
+        column: 1,
+        message: "empty document; expected a mapping with _naay_version".to_string(),
+    }
+}
+
+fn comments_only(line_no: usize) -> ParseError {
+    ParseError::Generic {
+        line: line_no,
+        column: 1,
+        message: "document contains no data, only comments".to_string(),
+    }
+}
+
+fn root_not_a_map(line_no: usize) -> ParseError {
+    ParseError::Generic {
+        line: line_no,
+        column: 1,
+        message: "root of document must be a mapping".to_string(),
+    }
+}
+struct ParseMachine<'a> {
+    env: ParseEnv<'a>,
+    stack: Vec<Frame<'a>>,
+}
+
+impl<'a> ParseMachine<'a> {
+    fn new(lines: Vec<Line<'a>>, options: &'a ParseOptions) -> Result<Self, ParseError> {
+        Self::start(lines, None, options)
+    }
+
+    // Reads lines from `source` as the frames ask for them.
+    fn streaming(source: LineSource<'a>, options: &'a ParseOptions) -> Result<Self, ParseError> {
+        Self::start(Vec::new(), Some(source), options)
+    }
+
+    fn start(
+        lines: Vec<Line<'a>>,
+        source: Option<LineSource<'a>>,
+        options: &'a ParseOptions,
+    ) -> Result<Self, ParseError> {
+        let mut env = ParseEnv {
+            lines,
+            index: 0,
+            source,
+            read_error: None,
+            anchors: HashMap::new(),
+            nodes: 0,
+            anchor_defs: Vec::new(),
+            options,
+            warnings: Vec::new(),
+        };
+        let first = env.next_content_index();
+        if let Some(err) = env.read_error.take() {
+            return Err(err);
+        }
+        let last = env.lines.last().ok_or_else(empty_document)?;
+        let first = &env.lines[first.ok_or_else(|| comments_only(last.line_no))?];
+        let stack = vec![Frame::new(detect_block_kind(first), first.indent)];
+        Ok(Self { env, stack })
+    }
+
+    // A fragment whose first content line is a lone value rather than a
+    // sequence item or mapping entry; `None` leaves it to the frames.
+    fn root_value(&mut self) -> Result<Option<YamlValue>, ParseError> {
+        let env = &mut self.env;
+        let Some(at) = env.next_content_index() else {
+            return Ok(None);
+        };
+        let line = env.lines[at].clone();
+        let content = env.split_comment(&line.content).0.trim_end();
+        if looks_like_seq(content) || (!content.starts_with(['[', '{']) && is_map_entry(content)) {
+            return Ok(None);
+        }
+        env.index = at + 1;
+        let column = line.indent + 1;
+        let InlineValueOutcome::Ready(node) =
+            parse_inline_value(env, content, line.line_no, column, column)?
+        else {
+            return Err(ParseError::Generic {
+                line: line.line_no,
+                column,
+                message: "a fragment's anchor must name a value on the same line".to_string(),
+            });
+        };
+        if let Some(next) = env.next_content_line() {
+            return Err(ParseError::Generic {
+                line: next.line_no,
+                column: next.indent + 1,
+                message: "unexpected content after a single-value fragment".to_string(),
+            });
+        }
+        Ok(Some(node.value))
+    }
+
+    fn run_step(&mut self) -> Result<Option<YamlValue>, ParseError> {
+        loop {
+            if let Some(value) = self.step_once()? {
+                return Ok(Some(value));
+            }
+        }
+    }
+
+    // Advances one frame step; yields the root value once the last frame
+    // returns.
+    fn step_once(&mut self) -> Result<Option<YamlValue>, ParseError> {
+        let step = self.step_frames();
+        if let Some(err) = self.env.read_error.take() {
+            return Err(err);
+        }
+        self.env.release_consumed();
+        step
+    }
+
+    fn step_frames(&mut self) -> Result<Option<YamlValue>, ParseError> {
+        let frame = self.stack.last_mut().ok_or_else(|| ParseError::Generic {
+            line: 1,
+            column: 1,
+            message: "unexpected empty parser stack".to_string(),
+        })?;
+
+        match frame.step(&mut self.env)? {
+            FrameStep::Continue => {}
+            FrameStep::NeedChild { indent } => {
+                let max_depth = self.env.options.max_depth;
+                let line = self.env.next_content_line().ok_or_else(|| ParseError::Generic {
+                    line: 1,
+                    column: 1,
+                    message: "expected nested block".to_string(),
+                })?;
+                if self.stack.len() >= max_depth {
+                    return Err(ParseError::Generic {
+                        line: line.line_no,
+                        column: line.indent + 1,
+                        message: "maximum nesting depth exceeded".to_string(),
+                    });
+                }
+                let kind = detect_block_kind(line);
+                self.stack.push(Frame::new(kind, indent));
+            }
+            FrameStep::CompactSeq(line) => {
+                if self.stack.len() >= self.env.options.max_depth {
+                    return Err(ParseError::Generic {
+                        line: line.line_no,
+                        column: line.indent + 1,
+                        message: "maximum nesting depth exceeded".to_string(),
+                    });
+                }
+                let mut seq = SeqFrame::new(line.indent);
+                seq.compact = Some(line);
+                self.stack.push(Frame::Seq(seq));
+            }
+            FrameStep::Return(value) => {
+                // Comments still pending when a frame ends sit below its last
+                // element and travel up as the block's trailing comments.
+                let trailing = self.stack.pop().map(Frame::into_pending).unwrap_or_default();
+                match self.stack.last_mut() {
+                    Some(parent) => parent.handle_child(value, trailing, &mut self.env)?,
+                    None => {
+                        let mut value = value;
+                        keep_document_trailing(&mut value, trailing);
+                        // The root only ends early on a line that dedents
+                        // past it or cannot continue it.
+                        if let Some(line) = self.env.next_content_line() {
+                            return Err(if looks_like_seq(&line.content) {
+                                ParseError::Generic {
+                                    line: line.line_no,
+                                    column: line.indent + 1,
+                                    message: "sequence item does not belong to any key".to_string(),
+                                }
+                            } else {
+                                inconsistent_indentation(line)
+                            });
+                        }
+                        return Ok(Some(value));
+                    }
+                }
+            }
+        }
+        Ok(None)
+    }
+
+    fn step(mut self) -> Next<Self, Result<(YamlValue, ParseReport), ParseError>> {
+        match self.run_step() {
+            Ok(Some(value)) => Next::Finish(Ok((
+                value,
+                ParseReport {
+                    warnings: self.env.warnings,
+                    anchors: self.env.anchors,
+                    anchor_defs: self.env.anchor_defs,
+                },
+            ))),
+            Ok(None) => Next::Recurse(self),
+            Err(err) => Next::Finish(Err(err)),
+        }
+    }
+}
+
+fn run_parse_machine(machine: ParseMachine<'_>) -> Result<(YamlValue, ParseReport), ParseError> {
+    trampoline::run(ParseMachine::step, machine)
+}
+
+// Comments after the last root entry end the document; they stay on that
+// entry so dumping writes them last again.
+fn keep_document_trailing(root: &mut YamlValue, trailing: Vec<CommentLine>) {
+    let last = match root {
+        YamlValue::Map(map) => map
+            .values_mut()
+            .max_by_key(|node| node.span.map(|s| (s.line, s.column))),
+        YamlValue::Seq(seq) => seq.last_mut(),
+        YamlValue::Null | YamlValue::Str(_) => None,
+    };
+    if let Some(node) = last {
+        node.trailing_comments.extend(trailing);
+    }
+}
+
+// Lines still to be read, for input that is not held in memory at once.
+type LineSource<'a> = Box<dyn Iterator<Item = Result<Line<'static>, ParseError>> + 'a>;
+
+struct ParseEnv<'a> {
+    // Every line for a string input; with a `source`, only a window of
+    // them from just before `index` through the lookahead read so far.
+    lines: Vec<Line<'a>>,
+    index: usize,
+    source: Option<LineSource<'a>>,
+    // A failed read ends the source; the failure is reported in place of
+    // whatever the truncated input led the frames to.
+    read_error: Option<ParseError>,
+    anchors: HashMap<String, YamlValue>,
+    options: &'a ParseOptions,
+    warnings: Vec<ParseWarning>,
+    nodes: usize,
+    // Every definition in source order; a redefined name gets a new entry.
+    anchor_defs: Vec<AnchorInfo>,
+}
+
+// What a finished parse leaves behind besides the value.
+struct ParseReport {
+    warnings: Vec<ParseWarning>,
+    anchors: HashMap<String, YamlValue>,
+    anchor_defs: Vec<AnchorInfo>,
+}
+
+impl<'a> ParseEnv<'a> {
+    // Strips a scalar's quotes, decoding escapes in double-quoted ones.
+    // `column` is where the opening quote sits.
+    fn unquote<'s>(&self, s: &'s str, line: usize, column: usize) -> Result<Cow<'s, str>, ParseError> {
+        if !(self.options.decode_escapes && s.starts_with('"') && is_quoted(s)) {
+            return Ok(strip_quotes(s));
+        }
+        let body = &s[1..s.len() - 1];
+        if !body.contains('\\') {
+            return Ok(Cow::Borrowed(body));
+        }
+        decode_escapes(body, self.options.unknown_escape)
+            .map(Cow::Owned)
+            .map_err(|(offset, escape)| ParseError::Generic {
+                line,
+                column: column + 1 + offset,
+                message: format!("unknown escape sequence '{escape}'"),
+            })
+    }
+
+    fn check_plain_scalar(&self, s: &str, line: usize, column: usize) -> Result<(), ParseError> {
+        if !self.options.strict_scalars || is_quoted(s) {
+            return Ok(());
+        }
+        let colon = s
+            .match_indices(':')
+            .map(|(pos, _)| pos)
+            .find(|&pos| s[pos + 1..].chars().next().is_none_or(char::is_whitespace));
+        match colon {
+            Some(pos) => Err(ParseError::Generic {
+                line,
+                column: column + pos,
+                message: format!("unexpected ': ' in plain scalar '{s}'; quote it if intended"),
+            }),
+            None => Ok(()),
+        }
+    }
+
+    fn check_scalar_len(&self, s: &str, line: usize, column: usize) -> Result<(), ParseError> {
+        match self.options.max_scalar_len {
+            Some(max) if s.len() > max => Err(ParseError::Generic {
+                line,
+                column,
+                message: format!("scalar exceeds maximum length of {max} bytes"),
+            }),
+            _ => Ok(()),
+        }
+    }
+
+    // Every node kept in the tree counts toward `max_nodes`, including the
+    // copies an alias expands to.
+    fn count_nodes(&mut self, count: usize, at: Span) -> Result<(), ParseError> {
+        self.nodes = self.nodes.saturating_add(count);
+        if self.nodes > self.options.max_nodes {
+            return Err(ParseError::Generic {
+                line: at.line,
+                column: at.column,
+                message: format!(
+                    "document exceeds maximum of {} nodes",
+                    self.options.max_nodes
+                ),
+            });
+        }
+        Ok(())
+    }
+
+    fn resolve_alias(
+        &mut self,
+        name: &str,
+        line: usize,
+        column: usize,
+    ) -> Result<YamlValue, ParseError> {
+        let value = self
+            .anchors
+            .get(name)
+            .cloned()
+            .ok_or_else(|| ParseError::Generic {
+                line,
+                column,
+                message: format!("unknown anchor: {name}"),
+            })?;
+        self.count_nodes(value.iter_nodes().count(), Span { line, column })?;
+        if let Some(info) = self.anchor_defs.iter_mut().rev().find(|a| a.name == name) {
+            info.uses += 1;
+        }
+        Ok(value)
+    }
+
+    fn define_anchor(&mut self, name: String, value: YamlValue, at: Span) {
+        self.anchor_defs.push(AnchorInfo {
+            name: name.clone(),
+            line: at.line,
+            column: at.column,
+            uses: 0,
+        });
+        self.anchors.insert(name, value);
+    }
+
+    // With `comment_groups`, a blank line between two queued comments is
+    // kept as an empty separator entry.
+    fn push_comment(&self, pending: &mut Vec<CommentLine>, line: &Line<'_>) {
+        if self.options.comment_groups && !pending.is_empty() {
+            let prev = self.lines[self.index - 1].line_no;
+            if line.line_no > prev + 1 {
+                pending.push(CommentLine {
+                    indent: 0,
+                    text: String::new(),
+                });
+            }
+        }
+        pending.push(CommentLine {
+            indent: line.indent,
+            text: line.content.to_string(),
+        });
+    }
+
+    // Comments right before a nested block that sit left of it belong to
+    // the entry opening the block, not to the block's first child.
+    fn take_inner_comments(&mut self, child_indent: usize) -> Vec<CommentLine> {
+        let mut inner = Vec::new();
+        while let Some(line) = self.peek_line().cloned() {
+            if !self.is_comment(&line) || line.indent >= child_indent {
+                break;
+            }
+            self.push_comment(&mut inner, &line);
+            self.index += 1;
+        }
+        inner
+    }
+
+    // Reads from the source until line `at` is buffered; `false` past the end.
+    fn fill(&mut self, at: usize) -> bool {
+        while self.lines.len() <= at {
+            let Some(source) = self.source.as_mut() else {
+                return false;
+            };
+            match source.next() {
+                Some(Ok(line)) => self.lines.push(line),
+                Some(Err(err)) => {
+                    self.read_error = Some(err);
+                    self.source = None;
+                }
+                None => self.source = None,
+            }
+        }
+        true
+    }
+
+    fn line_at(&mut self, at: usize) -> Option<&Line<'a>> {
+        self.fill(at);
+        self.lines.get(at)
+    }
+
+    fn peek_line(&mut self) -> Option<&Line<'a>> {
+        self.line_at(self.index)
+    }
+
+    // Comment lines don't decide where a block starts or ends; the next line
+    // with content does.
+    fn next_content_index(&mut self) -> Option<usize> {
+        let mut at = self.index;
+        while self.fill(at) {
+            if !self.is_comment(&self.lines[at]) {
+                return Some(at);
+            }
+            at += 1;
+        }
+        None
+    }
+
+    fn next_content_line(&mut self) -> Option<&Line<'a>> {
+        let at = self.next_content_index()?;
+        self.lines.get(at)
+    }
+
+    // Drops lines already parsed when reading from a source, keeping the
+    // last one for `push_comment`. Only called between frame steps, when
+    // nothing holds an index into `lines`.
+    fn release_consumed(&mut self) {
+        if self.source.is_some() && self.index > 64 {
+            self.lines.drain(..self.index - 1);
+            self.index = 1;
+        }
+    }
+
+    fn is_comment(&self, line: &Line<'_>) -> bool {
+        starts_with_any(&line.content, &self.options.comment_prefixes)
+    }
+
+    fn split_comment<'s>(&self, content: &'s str) -> (&'s str, Option<&'s str>) {
+        split_inline_comment(content, &self.options.comment_prefixes)
+    }
+
+    // A comment belongs to the frame that will own the next content line.
+    fn comment_within(&mut self, base_indent: usize) -> bool {
+        self.next_content_line()
+            .is_some_and(|line| line.indent >= base_indent)
+    }
+}
+
+enum Frame<'a> {
+    Seq(SeqFrame<'a>),
+    Map(MapFrame<'a>),
+}
+
+impl<'a> Frame<'a> {
+    fn new(kind: BlockKind, base_indent: usize) -> Self {
+        match kind {
+            BlockKind::Seq => Frame::Seq(SeqFrame::new(base_indent)),
+            BlockKind::Map => Frame::Map(MapFrame::new(base_indent)),
+        }
+    }
+
+    fn step(&mut self, env: &mut ParseEnv<'a>) -> Result<FrameStep<'a>, ParseError> {
+        match self {
+            Frame::Seq(seq) => seq.step(env),
+            Frame::Map(map) => map.step(env),
+        }
+    }
+
+    fn handle_child(
+        &mut self,
+        value: YamlValue,
+        trailing: Vec<CommentLine>,
+        env: &mut ParseEnv<'a>,
+    ) -> Result<(), ParseError> {
+        match self {
+            Frame::Seq(seq) => seq.handle_child(value, trailing, env),
+            Frame::Map(map) => map.handle_child(value, trailing, env),
+        }
+    }
+
+    fn into_pending(self) -> Vec<CommentLine> {
+        match self {
+            Frame::Seq(seq) => seq.pending_comments,
+            Frame::Map(map) => map.pending_comments,
+        }
+    }
+}
+
+enum FrameStep<'a> {
+    Continue,
+    NeedChild { indent: usize },
+    /// `- - a`: a nested sequence whose first item is the rest of the
+    /// current line, given here as a line starting at the inner dash.
+    CompactSeq(Line<'a>),
     Return(YamlValue),
 }
 
@@ -303,14 +1389,43 @@ enum BlockKind {
     Map,
 }
 
+// A line that can only be a scalar: no key, sequence dash, comment or
+// value indicator.
+fn is_plain_value_line(content: &str, comment_prefixes: &[CommentPrefix]) -> bool {
+    let (text, _) = split_inline_comment(content, comment_prefixes);
+    !text.is_empty()
+        && !text.contains(':')
+        && !looks_like_seq(text)
+        && !text.starts_with(['#', '!', '&', '*', '|', '>', '[', '{'])
+}
+
 fn detect_block_kind(line: &Line<'_>) -> BlockKind {
-    if looks_like_seq(line.content) {
+    if looks_like_seq(&line.content) {
         BlockKind::Seq
     } else {
         BlockKind::Map
     }
 }
 
+// A line deeper than its block's siblings that no entry above opened.
+fn inconsistent_indentation(line: &Line<'_>) -> ParseError {
+    ParseError::Generic {
+        line: line.line_no,
+        column: line.indent + 1,
+        message: "inconsistent indentation".to_string(),
+    }
+}
+
+// `key: value`, `"quoted key": value` or `? key`; a colon inside a quoted
+// scalar alone does not count.
+fn is_map_entry(content: &str) -> bool {
+    let rest = match content.chars().next() {
+        Some(q @ ('"' | '\'')) => content[1..].find(q).map_or("", |end| &content[end + 2..]),
+        _ => content,
+    };
+    content.starts_with('?') || rest.contains(':')
+}
+
 fn looks_like_seq(content: &str) -> bool {
     if !content.starts_with('-') {
         return false;
@@ -330,7 +1445,8 @@ struct SeqFrame<'a> {
     items: Vec<YamlNode>,
     pending_comments: Vec<CommentLine>,
     waiting: Option<SeqWaiting>,
-    _marker: PhantomData<&'a ()>,
+    // The rest of a `- - a` line, read before the input's next line.
+    compact: Option<Line<'a>>,
 }
 
 impl<'a> SeqFrame<'a> {
@@ -340,18 +1456,21 @@ impl<'a> SeqFrame<'a> {
             items: Vec::new(),
             pending_comments: Vec::new(),
             waiting: None,
-            _marker: PhantomData,
+            compact: None,
         }
     }
 
-    fn step(&mut self, env: &mut ParseEnv<'a>) -> Result<FrameStep, ParseError> {
+    fn step(&mut self, env: &mut ParseEnv<'a>) -> Result<FrameStep<'a>, ParseError> {
         if let Some(wait) = &self.waiting {
             return Ok(FrameStep::NeedChild {
                 indent: wait.child_indent(),
             });
         }
 
-        let line = match env.peek_line().copied() {
+        // A compact line is not in `env.lines`, so there is nothing to
+        // advance past once it is read.
+        let from_input = self.compact.is_none();
+        let line = match self.compact.take().or_else(|| env.peek_line().cloned()) {
             Some(line) => line,
             None => {
                 return Ok(FrameStep::Return(YamlValue::Seq(mem::take(
@@ -359,156 +1478,259 @@ impl<'a> SeqFrame<'a> {
                 ))));
             }
         };
+        reject_tabs(&line, env.options.tab_policy)?;
 
-        if line.indent < self.base_indent || !looks_like_seq(line.content) {
-            return Ok(FrameStep::Return(YamlValue::Seq(mem::take(
-                &mut self.items,
-            ))));
-        }
-
-        if line.content.starts_with('#') {
-            self.pending_comments.push(CommentLine {
-                indent: line.indent,
-                text: line.content.to_string(),
-            });
+        if env.is_comment(&line)
+            && (line.indent >= self.base_indent || env.comment_within(self.base_indent))
+        {
+            env.push_comment(&mut self.pending_comments, &line);
             env.index += 1;
             return Ok(FrameStep::Continue);
         }
 
-        if line.indent > self.base_indent {
+        if line.indent < self.base_indent || !looks_like_seq(&line.content) {
             return Ok(FrameStep::Return(YamlValue::Seq(mem::take(
                 &mut self.items,
             ))));
         }
 
-        let (content_no_comment, inline_comment) = split_inline_comment(line.content);
+        if line.indent > self.base_indent {
+            return Err(inconsistent_indentation(&line));
+        }
+
+        let (content_no_comment, inline_comment) = env.split_comment(&line.content);
         if !looks_like_seq(content_no_comment) {
             return Ok(FrameStep::Return(YamlValue::Seq(mem::take(
                 &mut self.items,
             ))));
         }
         let after_dash = content_no_comment[1..].trim_start();
-        env.index += 1;
-        let inline_comment = inline_comment.map(|c| c.to_string());
+        let value_column = line.indent + content_no_comment.len() - after_dash.len() + 1;
+        let span = line.span();
+
+        if from_input {
+            env.index += 1;
+        }
+
+        // `- - a`: the rest of the line opens a nested sequence, which reads
+        // it as its first line, indented to its dash.
+        if looks_like_seq(after_dash) {
+            let start = content_no_comment.len() - after_dash.len();
+            let child_indent = line.indent + start;
+            self.waiting = Some(SeqWaiting::Child {
+                inline_comment: None,
+                inner_comments: Vec::new(),
+                anchor: None,
+                child_indent,
+                span,
+            });
+            return Ok(FrameStep::CompactSeq(line.tail(start, child_indent)));
+        }
+
+        let mut inline_comment = inline_comment.map(|c| c.to_string());
 
         if after_dash.is_empty() {
-            if env.index >= env.lines.len() || env.lines[env.index].indent <= self.base_indent {
-                self.push_node(YamlValue::Str(String::new()), inline_comment);
+            if env.next_content_line().is_none_or(|next| next.indent <= self.base_indent) {
+                self.push_node(env, YamlValue::Null, inline_comment, span)?;
                 return Ok(FrameStep::Continue);
             }
-            let child_indent = env.lines[env.index].indent;
+            let child_indent = env.next_content_line().map_or(0, |next| next.indent);
             self.waiting = Some(SeqWaiting::Child {
                 inline_comment,
+                inner_comments: env.take_inner_comments(child_indent),
                 anchor: None,
                 child_indent,
+                span,
             });
             return Ok(FrameStep::NeedChild { indent: child_indent });
         }
 
-        if after_dash == "|" {
-            let s = parse_block_scalar(env.lines, &mut env.index, self.base_indent + 1)?;
-            self.push_node(YamlValue::Str(s), inline_comment);
+        if is_block_indicator(after_dash) {
+            let s = parse_block_body(env, self.base_indent + 1, after_dash)?;
+            self.push_node(env, YamlValue::Str(s), inline_comment, span)?.quote = quote_hint(after_dash);
             return Ok(FrameStep::Continue);
         }
 
         if after_dash == "[]" {
-            self.push_node(YamlValue::Seq(Vec::new()), inline_comment);
+            self.push_node(env, YamlValue::Seq(Vec::new()), inline_comment, span)?;
             return Ok(FrameStep::Continue);
         }
 
         if after_dash == "{}" {
-            self.push_node(YamlValue::Map(BTreeMap::new()), inline_comment);
+            self.push_node(env, YamlValue::Map(BTreeMap::new()), inline_comment, span)?;
             return Ok(FrameStep::Continue);
         }
 
-        if let Some(colon_pos) = after_dash.find(':') {
-            return self.handle_inline_map(
+        if let Some(node) =
+            parse_flow_collection(env, after_dash, &mut inline_comment, line.line_no, value_column)
+        {
+            let node = node?;
+            self.push_node(env, node.value, inline_comment, span)?.trailing_comments =
+                node.trailing_comments;
+            return Ok(FrameStep::Continue);
+        }
+
+        if after_dash.starts_with('!') {
+            let node = parse_tagged_scalar(
                 env,
-                line,
                 after_dash,
-                colon_pos,
-                inline_comment,
-            );
+                self.base_indent + 1,
+                line.line_no,
+                value_column,
+            )?;
+            let pushed = self.push_node(env, node.value, inline_comment, span)?;
+            pushed.tag = node.tag;
+            pushed.quote = node.quote;
+            return Ok(FrameStep::Continue);
         }
 
-        if after_dash.starts_with('&') {
-            if env.index >= env.lines.len() || env.lines[env.index].indent <= self.base_indent {
-                return Err(ParseError::Generic {
-                    line: line.line_no,
-                    column: 1,
+        // `"a": "b"` also starts and ends with a quote but is a map entry.
+        if is_quoted(after_dash) && closing_quote(after_dash) == Some(after_dash.len() - 1) {
+            let scalar = env.unquote(after_dash, line.line_no, value_column)?;
+            env.check_scalar_len(&scalar, line.line_no, value_column)?;
+            self.push_node(env, YamlValue::Str(scalar.to_string()), inline_comment, span)?.quote =
+                quote_hint(after_dash);
+            return Ok(FrameStep::Continue);
+        }
+
+        // `- &name value` anchors a value written on the same line.
+        if let Some((name, rest)) = after_dash
+            .strip_prefix('&')
+            .and_then(|a| a.split_once(char::is_whitespace))
+        {
+            let rest = rest.trim_start();
+            let column = value_column + after_dash.len() - rest.len();
+            let node = match parse_inline_value(env, rest, line.line_no, self.base_indent + 2, column)? {
+                InlineValueOutcome::Ready(node) => node,
+                InlineValueOutcome::NeedsBlock(_) => {
+                    return Err(ParseError::Generic {
+                        line: line.line_no,
+                        column,
+                        message: "a sequence item takes a single anchor".to_string(),
+                    });
+                }
+            };
+            let at = Span {
+                line: line.line_no,
+                column: value_column,
+            };
+            env.define_anchor(name.to_string(), node.value.clone(), at);
+            let pushed = self.push_node(env, node.value, inline_comment, span)?;
+            pushed.tag = node.tag;
+            pushed.quote = node.quote;
+            pushed.anchor = node.anchor;
+            return Ok(FrameStep::Continue);
+        }
+
+        if let Some(colon_pos) = key_colon(after_dash) {
+            return self.handle_inline_map(
+                env,
+                &line,
+                after_dash,
+                value_column,
+                colon_pos,
+                inline_comment,
+            );
+        }
+
+        if let Some(anchor) = after_dash.strip_prefix('&') {
+            if env.next_content_line().is_none_or(|next| next.indent <= self.base_indent) {
+                return Err(ParseError::Generic {
+                    line: line.line_no,
+                    column: value_column,
                     message: "anchor without nested value".to_string(),
                 });
             }
-            let child_indent = env.lines[env.index].indent;
+            let child_indent = env.next_content_line().map_or(0, |next| next.indent);
             self.waiting = Some(SeqWaiting::Child {
                 inline_comment,
-                anchor: Some(after_dash[1..].trim().to_string()),
+                inner_comments: env.take_inner_comments(child_indent),
+                anchor: Some(anchor.trim().to_string()),
                 child_indent,
+                span,
             });
             return Ok(FrameStep::NeedChild { indent: child_indent });
         }
 
-        if after_dash.starts_with('*') {
-            let name = after_dash[1..].trim();
-            let value = env
-                .anchors
-                .get(name)
-                .cloned()
-                .ok_or_else(|| ParseError::Generic {
-                    line: line.line_no,
-                    column: 1,
-                    message: format!("unknown anchor: {name}"),
-                })?;
-            self.push_node(value, inline_comment);
+        if let Some(alias) = after_dash.strip_prefix('*') {
+            let value = env.resolve_alias(alias.trim(), line.line_no, value_column)?;
+            self.push_node(env, value, inline_comment, span)?;
+            return Ok(FrameStep::Continue);
+        }
+
+        if after_dash == "~" {
+            self.push_node(env, YamlValue::Null, inline_comment, span)?;
             return Ok(FrameStep::Continue);
         }
 
-        let scalar = strip_quotes(after_dash);
-        self.push_node(YamlValue::Str(scalar.to_string()), inline_comment);
+        env.check_plain_scalar(after_dash, line.line_no, value_column)?;
+        let scalar = env.unquote(after_dash, line.line_no, value_column)?;
+        env.check_scalar_len(&scalar, line.line_no, value_column)?;
+        self.push_node(env, YamlValue::Str(scalar.to_string()), inline_comment, span)?.quote =
+            quote_hint(after_dash);
         Ok(FrameStep::Continue)
     }
 
     fn handle_inline_map(
         &mut self,
         env: &mut ParseEnv<'a>,
-        line: Line<'a>,
+        line: &Line<'a>,
         after_dash: &str,
+        key_column: usize,
         colon_pos: usize,
-        inline_comment: Option<String>,
-    ) -> Result<FrameStep, ParseError> {
+        mut inline_comment: Option<String>,
+    ) -> Result<FrameStep<'a>, ParseError> {
         let (kpart, rest) = after_dash.split_at(colon_pos);
-        let key = parse_key(kpart.trim(), line.line_no)?;
+        // Only a bare `<<` merges; `"<<"` is an ordinary key.
+        let key = match kpart.trim() {
+            "<<" => None,
+            raw => Some(parse_key(env, raw, line.line_no, key_column)?),
+        };
         let vpart = rest[1..].trim_start();
+        let value_column = key_column + after_dash.len() - vpart.len();
         let mut map = BTreeMap::new();
         let expected_indent = self.base_indent + 2;
+        let span = line.span();
+        let key_span = Span {
+            line: line.line_no,
+            column: line.indent + line.content.len() - line.content[1..].trim_start().len() + 1,
+        };
         let outcome = parse_inline_value(
             env,
             vpart,
             line.line_no,
             expected_indent,
-            colon_pos + 1,
+            value_column,
         )?;
         match outcome {
-            InlineValueOutcome::Ready(node) => {
+            InlineValueOutcome::Ready(mut node) => {
+                node.span = Some(key_span);
+                // The comment ends the entry's line, so it belongs to the
+                // entry rather than the whole item.
+                node.inline_comment = inline_comment.take();
                 insert_inline_entry(
                     &mut map,
                     key,
-                    node,
+                    *node,
                     line.line_no,
-                    colon_pos + 1,
+                    value_column,
                 )?;
-                if env.index < env.lines.len() && env.lines[env.index].indent > self.base_indent {
-                    let child_indent = env.lines[env.index].indent;
+                if let Some(child_indent) = env
+                    .next_content_line()
+                    .map(|next| next.indent)
+                    .filter(|&indent| indent > self.base_indent)
+                {
                     self.waiting = Some(SeqWaiting::InlineMapContinuation {
                         map,
                         inline_comment,
                         child_indent,
                         line_no: line.line_no,
-                        column: colon_pos + 1,
+                        column: value_column,
+                        span,
                     });
                     return Ok(FrameStep::NeedChild { indent: child_indent });
                 }
-                self.push_node(YamlValue::Map(map), inline_comment);
+                self.push_node(env, YamlValue::Map(map), inline_comment, span)?;
                 Ok(FrameStep::Continue)
             }
             InlineValueOutcome::NeedsBlock(wait) => {
@@ -519,7 +1741,9 @@ impl<'a> SeqFrame<'a> {
                     anchor_name: wait.anchor_name,
                     child_indent: wait.child_indent,
                     line_no: line.line_no,
-                    column: colon_pos + 1,
+                    column: value_column,
+                    span,
+                    key_span,
                 });
                 Ok(FrameStep::NeedChild {
                     indent: wait.child_indent,
@@ -531,6 +1755,7 @@ impl<'a> SeqFrame<'a> {
     fn handle_child(
         &mut self,
         value: YamlValue,
+        trailing: Vec<CommentLine>,
         env: &mut ParseEnv<'a>,
     ) -> Result<(), ParseError> {
         let waiting = self.waiting.take().ok_or_else(|| ParseError::Generic {
@@ -541,26 +1766,33 @@ impl<'a> SeqFrame<'a> {
         match waiting {
             SeqWaiting::Child {
                 inline_comment,
+                inner_comments,
                 anchor,
+                span,
                 ..
             } => {
-                if let Some(anchor) = anchor {
-                    env.anchors.insert(anchor, value.clone());
+                if let Some(anchor) = &anchor {
+                    env.define_anchor(anchor.clone(), value.clone(), span);
                 }
-                self.push_node(value, inline_comment);
+                let node = self.push_node(env, value, inline_comment, span)?;
+                node.trailing_comments = trailing;
+                node.inner_leading_comments = inner_comments;
+                node.anchor = anchor;
             }
             SeqWaiting::InlineMapContinuation {
                 mut map,
                 inline_comment,
                 line_no,
                 column,
+                span,
                 ..
             } => {
                 let extra = expect_map(value, line_no, column, "inline mapping continuation")?;
                 for (k, v) in extra {
                     map.insert(k, v);
                 }
-                self.push_node(YamlValue::Map(map), inline_comment);
+                self.push_node(env, YamlValue::Map(map), inline_comment, span)?.trailing_comments =
+                    trailing;
             }
             SeqWaiting::InlineAnchorValue {
                 mut map,
@@ -569,41 +1801,61 @@ impl<'a> SeqFrame<'a> {
                 anchor_name,
                 line_no,
                 column,
+                span,
+                key_span,
                 ..
             } => {
-                env.anchors.insert(anchor_name, value.clone());
-                let node = YamlNode::new(value);
+                env.define_anchor(anchor_name.clone(), value.clone(), key_span);
+                let mut node = YamlNode::new(value);
+                node.span = Some(key_span);
+                node.anchor = Some(anchor_name);
+                node.trailing_comments = trailing;
                 insert_inline_entry(&mut map, key, node, line_no, column)?;
-                if env.index < env.lines.len() && env.lines[env.index].indent > self.base_indent {
-                    let child_indent = env.lines[env.index].indent;
+                if let Some(child_indent) = env
+                    .next_content_line()
+                    .map(|next| next.indent)
+                    .filter(|&indent| indent > self.base_indent)
+                {
                     self.waiting = Some(SeqWaiting::InlineMapContinuation {
                         map,
                         inline_comment,
                         child_indent,
                         line_no,
                         column,
+                        span,
                     });
                     return Ok(());
                 }
-                self.push_node(YamlValue::Map(map), inline_comment);
+                self.push_node(env, YamlValue::Map(map), inline_comment, span)?;
             }
         }
         Ok(())
     }
 
-    fn push_node(&mut self, value: YamlValue, inline_comment: Option<String>) {
+    fn push_node(
+        &mut self,
+        env: &mut ParseEnv<'_>,
+        value: YamlValue,
+        inline_comment: Option<String>,
+        span: Span,
+    ) -> Result<&mut YamlNode, ParseError> {
+        env.count_nodes(1, span)?;
         let mut node = YamlNode::new(value);
         node.leading_comments = mem::take(&mut self.pending_comments);
         node.inline_comment = inline_comment;
+        node.span = Some(span);
         self.items.push(node);
+        Ok(self.items.last_mut().expect("node was just pushed"))
     }
 }
 
 enum SeqWaiting {
     Child {
         inline_comment: Option<String>,
+        inner_comments: Vec<CommentLine>,
         anchor: Option<String>,
         child_indent: usize,
+        span: Span,
     },
     InlineMapContinuation {
         map: BTreeMap<String, YamlNode>,
@@ -611,15 +1863,18 @@ enum SeqWaiting {
         child_indent: usize,
         line_no: usize,
         column: usize,
+        span: Span,
     },
     InlineAnchorValue {
         map: BTreeMap<String, YamlNode>,
-        key: String,
+        key: Option<String>,
         inline_comment: Option<String>,
         anchor_name: String,
         child_indent: usize,
         line_no: usize,
         column: usize,
+        span: Span,
+        key_span: Span,
     },
 }
 
@@ -638,6 +1893,8 @@ struct MapFrame<'a> {
     entries: BTreeMap<String, YamlNode>,
     pending_comments: Vec<CommentLine>,
     waiting: Option<MapWaiting>,
+    // Keys that came from a `<<` merge, for shadowing warnings.
+    merged: HashSet<String>,
     _marker: PhantomData<&'a ()>,
 }
 
@@ -648,18 +1905,19 @@ impl<'a> MapFrame<'a> {
             entries: BTreeMap::new(),
             pending_comments: Vec::new(),
             waiting: None,
+            merged: HashSet::new(),
             _marker: PhantomData,
         }
     }
 
-    fn step(&mut self, env: &mut ParseEnv<'a>) -> Result<FrameStep, ParseError> {
+    fn step(&mut self, env: &mut ParseEnv<'a>) -> Result<FrameStep<'a>, ParseError> {
         if let Some(wait) = &self.waiting {
             return Ok(FrameStep::NeedChild {
                 indent: wait.child_indent,
             });
         }
 
-        let line = match env.peek_line().copied() {
+        let line = match env.peek_line().cloned() {
             Some(line) => line,
             None => {
                 return Ok(FrameStep::Return(YamlValue::Map(mem::take(
@@ -667,134 +1925,237 @@ impl<'a> MapFrame<'a> {
                 ))));
             }
         };
+        reject_tabs(&line, env.options.tab_policy)?;
 
-        if line.indent < self.base_indent || looks_like_seq(line.content) {
-            return Ok(FrameStep::Return(YamlValue::Map(mem::take(
-                &mut self.entries,
-            ))));
-        }
-
-        if line.content.starts_with('#') {
-            self.pending_comments.push(CommentLine {
-                indent: line.indent,
-                text: line.content.to_string(),
-            });
+        if env.is_comment(&line)
+            && (line.indent >= self.base_indent || env.comment_within(self.base_indent))
+        {
+            env.push_comment(&mut self.pending_comments, &line);
             env.index += 1;
             return Ok(FrameStep::Continue);
         }
 
-        if line.indent > self.base_indent {
+        if line.indent < self.base_indent || looks_like_seq(&line.content) {
             return Ok(FrameStep::Return(YamlValue::Map(mem::take(
                 &mut self.entries,
             ))));
         }
 
-        let (content_no_comment, inline_comment) = split_inline_comment(line.content);
-        let colon_pos = content_no_comment
-            .find(':')
+        if line.indent > self.base_indent {
+            return Err(inconsistent_indentation(&line));
+        }
+
+        // Explicit `? key` entries read the key block first, then continue
+        // with the sibling `:` line as if it were an ordinary entry.
+        let span = line.span();
+        let (line, explicit_key) = if line.content == "?" || line.content.starts_with("? ") {
+            let key = parse_explicit_key(env, &line)?;
+            match env.peek_line().cloned() {
+                Some(next) if next.indent == self.base_indent && next.content.starts_with(':') => {
+                    (next, Some(key))
+                }
+                _ => {
+                    return Err(ParseError::Generic {
+                        line: line.line_no,
+                        column: line.indent + 1,
+                        message: "expected ':' line after explicit key".to_string(),
+                    });
+                }
+            }
+        } else {
+            (line, None)
+        };
+
+        let (content_no_comment, inline_comment) = env.split_comment(&line.content);
+        let colon_pos = key_colon(content_no_comment)
             .ok_or_else(|| ParseError::Generic {
                 line: line.line_no,
-                column: 1,
+                column: line.indent + content_no_comment.trim_end().len(),
                 message: "expected ':' in mapping entry".to_string(),
             })?;
+        let colon_column = line.indent + colon_pos + 1;
         let (kpart, rest) = content_no_comment.split_at(colon_pos);
-        let key = parse_key(kpart.trim(), line.line_no)?;
+        // Only a bare `<<` merges; `"<<"` is an ordinary key.
+        let merge = explicit_key.is_none() && kpart.trim() == "<<";
+        let key = match explicit_key {
+            Some(key) => key,
+            None => parse_key(env, kpart.trim(), line.line_no, line.indent + 1)?,
+        };
         let vpart = rest[1..].trim_start();
+        let value_column = line.indent + content_no_comment.len() - vpart.len() + 1;
         env.index += 1;
-        let inline_comment = inline_comment.map(|c| c.to_string());
-
-        if key == "<<" && vpart.starts_with('*') {
-            let name = vpart[1..].trim();
-            let aliased = env
-                .anchors
-                .get(name)
-                .cloned()
-                .ok_or_else(|| ParseError::Generic {
-                    line: line.line_no,
-                    column: colon_pos + 1,
-                    message: format!("unknown anchor: {name}"),
-                })?;
-            let map = expect_map(aliased, line.line_no, colon_pos + 1, "merge source")?;
-            for (k, v) in map {
-                self.entries.entry(k).or_insert(v);
+        let mut inline_comment = inline_comment.map(|c| c.to_string());
+
+        if merge && (vpart.starts_with('*') || vpart.starts_with('[')) {
+            let warn = env.options.warn_shadowed_merges;
+            let sources = resolve_merge_sources(env, vpart, line.line_no, colon_column)?;
+            let names: Vec<String> = sources.iter().map(|source| source.name.clone()).collect();
+            for MergeSource { map, .. } in sources {
+                for (k, mut v) in map {
+                    match self.entries.entry(k) {
+                        Entry::Occupied(slot) => {
+                            if warn && !self.merged.contains(slot.key()) {
+                                env.warnings.push(ParseWarning {
+                                    line: line.line_no,
+                                    column: colon_column,
+                                    message: format!(
+                                        "key '{}' shadows a value from this merge",
+                                        slot.key()
+                                    ),
+                                });
+                            }
+                        }
+                        Entry::Vacant(slot) => {
+                            if warn {
+                                self.merged.insert(slot.key().clone());
+                            }
+                            // Merged entries sit where the `<<` line was.
+                            v.span = Some(span);
+                            v.merged_from = names.clone();
+                            slot.insert(v);
+                        }
+                    }
+                }
             }
             self.pending_comments.clear();
             return Ok(FrameStep::Continue);
         }
 
+        if self.merged.remove(&key) {
+            env.warnings.push(ParseWarning {
+                line: span.line,
+                column: span.column,
+                message: format!("key '{key}' shadows a value from an earlier merge"),
+            });
+        }
+
         if vpart.is_empty() {
-            if env.index >= env.lines.len() || env.lines[env.index].indent <= self.base_indent {
-                let value = YamlValue::Str(String::new());
-                self.push_entry(key, value, inline_comment);
+            let next_value = env.peek_line().cloned().filter(|next| {
+                env.options.next_line_values
+                    && next.indent >= self.base_indent
+                    && is_plain_value_line(&next.content, &env.options.comment_prefixes)
+            });
+            if let Some(next) = next_value {
+                let (text, comment) = env.split_comment(&next.content);
+                env.index += 1;
+                let scalar = env.unquote(text, next.line_no, next.indent + 1)?;
+                env.check_scalar_len(&scalar, next.line_no, next.indent + 1)?;
+                let inline_comment = inline_comment.or(comment.map(str::to_string));
+                let value = YamlValue::Str(scalar.to_string());
+                self.push_entry(env, key, value, inline_comment, span)?.quote = quote_hint(text);
                 return Ok(FrameStep::Continue);
             }
-            let child_indent = env.lines[env.index].indent;
+            if env.next_content_line().is_none_or(|next| next.indent <= self.base_indent) {
+                self.push_entry(env, key, YamlValue::Null, inline_comment, span)?;
+                return Ok(FrameStep::Continue);
+            }
+            let child_indent = env.next_content_line().map_or(0, |next| next.indent);
             self.waiting = Some(MapWaiting {
                 key,
                 inline_comment,
+                inner_comments: env.take_inner_comments(child_indent),
                 anchor: None,
                 child_indent,
+                span,
             });
             return Ok(FrameStep::NeedChild { indent: child_indent });
         }
 
-        if vpart == "|" {
-            let s = parse_block_scalar(env.lines, &mut env.index, self.base_indent + 1)?;
-            self.push_entry(key, YamlValue::Str(s), inline_comment);
+        if vpart.starts_with('!') {
+            let node = parse_tagged_scalar(
+                env,
+                vpart,
+                self.base_indent + 1,
+                line.line_no,
+                value_column,
+            )?;
+            let pushed = self.push_entry(env, key, node.value, inline_comment, span)?;
+            pushed.tag = node.tag;
+            pushed.quote = node.quote;
+            return Ok(FrameStep::Continue);
+        }
+
+        if is_block_indicator(vpart) {
+            let s = parse_block_body(env, self.base_indent + 1, vpart)?;
+            self.push_entry(env, key, YamlValue::Str(s), inline_comment, span)?.quote = quote_hint(vpart);
             return Ok(FrameStep::Continue);
         }
 
         if vpart == "[]" {
-            self.push_entry(key, YamlValue::Seq(Vec::new()), inline_comment);
+            self.push_entry(env, key, YamlValue::Seq(Vec::new()), inline_comment, span)?;
             return Ok(FrameStep::Continue);
         }
 
         if vpart == "{}" {
-            self.push_entry(key, YamlValue::Map(BTreeMap::new()), inline_comment);
+            self.push_entry(env, key, YamlValue::Map(BTreeMap::new()), inline_comment, span)?;
+            return Ok(FrameStep::Continue);
+        }
+
+        if let Some(node) =
+            parse_flow_collection(env, vpart, &mut inline_comment, line.line_no, value_column)
+        {
+            let node = node?;
+            self.push_entry(env, key, node.value, inline_comment, span)?.trailing_comments =
+                node.trailing_comments;
+            return Ok(FrameStep::Continue);
+        }
+
+        if vpart.starts_with('&') && vpart.contains(char::is_whitespace) {
+            let InlineValueOutcome::Ready(node) =
+                parse_inline_value(env, vpart, line.line_no, self.base_indent + 1, value_column)?
+            else {
+                unreachable!("an anchor followed by a value is read inline");
+            };
+            let entry = self.push_entry(env, key, node.value, inline_comment, span)?;
+            entry.quote = node.quote;
+            entry.tag = node.tag;
+            entry.anchor = node.anchor;
             return Ok(FrameStep::Continue);
         }
 
-        if vpart.starts_with('&') {
-            if env.index >= env.lines.len() || env.lines[env.index].indent <= self.base_indent {
+        if let Some(anchor) = vpart.strip_prefix('&') {
+            if env.next_content_line().is_none_or(|next| next.indent <= self.base_indent) {
                 return Err(ParseError::Generic {
                     line: line.line_no,
-                    column: colon_pos + 1,
+                    column: colon_column,
                     message: "anchor without nested value".to_string(),
                 });
             }
-            let child_indent = env.lines[env.index].indent;
+            let child_indent = env.next_content_line().map_or(0, |next| next.indent);
             self.waiting = Some(MapWaiting {
                 key,
                 inline_comment,
-                anchor: Some(vpart[1..].trim().to_string()),
+                inner_comments: env.take_inner_comments(child_indent),
+                anchor: Some(anchor.trim().to_string()),
                 child_indent,
+                span,
             });
             return Ok(FrameStep::NeedChild { indent: child_indent });
         }
 
-        if vpart.starts_with('*') {
-            let name = vpart[1..].trim();
-            let value = env
-                .anchors
-                .get(name)
-                .cloned()
-                .ok_or_else(|| ParseError::Generic {
-                    line: line.line_no,
-                    column: colon_pos + 1,
-                    message: format!("unknown anchor: {name}"),
-                })?;
-            self.push_entry(key, value, inline_comment);
+        if let Some(alias) = vpart.strip_prefix('*') {
+            let value = env.resolve_alias(alias.trim(), line.line_no, value_column)?;
+            self.push_entry(env, key, value, inline_comment, span)?;
+            return Ok(FrameStep::Continue);
+        }
+
+        if vpart == "~" {
+            self.push_entry(env, key, YamlValue::Null, inline_comment, span)?;
             return Ok(FrameStep::Continue);
         }
 
-        let scalar = strip_quotes(vpart);
-        self.push_entry(key, YamlValue::Str(scalar.to_string()), inline_comment);
+        env.check_plain_scalar(vpart, line.line_no, value_column)?;
+        let scalar = env.unquote(vpart, line.line_no, value_column)?;
+        env.check_scalar_len(&scalar, line.line_no, value_column)?;
+        self.push_entry(env, key, YamlValue::Str(scalar.to_string()), inline_comment, span)?.quote =
+            quote_hint(vpart);
         Ok(FrameStep::Continue)
     }
 
     fn handle_child(
         &mut self,
         value: YamlValue,
+        trailing: Vec<CommentLine>,
         env: &mut ParseEnv<'a>,
     ) -> Result<(), ParseError> {
         let waiting = self.waiting.take().ok_or_else(|| ParseError::Generic {
@@ -802,30 +2163,50 @@ impl<'a> MapFrame<'a> {
             column: 1,
             message: "mapping not awaiting child".to_string(),
         })?;
-        if let Some(anchor) = waiting.anchor {
-            env.anchors.insert(anchor, value.clone());
+        if let Some(anchor) = &waiting.anchor {
+            env.define_anchor(anchor.clone(), value.clone(), waiting.span);
         }
-        self.push_entry(waiting.key, value, waiting.inline_comment);
+        let node = self.push_entry(env, waiting.key, value, waiting.inline_comment, waiting.span)?;
+        node.trailing_comments = trailing;
+        node.inner_leading_comments = waiting.inner_comments;
+        node.anchor = waiting.anchor;
         Ok(())
     }
 
-    fn push_entry(&mut self, key: String, value: YamlValue, inline_comment: Option<String>) {
+    fn push_entry(
+        &mut self,
+        env: &mut ParseEnv<'_>,
+        key: String,
+        value: YamlValue,
+        inline_comment: Option<String>,
+        span: Span,
+    ) -> Result<&mut YamlNode, ParseError> {
+        env.count_nodes(1, span)?;
         let mut node = YamlNode::new(value);
         node.leading_comments = mem::take(&mut self.pending_comments);
         node.inline_comment = inline_comment;
-        self.entries.insert(key, node);
+        node.span = Some(span);
+        Ok(match self.entries.entry(key) {
+            Entry::Occupied(mut slot) => {
+                slot.insert(node);
+                slot.into_mut()
+            }
+            Entry::Vacant(slot) => slot.insert(node),
+        })
     }
 }
 
 struct MapWaiting {
     key: String,
     inline_comment: Option<String>,
+    inner_comments: Vec<CommentLine>,
     anchor: Option<String>,
     child_indent: usize,
+    span: Span,
 }
 
 enum InlineValueOutcome {
-    Ready(YamlNode),
+    Ready(Box<YamlNode>),
     NeedsBlock(InlineValueWait),
 }
 
@@ -841,35 +2222,78 @@ fn parse_inline_value(
     expected_indent: usize,
     column: usize,
 ) -> Result<InlineValueOutcome, ParseError> {
+    if vpart.starts_with('!') {
+        let node = parse_tagged_scalar(env, vpart, expected_indent, line_no, column)?;
+        return Ok(InlineValueOutcome::Ready(Box::new(node)));
+    }
+
     if (vpart.starts_with('"') && vpart.ends_with('"') && vpart.len() >= 2)
         || (vpart.starts_with('\'') && vpart.ends_with('\'') && vpart.len() >= 2)
     {
-        return Ok(InlineValueOutcome::Ready(YamlNode::new(YamlValue::Str(
-            strip_quotes(vpart).to_string(),
-        ))));
+        let scalar = env.unquote(vpart, line_no, column)?;
+        env.check_scalar_len(&scalar, line_no, column)?;
+        let mut node = YamlNode::new(YamlValue::Str(scalar.to_string()));
+        node.quote = quote_hint(vpart);
+        return Ok(InlineValueOutcome::Ready(Box::new(node)));
     }
 
-    if vpart == "|" {
-        let s = parse_block_scalar(env.lines, &mut env.index, expected_indent)?;
-        return Ok(InlineValueOutcome::Ready(YamlNode::new(YamlValue::Str(s))));
+    if is_block_indicator(vpart) {
+        let s = parse_block_body(env, expected_indent, vpart)?;
+        let mut node = YamlNode::new(YamlValue::Str(s));
+        node.quote = quote_hint(vpart);
+        return Ok(InlineValueOutcome::Ready(Box::new(node)));
     }
 
     if vpart == "[]" {
-        return Ok(InlineValueOutcome::Ready(YamlNode::new(YamlValue::Seq(Vec::new()))));
+        let node = YamlNode::new(YamlValue::Seq(Vec::new()));
+        return Ok(InlineValueOutcome::Ready(Box::new(node)));
     }
     if vpart == "{}" {
-        return Ok(InlineValueOutcome::Ready(YamlNode::new(YamlValue::Map(
-            BTreeMap::new(),
-        ))));
+        let node = YamlNode::new(YamlValue::Map(BTreeMap::new()));
+        return Ok(InlineValueOutcome::Ready(Box::new(node)));
+    }
+    // The line's comment was already taken by the caller.
+    if let Some(node) = parse_flow_collection(env, vpart, &mut None, line_no, column) {
+        return node.map(|node| InlineValueOutcome::Ready(Box::new(node)));
+    }
+
+    // `&name value`: the anchor names the value on the same line.
+    if let Some((name, rest)) = vpart
+        .strip_prefix('&')
+        .and_then(|a| a.split_once(char::is_whitespace))
+    {
+        let rest = rest.trim_start();
+        let rest_column = column + vpart.len() - rest.len();
+        let node = match rest.starts_with('&') {
+            false => parse_inline_value(env, rest, line_no, expected_indent, rest_column)?,
+            true => InlineValueOutcome::NeedsBlock(InlineValueWait {
+                anchor_name: String::new(),
+                child_indent: 0,
+            }),
+        };
+        let InlineValueOutcome::Ready(mut node) = node else {
+            return Err(ParseError::Generic {
+                line: line_no,
+                column: rest_column,
+                message: "a value takes a single anchor".to_string(),
+            });
+        };
+        let at = Span {
+            line: line_no,
+            column,
+        };
+        env.define_anchor(name.to_string(), node.value.clone(), at);
+        node.anchor = Some(name.to_string());
+        return Ok(InlineValueOutcome::Ready(node));
     }
 
-    if vpart.starts_with('&') {
-        let next = env.lines.get(env.index).ok_or_else(|| ParseError::Generic {
+    if let Some(anchor) = vpart.strip_prefix('&') {
+        let next = env.next_content_line().ok_or_else(|| ParseError::Generic {
             line: line_no,
             column,
             message: "anchor without nested value".to_string(),
         })?;
-        if next.indent <= expected_indent - 1 {
+        if next.indent < expected_indent {
             return Err(ParseError::Generic {
                 line: line_no,
                 column,
@@ -877,38 +2301,218 @@ fn parse_inline_value(
             });
         }
         return Ok(InlineValueOutcome::NeedsBlock(InlineValueWait {
-            anchor_name: vpart[1..].trim().to_string(),
+            anchor_name: anchor.trim().to_string(),
             child_indent: next.indent,
         }));
     }
 
-    if vpart.starts_with('*') {
-        let name = vpart[1..].trim();
-        let aliased = env
-            .anchors
-            .get(name)
-            .cloned()
-            .ok_or_else(|| ParseError::Generic {
+    if let Some(alias) = vpart.strip_prefix('*') {
+        let aliased = env.resolve_alias(alias.trim(), line_no, column)?;
+        return Ok(InlineValueOutcome::Ready(Box::new(YamlNode::new(aliased))));
+    }
+
+    if vpart == "~" {
+        return Ok(InlineValueOutcome::Ready(Box::new(YamlNode::new(YamlValue::Null))));
+    }
+    env.check_plain_scalar(vpart, line_no, column)?;
+    env.check_scalar_len(vpart, line_no, column)?;
+    let mut node = YamlNode::new(YamlValue::Str(vpart.to_string()));
+    node.quote = QuoteHint::Plain;
+    Ok(InlineValueOutcome::Ready(Box::new(node)))
+}
+
+// `[a, "b"]` or `{a: 1, b: "two"}`, with scalar values only; `None` for
+// anything else. The collection may run over several lines when its first
+// line is the bracket alone or ends with `,`; see `read_flow_entries`.
+fn parse_flow_collection(
+    env: &mut ParseEnv<'_>,
+    vpart: &str,
+    inline_comment: &mut Option<String>,
+    line_no: usize,
+    column: usize,
+) -> Option<Result<YamlNode, ParseError>> {
+    let close = if vpart.starts_with('[') { ']' } else { '}' };
+    // `[draft] title` is a plain scalar that happens to start with a bracket.
+    if !vpart.starts_with(['[', '{']) || (vpart.contains(close) && !vpart.ends_with(close)) {
+        return None;
+    }
+    // So is `[draft` with nothing to say the collection goes on.
+    if !vpart.ends_with(close) && vpart.len() > 1 && !vpart.ends_with(',') {
+        return None;
+    }
+    Some(read_flow_entries(env, vpart, inline_comment, line_no, column).and_then(
+        |(entries, trailing)| {
+            let value = match vpart.starts_with('[') {
+                true => YamlValue::Seq(parse_flow_seq(env, entries)?),
+                false => YamlValue::Map(parse_flow_map(env, entries)?),
+            };
+            let mut node = YamlNode::new(value);
+            node.trailing_comments = trailing;
+            Ok(node)
+        },
+    ))
+}
+
+// One element of a flow collection and the comments around it.
+struct FlowEntry {
+    text: String,
+    line_no: usize,
+    column: usize,
+    leading_comments: Vec<CommentLine>,
+    inline_comment: Option<String>,
+}
+
+// Splits a flow collection into its elements, reading on from the next
+// lines until the bracket closes. A comment after an element on the same
+// line becomes that element's inline comment, and comment lines lead the
+// next element; comments after the last element are returned separately.
+// The first line's comment is only taken when the collection continues.
+fn read_flow_entries(
+    env: &mut ParseEnv<'_>,
+    vpart: &str,
+    inline_comment: &mut Option<String>,
+    line_no: usize,
+    column: usize,
+) -> Result<(Vec<FlowEntry>, Vec<CommentLine>), ParseError> {
+    let close = if vpart.starts_with('[') { ']' } else { '}' };
+    let mut entries: Vec<FlowEntry> = Vec::new();
+    let mut pending = Vec::new();
+    let mut text = vpart[1..].to_string();
+    let mut comment = match vpart.ends_with(close) {
+        true => None,
+        false => inline_comment.take(),
+    };
+    let (mut at_line, mut at_column, mut indent) = (line_no, column + 1, 0);
+    loop {
+        let body = text.strip_suffix(close);
+        let mut offset = 0;
+        let mut on_line = false;
+        for piece in split_flow_entries(body.unwrap_or(&text)) {
+            let piece_column = at_column + offset + piece.len() - piece.trim_start().len();
+            offset += piece.len() + 1;
+            if piece.trim().is_empty() {
+                continue;
+            }
+            entries.push(FlowEntry {
+                text: piece.trim().to_string(),
+                line_no: at_line,
+                column: piece_column,
+                leading_comments: mem::take(&mut pending),
+                inline_comment: None,
+            });
+            on_line = true;
+        }
+        if let Some(comment) = comment.take() {
+            match entries.last_mut() {
+                Some(entry) if on_line => entry.inline_comment = Some(comment),
+                _ => pending.push(CommentLine {
+                    indent,
+                    text: comment,
+                }),
+            }
+        }
+        if body.is_some() {
+            return Ok((entries, pending));
+        }
+        let next = loop {
+            let next = env.peek_line().cloned().ok_or_else(|| ParseError::Generic {
                 line: line_no,
                 column,
-                message: format!("unknown anchor: {name}"),
+                message: format!("unterminated flow collection; expected '{close}'"),
             })?;
-        return Ok(InlineValueOutcome::Ready(YamlNode::new(aliased)));
+            env.index += 1;
+            if !env.is_comment(&next) {
+                break next;
+            }
+            env.push_comment(&mut pending, &next);
+        };
+        let (next_text, next_comment) = env.split_comment(&next.content);
+        text = next_text.to_string();
+        comment = next_comment.map(str::to_string);
+        (at_line, at_column, indent) = (next.line_no, next.indent + 1, next.indent);
+    }
+}
+
+fn flow_scalar(env: &mut ParseEnv<'_>, entry: &FlowEntry, raw: &str) -> Result<YamlNode, ParseError> {
+    if raw.starts_with(['{', '[']) {
+        return Err(ParseError::Generic {
+            line: entry.line_no,
+            column: entry.column,
+            message: "nested flow collections are not supported".to_string(),
+        });
+    }
+    let mut node = match raw {
+        "~" => YamlNode::new(YamlValue::Null),
+        _ => {
+            let scalar = env.unquote(raw, entry.line_no, entry.column)?;
+            env.check_scalar_len(&scalar, entry.line_no, entry.column)?;
+            let mut node = YamlNode::new(YamlValue::Str(scalar.to_string()));
+            node.quote = quote_hint(raw);
+            node
+        }
+    };
+    node.leading_comments = entry.leading_comments.clone();
+    node.inline_comment = entry.inline_comment.clone();
+    Ok(node)
+}
+
+fn parse_flow_seq(
+    env: &mut ParseEnv<'_>,
+    entries: Vec<FlowEntry>,
+) -> Result<Vec<YamlNode>, ParseError> {
+    entries
+        .iter()
+        .map(|entry| flow_scalar(env, entry, &entry.text))
+        .collect()
+}
+
+fn parse_flow_map(
+    env: &mut ParseEnv<'_>,
+    entries: Vec<FlowEntry>,
+) -> Result<BTreeMap<String, YamlNode>, ParseError> {
+    let mut map = BTreeMap::new();
+    for entry in &entries {
+        let colon_pos = key_colon(&entry.text).ok_or_else(|| ParseError::Generic {
+            line: entry.line_no,
+            column: entry.column,
+            message: format!("expected ':' in flow mapping entry '{}'", entry.text),
+        })?;
+        let (kpart, vpart) = (&entry.text[..colon_pos], &entry.text[colon_pos + 1..]);
+        let node = flow_scalar(env, entry, vpart.trim())?;
+        map.insert(parse_key(env, kpart.trim(), entry.line_no, entry.column)?, node);
     }
+    Ok(map)
+}
 
-    Ok(InlineValueOutcome::Ready(YamlNode::new(YamlValue::Str(
-        vpart.to_string(),
-    ))))
+// Splits on commas outside quotes.
+fn split_flow_entries(inner: &str) -> Vec<&str> {
+    let mut entries = Vec::new();
+    let mut quote = None;
+    let mut start = 0;
+    for (pos, ch) in inner.char_indices() {
+        match (quote, ch) {
+            (None, '"' | '\'') => quote = Some(ch),
+            (Some(q), _) if ch == q => quote = None,
+            (None, ',') => {
+                entries.push(&inner[start..pos]);
+                start = pos + 1;
+            }
+            _ => {}
+        }
+    }
+    entries.push(&inner[start..]);
+    entries
 }
 
+// `key` is `None` for a bare `<<` merge key.
 fn insert_inline_entry(
     map: &mut BTreeMap<String, YamlNode>,
-    key: String,
-    mut node: YamlNode,
+    key: Option<String>,
+    node: YamlNode,
     line_no: usize,
     column: usize,
 ) -> Result<(), ParseError> {
-    if key == "<<" {
+    let Some(key) = key else {
         if let YamlValue::Map(extra) = node.value {
             for (k, v) in extra {
                 map.entry(k).or_insert(v);
@@ -920,13 +2524,58 @@ fn insert_inline_entry(
             column,
             message: "merge source must be a mapping".to_string(),
         });
-    }
-    node.leading_comments.clear();
-    node.inline_comment = None;
+    };
     map.insert(key, node);
     Ok(())
 }
 
+// One `<<` source: the alias and the mapping it stands for.
+struct MergeSource {
+    name: String,
+    map: BTreeMap<String, YamlNode>,
+}
+
+// Resolves `*alias` or `[*a, *b]` merge values in order, so applying them
+// with `or_insert` gives the left-most source precedence.
+fn resolve_merge_sources(
+    env: &mut ParseEnv<'_>,
+    vpart: &str,
+    line_no: usize,
+    column: usize,
+) -> Result<Vec<MergeSource>, ParseError> {
+    let items: Vec<&str> = match vpart.strip_prefix('[') {
+        Some(inner) => inner
+            .strip_suffix(']')
+            .ok_or_else(|| ParseError::Generic {
+                line: line_no,
+                column,
+                message: "unterminated merge sequence".to_string(),
+            })?
+            .split(',')
+            .map(str::trim)
+            .filter(|item| !item.is_empty())
+            .collect(),
+        None => vec![vpart],
+    };
+    let mut sources = Vec::with_capacity(items.len());
+    for item in items {
+        let name = item
+            .strip_prefix('*')
+            .ok_or_else(|| ParseError::Generic {
+                line: line_no,
+                column,
+                message: format!("merge sequence entries must be aliases, found '{item}'"),
+            })?
+            .trim();
+        let aliased = env.resolve_alias(name, line_no, column)?;
+        sources.push(MergeSource {
+            name: name.to_string(),
+            map: expect_map(aliased, line_no, column, "merge source")?,
+        });
+    }
+    Ok(sources)
+}
+
 fn expect_map(
     value: YamlValue,
     line_no: usize,
@@ -943,214 +2592,723 @@ fn expect_map(
     }
 }
 
-fn parse_block_scalar<'a>(
-    lines: &[Line<'a>],
-    index: &mut usize,
+fn parse_block_scalar(env: &mut ParseEnv<'_>, min_indent: usize) -> Result<String, ParseError> {
+    let start = env.index;
+    while env.line_at(env.index).is_some_and(|line| line.indent > min_indent) {
+        env.index += 1;
+    }
+    let block = &env.lines[start..env.index];
+    // The least indented line sets the block's indentation; deeper lines
+    // keep the difference as leading spaces. `content` has its leading
+    // spaces stripped already, so they are added back rather than cut.
+    let Some(min) = block.iter().map(|line| line.indent).min() else {
+        return Ok(String::new());
+    };
+    let mut out = String::new();
+    let mut prev_line_no = None;
+    for line in block {
+        if let Some(prev) = prev_line_no {
+            // Blank lines were dropped by `preprocess`; the gap in line
+            // numbers says how many there were.
+            for _ in prev..line.line_no {
+                out.push('\n');
+            }
+        }
+        for _ in min..line.indent {
+            out.push(' ');
+        }
+        out.push_str(&line.content);
+        prev_line_no = Some(line.line_no);
+    }
+    Ok(out)
+}
+
+fn is_block_indicator(raw: &str) -> bool {
+    raw == "|" || raw == ">"
+}
+
+fn parse_block_body(
+    env: &mut ParseEnv<'_>,
     min_indent: usize,
+    indicator: &str,
 ) -> Result<String, ParseError> {
-    let mut result_lines: Vec<(&str, usize)> = Vec::new();
-    while *index < lines.len() {
-        let line = &lines[*index];
-        if line.indent <= min_indent {
-            break;
+    let start = env.index;
+    let first = env.peek_line().map(|l| (l.line_no, l.indent + 1));
+    let text = parse_block_scalar(env, min_indent)?;
+    if env.options.tab_policy == TabPolicy::Reject {
+        for line in &env.lines[start..env.index] {
+            reject_tabs(line, TabPolicy::Reject)?;
         }
-        result_lines.push((line.content, line.indent));
-        *index += 1;
     }
-    if result_lines.is_empty() {
-        return Ok(String::new());
+    if let Some((line, column)) = first {
+        env.check_scalar_len(&text, line, column)?;
     }
-    let min = result_lines
-        .iter()
-        .map(|(_, ind)| *ind)
-        .min()
-        .unwrap_or(min_indent + 1);
+    if indicator != ">" {
+        return Ok(text);
+    }
+    // Folding joins adjacent lines with a space; more-indented lines keep
+    // their line breaks, as in YAML.
+    // A blank line folds to a single line break.
     let mut out = String::new();
-    for (i, (content, indent)) in result_lines.into_iter().enumerate() {
-        let cut = if indent >= min { indent - min } else { 0 };
-        let s = if cut >= content.len() {
-            ""
-        } else {
-            &content[cut..]
-        };
-        if i > 0 {
+    let mut prev_indented = false;
+    let mut prev_blank = false;
+    for (i, line) in text.split('\n').enumerate() {
+        if line.is_empty() {
             out.push('\n');
+            prev_blank = true;
+            continue;
         }
-        out.push_str(s);
+        let indented = line.starts_with(' ');
+        if i > 0 && !prev_blank {
+            out.push(if indented || prev_indented { '\n' } else { ' ' });
+        }
+        out.push_str(line);
+        prev_indented = indented;
+        prev_blank = false;
     }
     Ok(out)
 }
 
-fn parse_key(raw: &str, _line_no: usize) -> Result<String, ParseError> {
-    if raw.starts_with('"') && raw.ends_with('"') && raw.len() >= 2 {
-        Ok(raw[1..raw.len() - 1].to_string())
-    } else if raw.starts_with('\'') && raw.ends_with('\'') && raw.len() >= 2 {
-        Ok(raw[1..raw.len() - 1].to_string())
-    } else {
-        Ok(raw.to_string())
+// Reads the key of a `? key` entry: `? |` (or a bare `?`) takes the nested
+// lines literally, while a plain key folds continuation lines with spaces.
+fn parse_explicit_key(env: &mut ParseEnv<'_>, line: &Line<'_>) -> Result<String, ParseError> {
+    let (content, _) = env.split_comment(&line.content);
+    let rest = content[1..].trim_start();
+    env.index += 1;
+    if rest.is_empty() || rest == "|" {
+        return parse_block_scalar(env, line.indent + 1);
+    }
+    let column = line.indent + 1 + content.len() - rest.len();
+    let mut key = env.unquote(rest, line.line_no, column)?.into_owned();
+    while let Some(next) = env.peek_line().cloned() {
+        if next.indent <= line.indent {
+            break;
+        }
+        reject_tabs(&next, env.options.tab_policy)?;
+        key.push(' ');
+        key.push_str(&next.content);
+        env.index += 1;
     }
+    Ok(key)
 }
 
-fn strip_quotes(s: &str) -> &str {
-    if (s.starts_with('"') && s.ends_with('"') && s.len() >= 2)
-        || (s.starts_with('\'') && s.ends_with('\'') && s.len() >= 2)
-    {
-        &s[1..s.len() - 1]
+fn parse_key(
+    env: &mut ParseEnv<'_>,
+    raw: &str,
+    line_no: usize,
+    column: usize,
+) -> Result<String, ParseError> {
+    if let Some(alias) = raw.strip_prefix('*').filter(|_| env.options.alias_keys) {
+        return match env.resolve_alias(alias.trim(), line_no, column)? {
+            YamlValue::Str(s) => Ok(s),
+            other => Err(ParseError::Generic {
+                line: line_no,
+                column,
+                message: format!(
+                    "alias '{}' used as a key must name a scalar, found {}",
+                    alias.trim(),
+                    other.type_name()
+                ),
+            }),
+        };
+    }
+    Ok(env.unquote(raw, line_no, column)?.into_owned())
+}
+
+// Splits a leading `!tag` / `!!tag` token off a scalar. `!!str` needs no
+// special casing because untagged scalars are already strings.
+fn split_tag(raw: &str) -> (Option<String>, &str) {
+    if !raw.starts_with('!') {
+        return (None, raw);
+    }
+    match raw.find(char::is_whitespace) {
+        Some(end) => (Some(raw[..end].to_string()), raw[end..].trim_start()),
+        None => (Some(raw.to_string()), ""),
+    }
+}
+
+fn parse_tagged_scalar(
+    env: &mut ParseEnv<'_>,
+    raw: &str,
+    block_indent: usize,
+    line_no: usize,
+    column: usize,
+) -> Result<YamlNode, ParseError> {
+    let (tag, rest) = split_tag(raw);
+    let quote = quote_hint(rest);
+    let text = if is_block_indicator(rest) {
+        parse_block_body(env, block_indent, rest)?
     } else {
-        s
+        let text = env.unquote(rest, line_no, column)?.into_owned();
+        env.check_scalar_len(&text, line_no, column)?;
+        text
+    };
+    if let Some(handler) = tag.as_ref().and_then(|t| env.options.tag_handlers.get(t)) {
+        let value = handler(&text).map_err(|message| ParseError::Generic {
+            line: line_no,
+            column,
+            message,
+        })?;
+        return Ok(YamlNode::new(value));
     }
+    let mut node = YamlNode::new(YamlValue::Str(text));
+    node.tag = tag;
+    node.quote = quote;
+    Ok(node)
 }
 
-pub fn dump_naay(value: &YamlValue) -> Result<String, DumpError> {
-    let mut out = String::new();
-    write_value(&mut out, value, 0)?;
-    Ok(out)
+fn is_quoted(s: &str) -> bool {
+    (s.starts_with('"') && s.ends_with('"') && s.len() >= 2)
+        || (s.starts_with('\'') && s.ends_with('\'') && s.len() >= 2)
 }
 
-fn write_value(out: &mut String, value: &YamlValue, indent: usize) -> Result<(), std::fmt::Error> {
-    match value {
-        YamlValue::Str(s) => write_scalar(out, indent, s, None),
-        YamlValue::Seq(seq) => {
-            if seq.is_empty() {
-                for _ in 0..indent {
-                    out.push(' ');
-                }
-                out.push_str("[]\n");
-                Ok(())
-            } else {
-                write_seq(out, seq, indent)
+// Byte offset of the quote closing the one `s` starts with.
+fn closing_quote(s: &str) -> Option<usize> {
+    let quote = s.chars().next().filter(|c| matches!(c, '"' | '\''))?;
+    let mut chars = s.char_indices().skip(1).peekable();
+    while let Some((idx, ch)) = chars.next() {
+        match ch {
+            '\\' if quote == '"' => {
+                chars.next();
             }
-        }
-        YamlValue::Map(map) => {
-            if map.is_empty() {
-                for _ in 0..indent {
-                    out.push(' ');
-                }
-                out.push_str("{}\n");
-                Ok(())
-            } else {
-                write_map(out, map, indent)
+            '\'' if quote == '\'' && chars.peek().is_some_and(|&(_, next)| next == '\'') => {
+                chars.next();
             }
+            _ if ch == quote => return Some(idx),
+            _ => {}
         }
     }
+    None
 }
 
-fn write_comments(out: &mut String, comments: &[CommentLine]) -> Result<(), std::fmt::Error> {
-    for comment in comments {
-        for _ in 0..comment.indent {
-            out.push(' ');
-        }
-        out.push_str(&comment.text);
-        out.push('\n');
+// Byte offset of the `:` that ends a key, looking past a quoted key so
+// that `"a:b": c` splits after the closing quote.
+fn key_colon(content: &str) -> Option<usize> {
+    let start = closing_quote(content).map_or(0, |end| end + 1);
+    content[start..].find(':').map(|pos| start + pos)
+}
+
+fn quote_hint(raw: &str) -> QuoteHint {
+    if raw == "|" {
+        QuoteHint::Literal
+    } else if raw == ">" {
+        QuoteHint::Folded
+    } else if !is_quoted(raw) {
+        QuoteHint::Plain
+    } else if raw.starts_with('\'') {
+        QuoteHint::Single
+    } else {
+        QuoteHint::Double
     }
-    Ok(())
 }
 
-fn write_scalar(
-    out: &mut String,
-    indent: usize,
-    s: &str,
-    inline_comment: Option<&String>,
-) -> Result<(), std::fmt::Error> {
-    if s.contains('\n') {
-        out.push('|');
-        if let Some(comment) = inline_comment {
-            out.push(' ');
-            out.push_str(comment);
+// Decodes the body of a double-quoted scalar. On an unknown escape under
+// `UnknownEscape::Error`, returns its byte offset and text.
+fn decode_escapes(body: &str, policy: UnknownEscape) -> Result<String, (usize, String)> {
+    let mut out = String::with_capacity(body.len());
+    let mut chars = body.char_indices();
+    while let Some((offset, ch)) = chars.next() {
+        if ch != '\\' {
+            out.push(ch);
+            continue;
         }
-        out.push('\n');
-        for line in s.split('\n') {
-            for _ in 0..(indent + 2) {
-                out.push(' ');
+        let Some((_, esc)) = chars.next() else {
+            // A trailing backslash has nothing to escape.
+            match policy {
+                UnknownEscape::Error => return Err((offset, "\\".to_string())),
+                UnknownEscape::Literal => out.push('\\'),
+                UnknownEscape::Strip => {}
             }
-            out.push_str(line);
-            out.push('\n');
+            break;
+        };
+        let decoded = match esc {
+            '0' => Some('\0'),
+            'a' => Some('\u{7}'),
+            'b' => Some('\u{8}'),
+            't' | '\t' => Some('\t'),
+            'n' => Some('\n'),
+            'v' => Some('\u{b}'),
+            'f' => Some('\u{c}'),
+            'r' => Some('\r'),
+            'e' => Some('\u{1b}'),
+            ' ' | '"' | '/' | '\\' => Some(esc),
+            'N' => Some('\u{85}'),
+            '_' => Some('\u{a0}'),
+            'L' => Some('\u{2028}'),
+            'P' => Some('\u{2029}'),
+            'x' | 'u' | 'U' => {
+                let digits = match esc {
+                    'x' => 2,
+                    'u' => 4,
+                    _ => 8,
+                };
+                let hex: String = chars.clone().take(digits).map(|(_, c)| c).collect();
+                let decoded = (hex.len() == digits && hex.chars().all(|c| c.is_ascii_hexdigit()))
+                    .then(|| u32::from_str_radix(&hex, 16).ok().and_then(char::from_u32))
+                    .flatten();
+                if decoded.is_some() {
+                    chars.nth(digits - 1);
+                }
+                decoded
+            }
+            _ => None,
+        };
+        match (decoded, policy) {
+            (Some(c), _) => out.push(c),
+            (None, UnknownEscape::Error) => return Err((offset, format!("\\{esc}"))),
+            (None, UnknownEscape::Literal) => {
+                out.push('\\');
+                out.push(esc);
+            }
+            (None, UnknownEscape::Strip) => out.push(esc),
         }
+    }
+    Ok(out)
+}
+
+fn strip_quotes(s: &str) -> Cow<'_, str> {
+    if s.starts_with('\'') && is_quoted(s) {
+        // Single-quoted scalars escape an apostrophe by doubling it.
+        Cow::Owned(s[1..s.len() - 1].replace("''", "'"))
+    } else if is_quoted(s) {
+        Cow::Borrowed(&s[1..s.len() - 1])
     } else {
-        out.push('"');
-        for ch in s.chars() {
-            match ch {
-                '"' => out.push_str("\\\""),
-                '\\' => out.push_str("\\\\"),
-                _ => out.push(ch),
+        Cow::Borrowed(s)
+    }
+}
+
+pub type KeyFilter = Box<dyn Fn(&str, &YamlValue) -> bool>;
+pub type CommentRewriter = Box<dyn Fn(&str) -> String>;
+pub type GroupLabeler = Box<dyn Fn(&str) -> Option<String>>;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum QuoteStyle {
+    /// Keep the style recorded in each scalar's `QuoteHint` where it is safe;
+    /// scalars without one are double-quoted.
+    #[default]
+    Preserve,
+    /// Double-quote every single-line scalar, ignoring recorded styles.
+    Double,
+    /// Leave scalars bare unless that would change how naay re-parses them.
+    Plain,
+    /// Like `Plain`, but also quote scalars a typed YAML reader would turn
+    /// into numbers, booleans or null.
+    Minimal,
+    /// Single-quote every single-line scalar, doubling embedded apostrophes;
+    /// backslashes stay literal.
+    Single,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum LineEnding {
+    #[default]
+    Lf,
+    CrLf,
+}
+
+/// How an empty value is written. Both forms parse back as `Null`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum NullStyle {
+    /// `key:` and a bare `-`.
+    #[default]
+    Empty,
+    /// `key: ~` and `- ~`.
+    Tilde,
+}
+
+pub struct DumpOptions {
+    /// Called with each map entry's pointer path (e.g. `/debug/level`) and
+    /// value; entries for which it returns `false` are left out.
+    pub key_filter: Option<KeyFilter>,
+    /// Emitted as `#` comment lines above everything else, one per line.
+    pub header_comment: Option<String>,
+    pub quote_style: QuoteStyle,
+    /// Spaces between a sequence dash and its content (at least one).
+    pub dash_spacing: usize,
+    /// Column limit for plain output; longer single-line scalars are written
+    /// as folded (`>`) blocks broken at spaces. Words are never split.
+    pub line_width: Option<usize>,
+    /// Write inline comments on their own line above the entry instead.
+    pub inline_to_leading: bool,
+    pub line_ending: LineEnding,
+    /// Applied to the text (including `#`) of every leading, trailing and
+    /// inline comment before it is written.
+    pub comment_rewriter: Option<CommentRewriter>,
+    /// Emit map keys in sorted order. Otherwise parsed entries keep their
+    /// source order and entries without a span follow them, sorted.
+    pub sort_keys: bool,
+    /// Also sort sequences whose items are all scalars.
+    pub sort_scalar_seqs: bool,
+    /// Write a `# Contents:` comment listing the top-level keys after the
+    /// `_naay_version` entry (or first, without one).
+    pub emit_toc: bool,
+    /// Spaces per nesting level (at least one).
+    pub indent: usize,
+    /// Append a `# changed` inline comment to nodes marked `changed`.
+    pub annotate_changes: bool,
+    /// Called with each top-level key's pointer path (e.g. `/db_host`);
+    /// consecutive entries with the same result form a group. Groups are
+    /// separated by a blank line, and a `Some` label is written as a `#`
+    /// comment at the start of its group.
+    pub group_blank_lines: Option<GroupLabeler>,
+    /// Marker for comments the dumper writes itself (header, contents,
+    /// group labels, change notes); also kept out of plain scalars and keys.
+    pub comment_prefix: CommentPrefix,
+    /// Emit a root `_naay_version` entry before all other keys, whatever
+    /// the key order. On by default.
+    pub version_first: bool,
+    /// Write a non-root sequence or mapping with at most this many plain
+    /// scalar children on one line, as `[a, b]` or `{k: v}`. Children with
+    /// comments, tags or nested collections keep block style.
+    pub flow_threshold: Option<usize>,
+    /// Indent comments to the entries they sit with as written now, rather
+    /// than at the column they were parsed from.
+    pub reindent_comments: bool,
+    pub null_style: NullStyle,
+}
+
+impl Default for DumpOptions {
+    fn default() -> Self {
+        Self {
+            key_filter: None,
+            header_comment: None,
+            quote_style: QuoteStyle::default(),
+            dash_spacing: 1,
+            line_width: None,
+            inline_to_leading: false,
+            line_ending: LineEnding::default(),
+            comment_rewriter: None,
+            sort_keys: false,
+            sort_scalar_seqs: false,
+            emit_toc: false,
+            indent: 2,
+            annotate_changes: false,
+            group_blank_lines: None,
+            comment_prefix: CommentPrefix::default(),
+            version_first: true,
+            flow_threshold: None,
+            reindent_comments: false,
+            null_style: NullStyle::default(),
+        }
+    }
+}
+
+struct DumpCtx<'o> {
+    options: &'o DumpOptions,
+    path: String,
+    // Anchors some `<<` merge in the value refers to, and the entries of
+    // those written so far, as written; a merge is only written back once
+    // its anchors are out.
+    merge_anchors: HashSet<String>,
+    written_anchors: HashMap<String, BTreeMap<String, YamlNode>>,
+}
+
+impl<'o> DumpCtx<'o> {
+    fn new(options: &'o DumpOptions, value: &YamlValue) -> Self {
+        Self {
+            options,
+            path: String::new(),
+            merge_anchors: merge_anchor_names(value),
+            written_anchors: HashMap::new(),
+        }
+    }
+
+    // ` &name` (or `&name ` after a dash) for a mapping a merge refers to.
+    fn anchor(&mut self, node: &YamlNode) -> Option<String> {
+        let name = node.anchor.as_ref().filter(|name| self.merge_anchors.contains(*name))?;
+        let YamlValue::Map(map) = &node.value else {
+            return None;
+        };
+        let entries = self.visible(map).into_iter().map(|(k, v)| (k.clone(), v.clone())).collect();
+        self.written_anchors.insert(name.clone(), entries);
+        Some(name.clone())
+    }
+
+    fn step(&self) -> usize {
+        self.options.indent.max(1)
+    }
+
+    fn rewrite<'c>(&self, comment: &'c str) -> Cow<'c, str> {
+        match &self.options.comment_rewriter {
+            Some(rewriter) => Cow::Owned(rewriter(comment)),
+            None => Cow::Borrowed(comment),
+        }
+    }
+
+    fn enter(&mut self, segment: &str) -> usize {
+        let len = self.path.len();
+        push_pointer_segment(&mut self.path, segment);
+        len
+    }
+
+    fn leave(&mut self, len: usize) {
+        self.path.truncate(len);
+    }
+
+    fn visible<'m>(&mut self, map: &'m BTreeMap<String, YamlNode>) -> Vec<(&'m String, &'m YamlNode)> {
+        let mut visible = Vec::with_capacity(map.len());
+        for (k, node) in map {
+            let Some(filter) = &self.options.key_filter else {
+                visible.push((k, node));
+                continue;
+            };
+            let len = self.path.len();
+            push_pointer_segment(&mut self.path, k);
+            if filter(&self.path, &node.value) {
+                visible.push((k, node));
             }
+            self.path.truncate(len);
         }
-        out.push('"');
-        if let Some(comment) = inline_comment {
+        if !self.options.sort_keys {
+            // Stable, so entries with neither span nor order keep their
+            // sorted order at the end.
+            visible.sort_by_key(|(_, node)| {
+                node.span.map_or((usize::MAX, node.order.unwrap_or(usize::MAX)), |s| (s.line, s.column))
+            });
+        }
+        if self.options.version_first && self.path.is_empty() {
+            if let Some(pos) = visible.iter().position(|(k, _)| k.as_str() == "_naay_version") {
+                let version = visible.remove(pos);
+                visible.insert(0, version);
+            }
+        }
+        visible
+    }
+}
+
+fn merge_anchor_names(value: &YamlValue) -> HashSet<String> {
+    let mut names = HashSet::new();
+    let mut stack = vec![value];
+    while let Some(value) = stack.pop() {
+        let nodes: Box<dyn Iterator<Item = &YamlNode>> = match value {
+            YamlValue::Seq(seq) => Box::new(seq.iter()),
+            YamlValue::Map(map) => Box::new(map.values()),
+            _ => continue,
+        };
+        for node in nodes {
+            names.extend(node.merged_from.iter().cloned());
+            stack.push(&node.value);
+        }
+    }
+    names
+}
+
+fn push_pointer_segment(path: &mut String, segment: &str) {
+    path.push('/');
+    for ch in segment.chars() {
+        match ch {
+            '~' => path.push_str("~0"),
+            '/' => path.push_str("~1"),
+            _ => path.push(ch),
+        }
+    }
+}
+
+pub fn dump_naay(value: &YamlValue) -> Result<String, DumpError> {
+    dump_naay_with(value, &DumpOptions::default())
+}
+
+pub fn dump_naay_with(value: &YamlValue, options: &DumpOptions) -> Result<String, DumpError> {
+    let mut out = String::new();
+    let mut ctx = DumpCtx::new(options, value);
+    if let Some(header) = &options.header_comment {
+        write_header(&mut out, options.comment_prefix, header);
+    }
+    write_value(&mut out, &mut ctx, value, 0)?;
+    if options.line_ending == LineEnding::CrLf {
+        out = out.replace('\n', "\r\n");
+    }
+    Ok(out)
+}
+
+/// Dumps `value` with every line shifted right by `base_indent` spaces, for
+/// pasting a subtree into surrounding text. Empty lines stay empty, and
+/// comments are indented with their entries (see
+/// `DumpOptions::reindent_comments`) since a subtree's parsed columns no
+/// longer apply.
+pub fn dump_naay_indented(value: &YamlValue, base_indent: usize) -> Result<String, DumpError> {
+    let options = DumpOptions {
+        reindent_comments: true,
+        ..DumpOptions::default()
+    };
+    let dumped = dump_naay_with(value, &options)?;
+    let pad = " ".repeat(base_indent);
+    let mut out = String::with_capacity(dumped.len() + dumped.lines().count() * base_indent);
+    for line in dumped.split_inclusive('\n') {
+        if line != "\n" {
+            out.push_str(&pad);
+        }
+        out.push_str(line);
+    }
+    Ok(out)
+}
+
+fn write_header(out: &mut String, prefix: CommentPrefix, header: &str) {
+    for line in header.split('\n') {
+        out.push_str(prefix.as_str());
+        if !line.is_empty() {
             out.push(' ');
-            out.push_str(comment);
+            out.push_str(line);
         }
         out.push('\n');
     }
-    Ok(())
 }
 
-fn write_seq(out: &mut String, seq: &[YamlNode], indent: usize) -> Result<(), std::fmt::Error> {
-    for node in seq {
-        write_comments(out, &node.leading_comments)?;
-        for _ in 0..indent {
-            out.push(' ');
+/// Streams the dump into `writer`, writing and flushing one chunk per
+/// top-level entry so slow sinks receive data incrementally.
+pub fn dump_naay_to<W: io::Write>(value: &YamlValue, mut writer: W) -> Result<(), DumpError> {
+    let options = DumpOptions::default();
+    let mut ctx = DumpCtx::new(&options, value);
+    let mut chunk = String::new();
+    match value {
+        YamlValue::Map(map) if !map.is_empty() => {
+            let mut merges = Vec::new();
+            let entries = ctx.visible(map);
+            for &(k, node) in &entries {
+                if !write_merge_line(&mut chunk, &ctx, &entries, node, 0, &mut merges) {
+                    write_map_entry(&mut chunk, &mut ctx, k, node, 0)?;
+                }
+                // Keys covered by a `<<` line already written add nothing.
+                if chunk.is_empty() {
+                    continue;
+                }
+                writer.write_all(chunk.as_bytes())?;
+                writer.flush()?;
+                chunk.clear();
+            }
         }
-        out.push_str("- ");
-        match &node.value {
-            YamlValue::Str(s) => {
-                write_scalar(out, indent, s, node.inline_comment.as_ref())?;
-            }
-            YamlValue::Seq(child) => {
-                if child.is_empty() {
-                    out.push_str("[]");
-                    if let Some(comment) = &node.inline_comment {
-                        out.push(' ');
-                        out.push_str(comment);
-                    }
-                    out.push('\n');
-                } else {
-                    if let Some(comment) = &node.inline_comment {
-                        out.push(' ');
-                        out.push_str(comment);
-                    }
-                    out.push('\n');
-                    write_seq(out, child, indent + 2)?;
+        YamlValue::Seq(seq) if !seq.is_empty() => {
+            for (i, node) in seq.iter().enumerate() {
+                let len = ctx.enter(&i.to_string());
+                write_seq_item(&mut chunk, &mut ctx, node, 0)?;
+                ctx.leave(len);
+                writer.write_all(chunk.as_bytes())?;
+                writer.flush()?;
+                chunk.clear();
+            }
+        }
+        _ => {
+            write_value(&mut chunk, &mut ctx, value, 0)?;
+            writer.write_all(chunk.as_bytes())?;
+            writer.flush()?;
+        }
+    }
+    Ok(())
+}
+
+fn write_value(
+    out: &mut String,
+    ctx: &mut DumpCtx<'_>,
+    value: &YamlValue,
+    indent: usize,
+) -> Result<(), std::fmt::Error> {
+    match value {
+        // A null document has no content to write.
+        YamlValue::Null => Ok(()),
+        YamlValue::Str(s) => write_scalar(out, ctx, indent, s, None, None, QuoteHint::None),
+        YamlValue::Seq(seq) => {
+            if seq.is_empty() {
+                for _ in 0..indent {
+                    out.push(' ');
                 }
+                out.push_str("[]\n");
+                Ok(())
+            } else {
+                write_seq(out, ctx, seq, indent)
             }
-            YamlValue::Map(map) => {
-                if map.is_empty() {
-                    out.push_str("{}");
-                    if let Some(comment) = &node.inline_comment {
-                        out.push(' ');
-                        out.push_str(comment);
-                    }
-                    out.push('\n');
-                } else {
-                    if let Some(comment) = &node.inline_comment {
-                        out.push(' ');
-                        out.push_str(comment);
-                    }
-                    out.push('\n');
-                    write_map(out, map, indent + 2)?;
+        }
+        YamlValue::Map(map) => {
+            let visible = ctx.visible(map);
+            if visible.is_empty() {
+                for _ in 0..indent {
+                    out.push(' ');
                 }
+                out.push_str("{}\n");
+                Ok(())
+            } else {
+                write_entries(out, ctx, &visible, indent)
             }
         }
     }
-    Ok(())
 }
 
-fn write_map(
+// `indent` is where the surrounding entries are written; it replaces the
+// parsed column under `DumpOptions::reindent_comments`.
+fn write_comments(
     out: &mut String,
-    map: &BTreeMap<String, YamlNode>,
+    ctx: &DumpCtx<'_>,
+    comments: &[CommentLine],
     indent: usize,
 ) -> Result<(), std::fmt::Error> {
-    for (k, node) in map {
-        write_comments(out, &node.leading_comments)?;
+    for comment in comments {
+        let indent = match ctx.options.reindent_comments {
+            // A blank group separator stays empty.
+            true if comment.text.is_empty() => 0,
+            true => indent,
+            false => comment.indent,
+        };
         for _ in 0..indent {
             out.push(' ');
         }
-        let needs_quote = k
-            .chars()
-            .any(|c| c.is_whitespace() || matches!(c, ':' | '?' | '#'));
-        if needs_quote {
+        out.push_str(&ctx.rewrite(&comment.text));
+        out.push('\n');
+    }
+    Ok(())
+}
+
+fn write_scalar(
+    out: &mut String,
+    ctx: &DumpCtx<'_>,
+    indent: usize,
+    s: &str,
+    inline_comment: Option<&str>,
+    tag: Option<&str>,
+    quote: QuoteHint,
+) -> Result<(), std::fmt::Error> {
+    if let Some(tag) = tag {
+        out.push_str(tag);
+        out.push(' ');
+    }
+    let style = scalar_style(ctx.options.quote_style, quote);
+    let literal = ctx.options.quote_style == QuoteStyle::Preserve && quote == QuoteHint::Literal;
+    if let Some(lines) = folded_lines(out, ctx.options, indent, s, style, quote) {
+        out.push('>');
+        if let Some(comment) = inline_comment {
+            out.push(' ');
+            out.push_str(comment);
+        }
+        out.push('\n');
+        for line in lines {
+            for _ in 0..(indent + ctx.step()) {
+                out.push(' ');
+            }
+            out.push_str(line);
+            out.push('\n');
+        }
+    } else if s.contains('\n') || literal {
+        out.push('|');
+        if let Some(comment) = inline_comment {
+            out.push(' ');
+            out.push_str(comment);
+        }
+        out.push('\n');
+        for line in s.split('\n') {
+            if !line.is_empty() {
+                for _ in 0..(indent + ctx.step()) {
+                    out.push(' ');
+                }
+            }
+            out.push_str(line);
+            out.push('\n');
+        }
+    } else {
+        if style == QuoteStyle::Single {
+            out.push('\'');
+            out.push_str(&s.replace('\'', "''"));
+            out.push('\'');
+        } else if scalar_needs_quotes(s, style, ctx.options.comment_prefix) {
             out.push('"');
-            for ch in k.chars() {
+            for ch in s.chars() {
                 match ch {
                     '"' => out.push_str("\\\""),
                     '\\' => out.push_str("\\\\"),
@@ -1159,72 +3317,2788 @@ fn write_map(
             }
             out.push('"');
         } else {
-            out.push_str(k);
+            out.push_str(s);
         }
-        out.push_str(":");
-        match &node.value {
-            YamlValue::Str(s) => {
-                out.push(' ');
-                write_scalar(out, indent, s, node.inline_comment.as_ref())?;
-            }
-            YamlValue::Seq(child) => {
-                if child.is_empty() {
-                    out.push_str(" []");
-                    if let Some(comment) = &node.inline_comment {
-                        out.push(' ');
-                        out.push_str(comment);
-                    }
-                    out.push('\n');
-                } else {
-                    if let Some(comment) = &node.inline_comment {
-                        out.push(' ');
-                        out.push_str(comment);
-                    }
-                    out.push('\n');
-                    write_seq(out, child, indent + 2)?;
-                }
-            }
-            YamlValue::Map(child) => {
-                if child.is_empty() {
-                    out.push_str(" {}");
-                    if let Some(comment) = &node.inline_comment {
-                        out.push(' ');
-                        out.push_str(comment);
-                    }
-                    out.push('\n');
-                } else {
-                    if let Some(comment) = &node.inline_comment {
-                        out.push(' ');
-                        out.push_str(comment);
-                    }
-                    out.push('\n');
-                    write_map(out, child, indent + 2)?;
-                }
-            }
+        if let Some(comment) = inline_comment {
+            out.push(' ');
+            out.push_str(comment);
         }
+        out.push('\n');
     }
     Ok(())
 }
 
-#[cfg(test)]
-mod tests {
-    use super::*;
+// Any style other than `Preserve` overrides the hint.
+fn scalar_style(style: QuoteStyle, quote: QuoteHint) -> QuoteStyle {
+    match (style, quote) {
+        (QuoteStyle::Preserve, QuoteHint::Single) => QuoteStyle::Single,
+        (QuoteStyle::Preserve, QuoteHint::Plain | QuoteHint::Folded) => QuoteStyle::Plain,
+        (style, _) => style,
+    }
+}
 
-    #[test]
-    fn preserves_single_line_comments() {
-        let input = r#"
-    # preface
-    _naay_version: "1.0" # force version
-defaults:
-    # nested
-    alignment: "TRUE NEUTRAL"
-"#;
+// `value` written on one line as `[a, b]` or `{k: v}`, when
+// `DumpOptions::flow_threshold` allows it.
+fn flow_collection(ctx: &mut DumpCtx<'_>, node: &YamlNode) -> Option<String> {
+    let threshold = ctx.options.flow_threshold?;
+    if !node.inner_leading_comments.is_empty() {
+        return None;
+    }
+    let (open, close, entries): (_, _, Vec<(Option<&String>, &YamlNode)>) = match &node.value {
+        YamlValue::Seq(seq) => {
+            let mut items: Vec<&YamlNode> = seq.iter().collect();
+            if ctx.options.sort_scalar_seqs {
+                items.sort_by(|a, b| a.value.as_str().cmp(&b.value.as_str()));
+            }
+            ('[', ']', items.into_iter().map(|node| (None, node)).collect())
+        }
+        YamlValue::Map(map) => {
+            let visible = ctx.visible(map);
+            ('{', '}', visible.into_iter().map(|(k, node)| (Some(k), node)).collect())
+        }
+        YamlValue::Null | YamlValue::Str(_) => return None,
+    };
+    if entries.is_empty() || entries.len() > threshold {
+        return None;
+    }
+    let mut out = String::new();
+    out.push(open);
+    for (i, (key, node)) in entries.into_iter().enumerate() {
+        let YamlValue::Str(s) = &node.value else {
+            return None;
+        };
+        let plain = node.leading_comments.is_empty()
+            && node.inline_comment.is_none()
+            && node.trailing_comments.is_empty()
+            && node.tag.is_none()
+            && !(ctx.options.annotate_changes && node.changed)
+            && !s.contains('\n');
+        if !plain {
+            return None;
+        }
+        if i > 0 {
+            out.push_str(", ");
+        }
+        if let Some(key) = key {
+            if key_needs_quotes(key, ctx.options.comment_prefix) || key.contains([',', ']', '}']) {
+                return None;
+            }
+            out.push_str(key);
+            out.push_str(": ");
+        }
+        push_flow_scalar(&mut out, ctx, s, node.quote);
+    }
+    out.push(close);
+    Some(out)
+}
 
-        let parsed = parse_naay(input).expect("parse should succeed");
-        let dumped = dump_naay(&parsed).expect("dump should succeed");
+fn push_flow_scalar(out: &mut String, ctx: &DumpCtx<'_>, s: &str, quote: QuoteHint) {
+    let style = scalar_style(ctx.options.quote_style, quote);
+    if style == QuoteStyle::Single {
+        out.push('\'');
+        out.push_str(&s.replace('\'', "''"));
+        out.push('\'');
+    } else if scalar_needs_quotes(s, style, ctx.options.comment_prefix)
+        || s.contains([',', '[', ']', '{', '}'])
+    {
+        out.push('"');
+        for ch in s.chars() {
+            match ch {
+                '"' => out.push_str("\\\""),
+                '\\' => out.push_str("\\\\"),
+                _ => out.push(ch),
+            }
+        }
+        out.push('"');
+    } else {
+        out.push_str(s);
+    }
+}
 
-        assert!(dumped.contains("# preface"));
-        assert!(dumped.contains("# force version"));
-        assert!(dumped.contains("# nested"));
+// Splits `s` into the lines of a folded block when it should be written as
+// one. Only strings whose words are separated by single spaces qualify, since
+// folding rejoins them with exactly one.
+fn folded_lines<'s>(
+    out: &str,
+    options: &DumpOptions,
+    indent: usize,
+    s: &'s str,
+    style: QuoteStyle,
+    quote: QuoteHint,
+) -> Option<Vec<&'s str>> {
+    if !matches!(style, QuoteStyle::Plain | QuoteStyle::Minimal)
+        || s.is_empty()
+        || s.contains('\n')
+        || s.starts_with(' ')
+        || s.ends_with(' ')
+        || s.contains("  ")
+    {
+        return None;
+    }
+    let hinted = options.quote_style == QuoteStyle::Preserve && quote == QuoteHint::Folded;
+    let Some(width) = options.line_width else {
+        return hinted.then(|| vec![s]);
+    };
+    let column = out[out.rfind('\n').map_or(0, |p| p + 1)..].chars().count();
+    if !hinted && column + s.chars().count() <= width {
+        return None;
+    }
+    let avail = width.saturating_sub(indent + options.indent.max(1)).max(1);
+    let mut lines = Vec::new();
+    let mut start = 0;
+    let mut len = 0;
+    for (pos, word) in s.split(' ').scan(0, |pos, w| {
+        let at = *pos;
+        *pos += w.len() + 1;
+        Some((at, w))
+    }) {
+        let word_len = word.chars().count();
+        if len > 0 && len + 1 + word_len > avail {
+            lines.push(&s[start..pos - 1]);
+            start = pos;
+            len = 0;
+        }
+        len += usize::from(len > 0) + word_len;
+    }
+    lines.push(&s[start..]);
+    Some(lines)
+}
+
+fn scalar_needs_quotes(s: &str, style: QuoteStyle, prefix: CommentPrefix) -> bool {
+    match style {
+        QuoteStyle::Preserve | QuoteStyle::Double => true,
+        QuoteStyle::Plain => !plain_scalar_round_trips(s, prefix),
+        QuoteStyle::Minimal => !plain_scalar_round_trips(s, prefix) || looks_typed(s),
+        QuoteStyle::Single => true,
+    }
+}
+
+// Whether `s` written bare re-parses to the same string in any value
+// position (mapping value, sequence item or inline map).
+fn plain_scalar_round_trips(s: &str, prefix: CommentPrefix) -> bool {
+    let Some(first) = s.chars().next() else {
+        return false;
+    };
+    // Tabs are refused in plain scalars under the default tab policy.
+    if s.trim() != s || s.contains('\t') {
+        return false;
+    }
+    if matches!(
+        first,
+        '-' | '?' | ':' | ',' | '[' | ']' | '{' | '}' | '#' | '&' | '*' | '!' | '|' | '>' | '\''
+            | '"' | '%' | '@' | '`'
+    ) {
+        return false;
+    }
+    let prefix = prefix.as_str();
+    // A `#` (or the configured prefix) after any whitespace would be read
+    // back as the start of an inline comment.
+    let starts_comment = s.char_indices().any(|(idx, c)| {
+        let rest = &s[idx + c.len_utf8()..];
+        c.is_whitespace() && (rest.starts_with('#') || rest.starts_with(prefix))
+    });
+    // A bare `~` reads back as null.
+    s != "~" && !s.contains(':') && !s.starts_with(prefix) && !starts_comment
+}
+
+fn looks_typed(s: &str) -> bool {
+    let lower = s.to_ascii_lowercase();
+    if matches!(
+        lower.as_str(),
+        "true" | "false" | "yes" | "no" | "on" | "off" | "y" | "n" | "null" | "~"
+            | ".nan" | ".inf" | "-.inf" | "+.inf"
+    ) {
+        return true;
+    }
+    let digits = lower.strip_prefix(['+', '-']).unwrap_or(&lower);
+    if let Some(hex) = digits.strip_prefix("0x") {
+        return !hex.is_empty() && hex.chars().all(|c| c.is_ascii_hexdigit());
+    }
+    if let Some(oct) = digits.strip_prefix("0o") {
+        return !oct.is_empty() && oct.chars().all(|c| c.is_digit(8));
+    }
+    lower.parse::<f64>().is_ok()
+}
+
+fn write_seq(
+    out: &mut String,
+    ctx: &mut DumpCtx<'_>,
+    seq: &[YamlNode],
+    indent: usize,
+) -> Result<(), std::fmt::Error> {
+    let mut items: Vec<&YamlNode> = seq.iter().collect();
+    if ctx.options.sort_scalar_seqs && items.iter().all(|node| node.value.is_str()) {
+        items.sort_by(|a, b| a.value.as_str().cmp(&b.value.as_str()));
+    }
+    for (i, node) in items.into_iter().enumerate() {
+        let len = ctx.enter(&i.to_string());
+        write_seq_item(out, ctx, node, indent)?;
+        ctx.leave(len);
+    }
+    Ok(())
+}
+
+// Writes a node's leading comments and returns the inline comment still to
+// be written after its value, if any.
+fn write_node_comments<'n>(
+    out: &mut String,
+    ctx: &DumpCtx<'_>,
+    node: &'n YamlNode,
+    indent: usize,
+) -> Result<Option<Cow<'n, str>>, std::fmt::Error> {
+    write_comments(out, ctx, &node.leading_comments, indent)?;
+    let inline = node
+        .inline_comment
+        .as_deref()
+        .filter(|c| !c.is_empty())
+        .map(|c| ctx.rewrite(c));
+    let inline = match inline {
+        Some(comment) if ctx.options.inline_to_leading => {
+            for _ in 0..indent {
+                out.push(' ');
+            }
+            out.push_str(&comment);
+            out.push('\n');
+            None
+        }
+        inline => inline,
+    };
+    if !(ctx.options.annotate_changes && node.changed) {
+        return Ok(inline);
+    }
+    let prefix = ctx.options.comment_prefix.as_str();
+    Ok(Some(match inline {
+        Some(comment) => Cow::Owned(format!("{comment} {prefix} changed")),
+        None => Cow::Owned(format!("{prefix} changed")),
+    }))
+}
+
+fn write_seq_item(
+    out: &mut String,
+    ctx: &mut DumpCtx<'_>,
+    node: &YamlNode,
+    indent: usize,
+) -> Result<(), std::fmt::Error> {
+    let inline = write_node_comments(out, ctx, node, indent)?;
+    for _ in 0..indent {
+        out.push(' ');
+    }
+    out.push('-');
+    for _ in 0..ctx.options.dash_spacing.max(1) {
+        out.push(' ');
+    }
+    if let Some(name) = ctx.anchor(node) {
+        out.push('&');
+        out.push_str(&name);
+        out.push(' ');
+    }
+    if let Some(flow) = flow_collection(ctx, node) {
+        out.push_str(&flow);
+        if let Some(comment) = inline {
+            out.push(' ');
+            out.push_str(&comment);
+        }
+        out.push('\n');
+        return Ok(());
+    }
+    let mut block = false;
+    match &node.value {
+        YamlValue::Null => {
+            // Drop the dash spacing so an empty item is a bare `-`.
+            out.truncate(out.trim_end_matches(' ').len());
+            if ctx.options.null_style == NullStyle::Tilde {
+                out.push_str(" ~");
+            }
+            if let Some(comment) = inline {
+                out.push(' ');
+                out.push_str(&comment);
+            }
+            out.push('\n');
+        }
+        YamlValue::Str(s) => {
+            write_scalar(
+                out,
+                ctx,
+                indent,
+                s,
+                inline.as_deref(),
+                node.tag.as_deref(),
+                node.quote,
+            )?;
+        }
+        YamlValue::Seq(child) => {
+            if child.is_empty() {
+                out.push_str("[]");
+                if let Some(comment) = inline {
+                    out.push(' ');
+                    out.push_str(&comment);
+                }
+                out.push('\n');
+            } else {
+                // `-` or `- # comment`, without the dash spacing at the end.
+                out.truncate(out.trim_end_matches(' ').len());
+                if let Some(comment) = inline {
+                    out.push(' ');
+                    out.push_str(&comment);
+                }
+                out.push('\n');
+                write_comments(out, ctx, &node.inner_leading_comments, indent)?;
+                write_seq(out, ctx, child, indent + ctx.step())?;
+                write_comments(out, ctx, &node.trailing_comments, indent + ctx.step())?;
+                block = true;
+            }
+        }
+        YamlValue::Map(map) => {
+            let visible = ctx.visible(map);
+            if visible.is_empty() {
+                out.push_str("{}");
+                if let Some(comment) = inline {
+                    out.push(' ');
+                    out.push_str(&comment);
+                }
+                out.push('\n');
+            } else {
+                // `-` or `- # comment`, without the dash spacing at the end.
+                out.truncate(out.trim_end_matches(' ').len());
+                if let Some(comment) = inline {
+                    out.push(' ');
+                    out.push_str(&comment);
+                }
+                out.push('\n');
+                write_comments(out, ctx, &node.inner_leading_comments, indent)?;
+                write_entries(out, ctx, &visible, indent + ctx.step())?;
+                write_comments(out, ctx, &node.trailing_comments, indent + ctx.step())?;
+                block = true;
+            }
+        }
+    }
+    // A scalar or empty value holds trailing comments only at the end of
+    // the document.
+    if !block {
+        write_comments(out, ctx, &node.trailing_comments, indent)?;
+    }
+    Ok(())
+}
+
+fn write_entries(
+    out: &mut String,
+    ctx: &mut DumpCtx<'_>,
+    entries: &[(&String, &YamlNode)],
+    indent: usize,
+) -> Result<(), std::fmt::Error> {
+    let toc = ctx.options.emit_toc && indent == 0 && ctx.path.is_empty();
+    let has_version = entries.iter().any(|(k, _)| k.as_str() == "_naay_version");
+    if toc && !has_version {
+        write_toc(out, ctx.options.comment_prefix, entries);
+    }
+    let grouper = ctx.options.group_blank_lines.as_ref().filter(|_| indent == 0 && ctx.path.is_empty());
+    let mut group: Option<Option<String>> = None;
+    let mut merges = Vec::new();
+    for (k, node) in entries {
+        if write_merge_line(out, ctx, entries, node, indent, &mut merges) {
+            continue;
+        }
+        if let Some(grouper) = grouper {
+            let mut path = String::new();
+            push_pointer_segment(&mut path, k);
+            let label = grouper(&path);
+            if group.as_ref() != Some(&label) {
+                if group.is_some() {
+                    out.push('\n');
+                }
+                if let Some(text) = &label {
+                    write_header(out, ctx.options.comment_prefix, text);
+                }
+                group = Some(label);
+            }
+        }
+        write_map_entry(out, ctx, k, node, indent)?;
+        if toc && k.as_str() == "_naay_version" {
+            write_toc(out, ctx.options.comment_prefix, entries);
+        }
+    }
+    Ok(())
+}
+
+// An entry a `<<` merge brought in is written as that `<<` line, once per
+// merge, provided reading the line back gives the same entries (see
+// `merge_still_holds`); an entry replaced through `set_value` is written
+// itself. Returns whether the entry was covered.
+fn write_merge_line<'n>(
+    out: &mut String,
+    ctx: &DumpCtx<'_>,
+    entries: &[(&String, &'n YamlNode)],
+    node: &'n YamlNode,
+    indent: usize,
+    merges: &mut Vec<(&'n [String], bool)>,
+) -> bool {
+    let names = node.merged_from.as_slice();
+    if names.is_empty() || node.changed {
+        return false;
+    }
+    if let Some(&(_, holds)) = merges.iter().find(|(seen, _)| *seen == names) {
+        return holds;
+    }
+    let holds = merge_still_holds(ctx, entries, names);
+    merges.push((names, holds));
+    if !holds {
+        return false;
+    }
+    for _ in 0..indent {
+        out.push(' ');
+    }
+    out.push_str("<<: ");
+    let aliases: Vec<String> = names.iter().map(|name| format!("*{name}")).collect();
+    match aliases.as_slice() {
+        [alias] => out.push_str(alias),
+        _ => {
+            out.push('[');
+            out.push_str(&aliases.join(", "));
+            out.push(']');
+        }
+    }
+    out.push('\n');
+    true
+}
+
+// Whether `<<` with `names` would bring back exactly the entries it covers:
+// every anchor is already written, each key it merges is still in the
+// mapping, and each covered entry equals the merged value it stands for.
+fn merge_still_holds(ctx: &DumpCtx<'_>, entries: &[(&String, &YamlNode)], names: &[String]) -> bool {
+    let mut merged: BTreeMap<&str, &YamlNode> = BTreeMap::new();
+    for name in names {
+        let Some(source) = ctx.written_anchors.get(name) else {
+            return false;
+        };
+        for (k, v) in source {
+            merged.entry(k.as_str()).or_insert(v);
+        }
+    }
+    let keys: HashSet<&str> = entries.iter().map(|(k, _)| k.as_str()).collect();
+    merged.keys().all(|k| keys.contains(k))
+        && entries
+            .iter()
+            .filter(|(_, node)| node.merged_from == names && !node.changed)
+            .all(|(k, node)| merged.get(k.as_str()).is_some_and(|v| v.value.semantic_eq(&node.value)))
+}
+
+fn write_toc(out: &mut String, prefix: CommentPrefix, entries: &[(&String, &YamlNode)]) {
+    out.push_str(prefix.as_str());
+    out.push_str(" Contents:\n");
+    for (k, _) in entries.iter().filter(|(k, _)| k.as_str() != "_naay_version") {
+        out.push_str(prefix.as_str());
+        out.push_str("   ");
+        out.push_str(k);
+        out.push('\n');
+    }
+}
+
+// Whether `k` written bare would re-parse as something else: an indicator,
+// a quoted scalar, an alias, a merge or a comment.
+fn key_needs_quotes(k: &str, prefix: CommentPrefix) -> bool {
+    k.is_empty()
+        || k == "<<"
+        || k.starts_with(['-', '*', '&', '!', '[', '{', '|', '>', '"', '\'', '%', '@', '`'])
+        || k.starts_with(prefix.as_str())
+        || k.chars().any(|c| c.is_whitespace() || matches!(c, ':' | '?' | '#'))
+}
+
+fn write_map_entry(
+    out: &mut String,
+    ctx: &mut DumpCtx<'_>,
+    k: &str,
+    node: &YamlNode,
+    indent: usize,
+) -> Result<(), std::fmt::Error> {
+    let len = ctx.enter(k);
+    let inline = write_node_comments(out, ctx, node, indent)?;
+    for _ in 0..indent {
+        out.push(' ');
+    }
+    let needs_quote = key_needs_quotes(k, ctx.options.comment_prefix);
+    if k.contains('\n') {
+        out.push_str("? |\n");
+        for line in k.split('\n') {
+            if line.is_empty() {
+                out.push('\n');
+                continue;
+            }
+            for _ in 0..(indent + ctx.step()) {
+                out.push(' ');
+            }
+            out.push_str(line);
+            out.push('\n');
+        }
+        for _ in 0..indent {
+            out.push(' ');
+        }
+    } else if needs_quote && k.contains(['"', '\\']) {
+        // Single quotes read back the same whether or not escapes are decoded.
+        out.push('\'');
+        out.push_str(&k.replace('\'', "''"));
+        out.push('\'');
+    } else if needs_quote {
+        out.push('"');
+        out.push_str(k);
+        out.push('"');
+    } else {
+        out.push_str(k);
+    }
+    out.push(':');
+    if let Some(name) = ctx.anchor(node) {
+        out.push_str(" &");
+        out.push_str(&name);
+    }
+    if let Some(flow) = flow_collection(ctx, node) {
+        out.push(' ');
+        out.push_str(&flow);
+        if let Some(comment) = inline {
+            out.push(' ');
+            out.push_str(&comment);
+        }
+        out.push('\n');
+        ctx.leave(len);
+        return Ok(());
+    }
+    let mut block = false;
+    match &node.value {
+        YamlValue::Null => {
+            if ctx.options.null_style == NullStyle::Tilde {
+                out.push_str(" ~");
+            }
+            if let Some(comment) = inline {
+                out.push(' ');
+                out.push_str(&comment);
+            }
+            out.push('\n');
+        }
+        YamlValue::Str(s) => {
+            out.push(' ');
+            write_scalar(
+                out,
+                ctx,
+                indent,
+                s,
+                inline.as_deref(),
+                node.tag.as_deref(),
+                node.quote,
+            )?;
+        }
+        YamlValue::Seq(child) => {
+            if child.is_empty() {
+                out.push_str(" []");
+                if let Some(comment) = inline {
+                    out.push(' ');
+                    out.push_str(&comment);
+                }
+                out.push('\n');
+            } else {
+                if let Some(comment) = inline {
+                    out.push(' ');
+                    out.push_str(&comment);
+                }
+                out.push('\n');
+                write_comments(out, ctx, &node.inner_leading_comments, indent)?;
+                write_seq(out, ctx, child, indent + ctx.step())?;
+                write_comments(out, ctx, &node.trailing_comments, indent + ctx.step())?;
+                block = true;
+            }
+        }
+        YamlValue::Map(child) => {
+            let visible = ctx.visible(child);
+            if visible.is_empty() {
+                out.push_str(" {}");
+                if let Some(comment) = inline {
+                    out.push(' ');
+                    out.push_str(&comment);
+                }
+                out.push('\n');
+            } else {
+                if let Some(comment) = inline {
+                    out.push(' ');
+                    out.push_str(&comment);
+                }
+                out.push('\n');
+                write_comments(out, ctx, &node.inner_leading_comments, indent)?;
+                write_entries(out, ctx, &visible, indent + ctx.step())?;
+                write_comments(out, ctx, &node.trailing_comments, indent + ctx.step())?;
+                block = true;
+            }
+        }
+    }
+    // A scalar or empty value holds trailing comments only at the end of
+    // the document.
+    if !block {
+        write_comments(out, ctx, &node.trailing_comments, indent)?;
+    }
+    ctx.leave(len);
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn preserves_single_line_comments() {
+        let input = r#"
+# preface
+_naay_version: "1.0" # force version
+defaults:
+    # nested
+    alignment: "TRUE NEUTRAL"
+"#;
+
+        let parsed = parse_naay(input).expect("parse should succeed");
+        let dumped = dump_naay(&parsed).expect("dump should succeed");
+
+        assert!(dumped.contains("# preface"));
+        assert!(dumped.contains("# force version"));
+        assert!(dumped.contains("# nested"));
+    }
+
+    #[test]
+    fn records_spans_for_nested_values() {
+        let input = r#"_naay_version: "1.0"
+defaults:
+  alignment: "TRUE NEUTRAL"
+  tags:
+    - first
+    - key: value
+"#;
+
+        let parsed = parse_naay(input).expect("parse should succeed");
+        let YamlValue::Map(root) = &parsed else {
+            panic!("root should be a map");
+        };
+        let defaults = &root["defaults"];
+        assert_eq!(defaults.span, Some(Span { line: 2, column: 1 }));
+        let YamlValue::Map(inner) = &defaults.value else {
+            panic!("defaults should be a map");
+        };
+        assert_eq!(inner["alignment"].span, Some(Span { line: 3, column: 3 }));
+        let YamlValue::Seq(tags) = &inner["tags"].value else {
+            panic!("tags should be a sequence");
+        };
+        assert_eq!(tags[1].span, Some(Span { line: 6, column: 5 }));
+        let YamlValue::Map(item) = &tags[1].value else {
+            panic!("inline map expected");
+        };
+        assert_eq!(item["key"].span, Some(Span { line: 6, column: 7 }));
+    }
+
+
+    #[test]
+    fn parses_and_reemits_scalar_tags() {
+        let input = r#"_naay_version: "1.0"
+version: !!str 1.0
+count: !!int 42
+when: !date "2024-01-01"
+items:
+  - !!int 7
+  - name: !custom value
+"#;
+
+        let parsed = parse_naay(input).expect("parse should succeed");
+        let YamlValue::Map(root) = &parsed else {
+            panic!("root should be a map");
+        };
+        assert_eq!(root["version"].value, YamlValue::Str("1.0".to_string()));
+        assert_eq!(root["version"].tag.as_deref(), Some("!!str"));
+        assert_eq!(root["count"].value, YamlValue::Str("42".to_string()));
+        assert_eq!(root["count"].tag.as_deref(), Some("!!int"));
+        assert_eq!(root["when"].value, YamlValue::Str("2024-01-01".to_string()));
+        assert_eq!(root["when"].tag.as_deref(), Some("!date"));
+
+        let dumped = dump_naay(&parsed).expect("dump should succeed");
+        assert!(dumped.contains("version: !!str 1.0"));
+        assert!(dumped.contains("count: !!int 42"));
+        assert!(dumped.contains("when: !date \"2024-01-01\""));
+        assert!(dumped.contains("- !!int 7"));
+        assert!(dumped.contains("name: !custom value"));
+        let reparsed = parse_naay(&dumped).expect("reparse should succeed");
+        assert_eq!(dump_naay(&reparsed).expect("dump should succeed"), dumped);
+    }
+
+
+    #[test]
+    fn applies_registered_tag_handlers() {
+        let mut options = ParseOptions::default();
+        options.tag_handlers.insert(
+            "!upper".to_string(),
+            Box::new(|s: &str| Ok(YamlValue::Str(s.to_uppercase()))),
+        );
+        options.tag_handlers.insert(
+            "!reject".to_string(),
+            Box::new(|s: &str| Err(format!("rejected '{s}'"))),
+        );
+
+        let input = r#"_naay_version: "1.0"
+name: !upper shout
+other: !keep quiet
+"#;
+        let parsed = parse_naay_with(input, &options).expect("parse should succeed");
+        let YamlValue::Map(root) = &parsed else {
+            panic!("root should be a map");
+        };
+        assert_eq!(root["name"].value, YamlValue::Str("SHOUT".to_string()));
+        assert_eq!(root["name"].tag, None);
+        assert_eq!(root["other"].tag.as_deref(), Some("!keep"));
+
+        let err = parse_naay_with("_naay_version: \"1.0\"\nbad: !reject nope\n", &options)
+            .expect_err("handler error should surface");
+        let ParseError::Generic {
+            line,
+            column,
+            message,
+        } = err;
+        assert_eq!((line, column), (2, 6));
+        assert_eq!(message, "rejected 'nope'");
+    }
+
+
+    #[test]
+    fn streams_one_chunk_per_top_level_entry() {
+        struct CountingWriter {
+            chunks: Vec<String>,
+            flushes: usize,
+        }
+
+        impl io::Write for CountingWriter {
+            fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+                self.chunks.push(String::from_utf8_lossy(buf).into_owned());
+                Ok(buf.len())
+            }
+
+            fn flush(&mut self) -> io::Result<()> {
+                self.flushes += 1;
+                Ok(())
+            }
+        }
+
+        let input = r#"_naay_version: "1.0"
+alpha: "a"
+beta:
+  nested: "b"
+"#;
+        let parsed = parse_naay(input).expect("parse should succeed");
+        let mut writer = CountingWriter {
+            chunks: Vec::new(),
+            flushes: 0,
+        };
+        dump_naay_to(&parsed, &mut writer).expect("dump should succeed");
+
+        assert_eq!(writer.chunks.len(), 3);
+        assert_eq!(writer.flushes, 3);
+        assert_eq!(writer.chunks[0], "_naay_version: \"1.0\"\n");
+        assert_eq!(writer.chunks[2], "beta:\n  nested: \"b\"\n");
+        assert_eq!(writer.chunks.concat(), dump_naay(&parsed).expect("dump should succeed"));
+    }
+
+
+    #[test]
+    fn parses_explicit_multi_line_keys() {
+        let input = r#"_naay_version: "1.0"
+? |
+  first line
+  second line
+: "value"
+? folded
+  key
+: other
+"#;
+
+        let parsed = parse_naay(input).expect("parse should succeed");
+        let YamlValue::Map(root) = &parsed else {
+            panic!("root should be a map");
+        };
+        let node = &root["first line\nsecond line"];
+        assert_eq!(node.value, YamlValue::Str("value".to_string()));
+        assert_eq!(node.span, Some(Span { line: 2, column: 1 }));
+        assert_eq!(root["folded key"].value, YamlValue::Str("other".to_string()));
+
+        let dumped = dump_naay(&parsed).expect("dump should succeed");
+        assert!(dumped.contains("? |\n  first line\n  second line\n: \"value\"\n"));
+        let reparsed = parse_naay(&dumped).expect("reparse should succeed");
+        let YamlValue::Map(again) = &reparsed else {
+            panic!("root should be a map");
+        };
+        assert_eq!(again["first line\nsecond line"].value, node.value);
+
+        let err = parse_naay("_naay_version: \"1.0\"\n? dangling\nnext: x\n")
+            .expect_err("missing ':' line should error");
+        assert!(err.to_string().contains("explicit key"));
+    }
+
+
+    #[test]
+    fn merges_sequence_of_aliases_left_most_first() {
+        let input = r#"_naay_version: "1.0"
+base: &base
+  color: "red"
+  size: "small"
+overrides: &overrides
+  size: "large"
+  shape: "round"
+item:
+  <<: [*base, *overrides]
+  name: "widget"
+"#;
+
+        let parsed = parse_naay(input).expect("parse should succeed");
+        let YamlValue::Map(root) = &parsed else {
+            panic!("root should be a map");
+        };
+        let YamlValue::Map(item) = &root["item"].value else {
+            panic!("item should be a map");
+        };
+        let get = |k: &str| item[k].value.clone();
+        assert_eq!(get("color"), YamlValue::Str("red".to_string()));
+        assert_eq!(get("size"), YamlValue::Str("small".to_string()));
+        assert_eq!(get("shape"), YamlValue::Str("round".to_string()));
+        assert_eq!(get("name"), YamlValue::Str("widget".to_string()));
+
+        let bad = r#"_naay_version: "1.0"
+list: &list
+  - a
+item:
+  <<: [*list]
+"#;
+        let err = parse_naay(bad).expect_err("non-map merge source should error");
+        assert!(err.to_string().contains("merge source must be a mapping"));
+    }
+
+
+    #[test]
+    fn key_filter_drops_entries_by_path() {
+        let input = r#"_naay_version: "1.0"
+app:
+  name: "demo"
+  debug:
+    verbose: "true"
+debug:
+  level: "trace"
+"#;
+        let parsed = parse_naay(input).expect("parse should succeed");
+        let options = DumpOptions {
+            key_filter: Some(Box::new(|path: &str, _: &YamlValue| {
+                !path.ends_with("/debug") && !path.contains("/debug/")
+            })),
+            ..DumpOptions::default()
+        };
+        let dumped = dump_naay_with(&parsed, &options).expect("dump should succeed");
+        assert!(!dumped.contains("debug"));
+        assert!(!dumped.contains("verbose"));
+        assert!(dumped.contains("name: \"demo\""));
+
+        let only_root_debug = DumpOptions {
+            key_filter: Some(Box::new(|path: &str, _: &YamlValue| path != "/debug")),
+            ..DumpOptions::default()
+        };
+        let dumped = dump_naay_with(&parsed, &only_root_debug).expect("dump should succeed");
+        assert!(dumped.contains("  debug:\n    verbose: \"true\""));
+        assert!(!dumped.contains("level"));
+    }
+
+
+    #[test]
+    fn value_accessors_borrow_matching_variants() {
+        let scalar = YamlValue::Str("42".to_string());
+        let seq = YamlValue::Seq(vec![YamlNode::new(YamlValue::Str("a".to_string()))]);
+        let mut entries = BTreeMap::new();
+        entries.insert("k".to_string(), YamlNode::new(YamlValue::Str("v".to_string())));
+        let map = YamlValue::Map(entries);
+
+        assert_eq!(scalar.as_str(), Some("42"));
+        assert_eq!(seq.as_str(), None);
+        assert_eq!(seq.as_seq().map(<[YamlNode]>::len), Some(1));
+        assert!(map.as_seq().is_none());
+        assert_eq!(
+            map.as_map().and_then(|m| m.get("k")).and_then(|n| n.value.as_str()),
+            Some("v")
+        );
+        assert!(scalar.as_map().is_none());
+
+        assert!(scalar.is_str() && !scalar.is_seq() && !scalar.is_map());
+        assert!(seq.is_seq() && !seq.is_str());
+        assert!(map.is_map() && !map.is_seq());
+
+        assert_eq!(scalar.as_i64(), Some(42));
+        assert_eq!(scalar.as_f64(), Some(42.0));
+        assert_eq!(YamlValue::Str("1.5".to_string()).as_i64(), None);
+        assert_eq!(YamlValue::Str("1.5".to_string()).as_f64(), Some(1.5));
+        assert_eq!(YamlValue::Str("true".to_string()).as_bool(), Some(true));
+        assert_eq!(YamlValue::Str("false".to_string()).as_bool(), Some(false));
+        assert_eq!(YamlValue::Str("yes".to_string()).as_bool(), None);
+        assert_eq!(map.as_i64(), None);
+    }
+
+
+    #[test]
+    fn keeps_tabs_inside_block_scalars_only() {
+        let input = "_naay_version: \"1.0\"\nmakefile: |\n  all:\n  \tcc -o app main.c\n";
+        let parsed = parse_naay(input).expect("parse should succeed");
+        let YamlValue::Map(root) = &parsed else {
+            panic!("root should be a map");
+        };
+        assert_eq!(
+            root["makefile"].value,
+            YamlValue::Str("all:\n\tcc -o app main.c".to_string())
+        );
+        let dumped = dump_naay(&parsed).expect("dump should succeed");
+        let reparsed = parse_naay(&dumped).expect("reparse should succeed");
+        let YamlValue::Map(again) = &reparsed else {
+            panic!("root should be a map");
+        };
+        assert_eq!(again["makefile"].value, root["makefile"].value);
+
+        let err = parse_naay("_naay_version: \"1.0\"\nouter:\n\tinner: x\n")
+            .expect_err("tab indentation should error");
+        let ParseError::Generic { line, column, .. } = err;
+        assert_eq!((line, column), (3, 1));
+        assert!(parse_naay("_naay_version: \"1.0\"\nkey: a\tb\n").is_err());
+    }
+
+
+    #[test]
+    fn emits_header_comment_before_content() {
+        let parsed = parse_naay("_naay_version: \"1.0\"\nname: \"demo\"\n")
+            .expect("parse should succeed");
+        let options = DumpOptions {
+            header_comment: Some("generated by naay-gen\n\ndo not edit".to_string()),
+            ..DumpOptions::default()
+        };
+        let dumped = dump_naay_with(&parsed, &options).expect("dump should succeed");
+        assert!(dumped.starts_with("# generated by naay-gen\n#\n# do not edit\n_naay_version: \"1.0\"\n"));
+
+        let reparsed = parse_naay(&dumped).expect("reparse should succeed");
+        let YamlValue::Map(root) = &reparsed else {
+            panic!("root should be a map");
+        };
+        assert_eq!(root["_naay_version"].leading_comments.len(), 3);
+        assert_eq!(root["name"].value, YamlValue::Str("demo".to_string()));
+    }
+
+
+    #[test]
+    fn plain_and_minimal_quote_styles_only_quote_hazards() {
+        let input = r#"_naay_version: "1.0"
+alignment: "TRUE NEUTRAL"
+count: "42"
+flag: "yes"
+empty: ""
+hazard: "a: b"
+dash: "-x"
+comment: "a # b"
+items:
+  - "plain item"
+  - "k: v"
+"#;
+        let parsed = parse_naay(input).expect("parse should succeed");
+
+        let plain = DumpOptions {
+            quote_style: QuoteStyle::Plain,
+            ..DumpOptions::default()
+        };
+        let dumped = dump_naay_with(&parsed, &plain).expect("dump should succeed");
+        assert!(dumped.contains("alignment: TRUE NEUTRAL\n"));
+        assert!(dumped.contains("count: 42\n"));
+        assert!(dumped.contains("flag: yes\n"));
+        assert!(dumped.contains("empty: \"\"\n"));
+        assert!(dumped.contains("hazard: \"a: b\"\n"));
+        assert!(dumped.contains("dash: \"-x\"\n"));
+        assert!(dumped.contains("comment: \"a # b\"\n"));
+        assert!(dumped.contains("- plain item\n"));
+        assert!(dumped.contains("- \"k: v\"\n"));
+        let reparsed = parse_naay(&dumped).expect("reparse should succeed");
+        assert_eq!(
+            dump_naay_with(&reparsed, &plain).expect("dump should succeed"),
+            dumped
+        );
+
+        let minimal = DumpOptions {
+            quote_style: QuoteStyle::Minimal,
+            ..DumpOptions::default()
+        };
+        let dumped = dump_naay_with(&parsed, &minimal).expect("dump should succeed");
+        assert!(dumped.contains("alignment: TRUE NEUTRAL\n"));
+        assert!(dumped.contains("count: \"42\"\n"));
+        assert!(dumped.contains("flag: \"yes\"\n"));
+        assert!(dumped.contains("hazard: \"a: b\"\n"));
+
+        let tabbed = MapBuilder::new()
+            .str("_naay_version", "1.0")
+            .str("tab", "a\tb")
+            .build();
+        for options in [&plain, &minimal] {
+            let dumped = dump_naay_with(&tabbed, options).expect("dump should succeed");
+            assert!(dumped.contains("tab: \"a\tb\"\n"), "{dumped}");
+            let reparsed = parse_naay(&dumped).expect("reparse should succeed");
+            assert!(reparsed.semantic_eq(&tabbed));
+        }
+    }
+
+
+    #[test]
+    fn single_quote_style_keeps_backslashes_literal() {
+        let mut entries = BTreeMap::new();
+        entries.insert(
+            "_naay_version".to_string(),
+            YamlNode::new(YamlValue::Str("1.0".to_string())),
+        );
+        entries.insert(
+            "path".to_string(),
+            YamlNode::new(YamlValue::Str("C:\\Users\\naay\\config".to_string())),
+        );
+        entries.insert(
+            "quote".to_string(),
+            YamlNode::new(YamlValue::Str("it's".to_string())),
+        );
+        entries.insert(
+            "block".to_string(),
+            YamlNode::new(YamlValue::Str("line 'one'\nline two".to_string())),
+        );
+        let value = YamlValue::Map(entries);
+        let options = DumpOptions {
+            quote_style: QuoteStyle::Single,
+            ..DumpOptions::default()
+        };
+        let dumped = dump_naay_with(&value, &options).expect("dump should succeed");
+        assert!(dumped.contains("path: 'C:\\Users\\naay\\config'\n"));
+        assert!(dumped.contains("quote: 'it''s'\n"));
+        assert!(dumped.contains("block: |\n  line 'one'\n  line two\n"));
+
+        let reparsed = parse_naay(&dumped).expect("reparse should succeed");
+        let YamlValue::Map(root) = &reparsed else {
+            panic!("root should be a map");
+        };
+        assert_eq!(root["path"].value, YamlValue::Str("C:\\Users\\naay\\config".to_string()));
+        assert_eq!(root["quote"].value, YamlValue::Str("it's".to_string()));
+    }
+
+
+    #[test]
+    fn dash_spacing_option_widens_sequence_items() {
+        let input = r#"_naay_version: "1.0"
+items:
+  - "a"
+  -   "b"
+  - nested: "c"
+  - |
+    block
+    text
+"#;
+        let parsed = parse_naay(input).expect("parse should succeed");
+        let options = DumpOptions {
+            dash_spacing: 2,
+            ..DumpOptions::default()
+        };
+        let dumped = dump_naay_with(&parsed, &options).expect("dump should succeed");
+        assert!(dumped.contains("  -  \"a\"\n  -  \"b\"\n"));
+        assert!(dumped.contains("  -  |\n"));
+
+        let reparsed = parse_naay(&dumped).expect("reparse should succeed");
+        assert_eq!(
+            dump_naay(&reparsed).expect("dump should succeed"),
+            dump_naay(&parsed).expect("dump should succeed")
+        );
+    }
+
+
+    #[test]
+    fn scalars_keep_their_source_quoting() {
+        let input = r#"_naay_version: "1.0"
+single: 'foo'
+double: "bar"
+plain: baz
+hazard: 'a: b'
+block: |
+  only line
+items:
+  - 'it''s'
+  - qux
+"#;
+        let parsed = parse_naay(input).expect("parse should succeed");
+        let YamlValue::Map(root) = &parsed else {
+            panic!("root should be a map");
+        };
+        assert_eq!(root["single"].quote, QuoteHint::Single);
+        assert_eq!(root["double"].quote, QuoteHint::Double);
+        assert_eq!(root["plain"].quote, QuoteHint::Plain);
+        assert_eq!(root["block"].quote, QuoteHint::Literal);
+
+        let dumped = dump_naay(&parsed).expect("dump should succeed");
+        assert!(dumped.contains("single: 'foo'\n"));
+        assert!(dumped.contains("double: \"bar\"\n"));
+        assert!(dumped.contains("plain: baz\n"));
+        assert!(dumped.contains("hazard: 'a: b'\n"));
+        assert!(dumped.contains("block: |\n  only line\n"));
+        assert!(dumped.contains("- 'it''s'\n"));
+        assert!(dumped.contains("- qux\n"));
+
+        let options = DumpOptions {
+            quote_style: QuoteStyle::Plain,
+            ..DumpOptions::default()
+        };
+        let overridden = dump_naay_with(&parsed, &options).expect("dump should succeed");
+        assert!(overridden.contains("single: foo\n"));
+    }
+
+
+    #[test]
+    fn line_width_folds_long_plain_scalars() {
+        let sentence = "the quick brown fox jumps over the lazy dog while a supercalifragilisticexpialidociousness-sized word stays whole";
+        let mut entries = BTreeMap::new();
+        entries.insert(
+            "_naay_version".to_string(),
+            YamlNode::new(YamlValue::Str("1.0".to_string())),
+        );
+        entries.insert(
+            "text".to_string(),
+            YamlNode::new(YamlValue::Str(sentence.to_string())),
+        );
+        let value = YamlValue::Map(entries);
+        let options = DumpOptions {
+            quote_style: QuoteStyle::Plain,
+            line_width: Some(40),
+            ..DumpOptions::default()
+        };
+        let dumped = dump_naay_with(&value, &options).expect("dump should succeed");
+        assert!(dumped.contains("text: >\n  the quick brown fox jumps over the\n  lazy dog"));
+        assert!(dumped.contains("\n  supercalifragilisticexpialidociousness-sized\n"));
+        for line in dumped.lines() {
+            assert!(line.len() <= 40 || !line.trim().contains(' '), "too long: {line}");
+        }
+
+        let reparsed = parse_naay(&dumped).expect("reparse should succeed");
+        let YamlValue::Map(root) = &reparsed else {
+            panic!("root should be a map");
+        };
+        assert_eq!(root["text"].value, YamlValue::Str(sentence.to_string()));
+        assert_eq!(root["text"].quote, QuoteHint::Folded);
+        assert!(dump_naay(&reparsed)
+            .expect("dump should succeed")
+            .contains("text: >\n  the quick brown fox"));
+    }
+
+
+    #[test]
+    fn max_scalar_len_rejects_long_scalars() {
+        let options = ParseOptions {
+            max_scalar_len: Some(16),
+            ..ParseOptions::default()
+        };
+        let input = r#"_naay_version: "1.0"
+short: "fits"
+block: |
+  first line
+  second line
+"#;
+        match parse_naay_with(input, &options) {
+            Err(ParseError::Generic { line, column, message }) => {
+                assert_eq!((line, column), (4, 3));
+                assert!(message.contains("maximum length of 16"));
+            }
+            other => panic!("expected length error, got {other:?}"),
+        }
+
+        let inline = "_naay_version: \"1.0\"\nitems:\n  - \"seventeen chars!!\"\n";
+        match parse_naay_with(inline, &options) {
+            Err(ParseError::Generic { line, column, .. }) => assert_eq!((line, column), (3, 5)),
+            other => panic!("expected length error, got {other:?}"),
+        }
+
+        let relaxed = ParseOptions::default();
+        assert!(parse_naay_with(input, &relaxed).is_ok());
+    }
+
+
+    #[test]
+    fn tab_policies_differ_only_outside_indentation() {
+        let quoted = "_naay_version: \"1.0\"\nmsg: \"a\tb\"\nalt: 'c\td'\n";
+        let indented = "_naay_version: \"1.0\"\nouter:\n\tinner: \"x\"\n";
+        let block = "_naay_version: \"1.0\"\nscript: |\n  all:\n  \tcc main.c\n";
+        let with = |policy| ParseOptions {
+            tab_policy: policy,
+            ..ParseOptions::default()
+        };
+        let position = |result: Result<YamlValue, ParseError>| match result {
+            Err(ParseError::Generic { line, column, .. }) => (line, column),
+            Ok(_) => panic!("expected a tab error"),
+        };
+
+        let allow = with(TabPolicy::AllowInScalars);
+        let parsed = parse_naay_with(quoted, &allow).expect("quoted tabs are allowed");
+        let YamlValue::Map(root) = &parsed else {
+            panic!("root should be a map");
+        };
+        assert_eq!(root["msg"].value, YamlValue::Str("a\tb".to_string()));
+        assert_eq!(root["alt"].value, YamlValue::Str("c\td".to_string()));
+        assert!(parse_naay_with(block, &allow).is_ok());
+        assert_eq!(position(parse_naay_with(indented, &allow)), (3, 1));
+
+        let reject = with(TabPolicy::Reject);
+        assert_eq!(position(parse_naay_with(quoted, &reject)), (2, 8));
+        assert_eq!(position(parse_naay_with(block, &reject)), (4, 3));
+        assert_eq!(position(parse_naay_with(indented, &reject)), (3, 1));
+
+        let convert = with(TabPolicy::ConvertToSpaces(2));
+        let parsed = parse_naay_with(quoted, &convert).expect("tabs become spaces");
+        let YamlValue::Map(root) = &parsed else {
+            panic!("root should be a map");
+        };
+        assert_eq!(root["msg"].value, YamlValue::Str("a  b".to_string()));
+        assert_eq!(position(parse_naay_with(indented, &convert)), (3, 1));
+        assert_eq!(position(parse_naay_with(block, &convert)), (4, 3));
+    }
+
+
+    #[test]
+    fn mapping_errors_point_at_the_offending_column() {
+        let position = |input: &str| match parse_naay(input) {
+            Err(ParseError::Generic { line, column, .. }) => (line, column),
+            Ok(_) => panic!("expected an error for {input:?}"),
+        };
+        assert_eq!(position("_naay_version: \"1.0\"\nouter:\n  no colon here\n"), (3, 15));
+        assert_eq!(position("_naay_version: \"1.0\"\nouter:\n  ref: *missing\n"), (3, 8));
+        assert_eq!(position("_naay_version: \"1.0\"\nouter:\n  <<: *missing\n"), (3, 5));
+        assert_eq!(position("_naay_version: \"1.0\"\nouter:\n  key: &lonely\n"), (3, 6));
+        assert_eq!(position("_naay_version: \"1.0\"\nitems:\n  - *missing\n"), (3, 5));
+        assert_eq!(position("_naay_version: \"1.0\"\nitems:\n  - a: 1\n  - a: *nope\n"), (4, 8));
+        assert_eq!(position("_naay_version: \"1.0\"\nitems:\n  - a: *nope # why\n"), (3, 8));
+    }
+
+
+    #[test]
+    fn sequence_item_anchor_is_usable_from_a_map_value() {
+        let input = r#"_naay_version: "1.0"
+items:
+  - &item {x: 1}
+  - "plain"
+ref: *item
+"#;
+        let parsed = parse_naay(input).expect("parse should succeed");
+        let YamlValue::Map(root) = &parsed else {
+            panic!("root should be a map");
+        };
+        let YamlValue::Seq(items) = &root["items"].value else {
+            panic!("items should be a sequence");
+        };
+        let YamlValue::Map(item) = &items[0].value else {
+            panic!("anchored item should be a map");
+        };
+        assert_eq!(item["x"].value, YamlValue::Str("1".to_string()));
+        assert_eq!(root["ref"].value, items[0].value);
+    }
+
+
+    #[test]
+    fn warns_when_local_keys_shadow_merged_ones() {
+        let input = r#"_naay_version: "1.0"
+base: &base
+  host: "localhost"
+  port: "80"
+before:
+  <<: *base
+  port: "8080"
+after:
+  host: "example.com"
+  <<: *base
+"#;
+        let options = ParseOptions {
+            warn_shadowed_merges: true,
+            ..ParseOptions::default()
+        };
+        let (value, warnings) =
+            parse_naay_with_warnings(input, &options).expect("parse should succeed");
+        let YamlValue::Map(root) = &value else {
+            panic!("root should be a map");
+        };
+        let YamlValue::Map(before) = &root["before"].value else {
+            panic!("before should be a map");
+        };
+        assert_eq!(before["port"].value, YamlValue::Str("8080".to_string()));
+        assert_eq!(warnings.len(), 2);
+        assert_eq!((warnings[0].line, warnings[0].column), (7, 3));
+        assert!(warnings[0].message.contains("'port'"));
+        assert_eq!((warnings[1].line, warnings[1].column), (10, 5));
+        assert!(warnings[1].message.contains("'host'"));
+
+        let (_, quiet) = parse_naay_with_warnings(input, &ParseOptions::default())
+            .expect("parse should succeed");
+        assert!(quiet.is_empty());
+    }
+
+
+    #[test]
+    fn render_points_a_caret_at_the_error_column() {
+        let source = "_naay_version: \"1.0\"\nouter:\n  ref: *missing\n";
+        let err = parse_naay(source).expect_err("unknown anchor should error");
+        assert_eq!(
+            err.render(source),
+            "error: unknown anchor: missing\n --> 3:8\n  |\n3 |   ref: *missing\n  |        ^\n"
+        );
+
+        let far = ParseError::Generic {
+            line: 42,
+            column: 1,
+            message: "boom".to_string(),
+        };
+        assert_eq!(far.render(source), "error: boom\n --> 42:1\n");
+    }
+
+
+    #[test]
+    fn empty_input_is_its_own_error() {
+        for input in ["", "  \n\n   \n"] {
+            match parse_naay(input) {
+                Err(ParseError::Generic { line, column, message }) => {
+                    assert_eq!((line, column), (1, 1));
+                    assert_eq!(message, "empty document; expected a mapping with _naay_version");
+                }
+                other => panic!("expected empty-document error, got {other:?}"),
+            }
+        }
+    }
+
+
+    #[test]
+    fn inline_to_leading_moves_inline_comments_above_entries() {
+        let input = r#"_naay_version: "1.0"
+server: # listener settings
+  port: "80" # default port
+items:
+  - "a" # first
+"#;
+        let parsed = parse_naay(input).expect("parse should succeed");
+        let options = DumpOptions {
+            inline_to_leading: true,
+            ..DumpOptions::default()
+        };
+        let dumped = dump_naay_with(&parsed, &options).expect("dump should succeed");
+        assert!(dumped.contains("# listener settings\nserver:\n"));
+        assert!(dumped.contains("  # default port\n  port: \"80\"\n"));
+        assert!(dumped.contains("  # first\n  - \"a\"\n"));
+
+        let reparsed = parse_naay(&dumped).expect("reparse should succeed");
+        let YamlValue::Map(root) = &reparsed else {
+            panic!("root should be a map");
+        };
+        let YamlValue::Map(server) = &root["server"].value else {
+            panic!("server should be a map");
+        };
+        assert_eq!(server["port"].inline_comment, None);
+        assert_eq!(server["port"].leading_comments[0].text, "# default port");
+        assert_eq!(root["server"].leading_comments[0].text, "# listener settings");
+        // A comment above the first item must not hide the sequence.
+        let YamlValue::Seq(items) = &root["items"].value else {
+            panic!("items should stay a sequence");
+        };
+        assert_eq!(items[0].leading_comments[0].text, "# first");
+    }
+
+
+    #[test]
+    fn trailing_comments_stay_below_their_block() {
+        let input = r#"_naay_version: "1.0"
+server:
+  host: "localhost"
+  port: "80"
+  # end of server settings
+items:
+  - "a"
+  # end of items
+zone: "x"
+"#;
+        let parsed = parse_naay(input).expect("parse should succeed");
+        let YamlValue::Map(root) = &parsed else {
+            panic!("root should be a map");
+        };
+        assert_eq!(root["server"].trailing_comments.len(), 1);
+        assert_eq!(root["server"].trailing_comments[0].text, "# end of server settings");
+        assert_eq!(root["items"].trailing_comments[0].text, "# end of items");
+        assert!(root["zone"].leading_comments.is_empty());
+
+        let dumped = dump_naay(&parsed).expect("dump should succeed");
+        assert!(dumped.contains("  port: \"80\"\n  # end of server settings\nitems:"));
+        assert!(dumped.contains("  - \"a\"\n  # end of items\nzone:"));
+        let reparsed = parse_naay(&dumped).expect("reparse should succeed");
+        assert_eq!(dump_naay(&reparsed).expect("dump should succeed"), dumped);
+    }
+
+
+    #[test]
+    fn parses_crlf_and_dumps_either_line_ending() {
+        let input = "_naay_version: \"1.0\"\r\n# note\r\nouter:\r\n  name: plain\r\n  text: |\r\n    one\r\n    two\r\n";
+        let parsed = parse_naay(input).expect("parse should succeed");
+        let YamlValue::Map(root) = &parsed else {
+            panic!("root should be a map");
+        };
+        let YamlValue::Map(outer) = &root["outer"].value else {
+            panic!("outer should be a map");
+        };
+        assert_eq!(outer["name"].value, YamlValue::Str("plain".to_string()));
+        assert_eq!(outer["text"].value, YamlValue::Str("one\ntwo".to_string()));
+        assert_eq!(root["outer"].leading_comments[0].text, "# note");
+
+        let lf = dump_naay(&parsed).expect("dump should succeed");
+        assert!(!lf.contains('\r'));
+        let options = DumpOptions {
+            line_ending: LineEnding::CrLf,
+            ..DumpOptions::default()
+        };
+        let crlf = dump_naay_with(&parsed, &options).expect("dump should succeed");
+        assert_eq!(crlf, lf.replace('\n', "\r\n"));
+        assert_eq!(crlf.matches('\n').count(), crlf.matches("\r\n").count());
+        let reparsed = parse_naay(&crlf).expect("reparse should succeed");
+        assert_eq!(dump_naay(&reparsed).expect("dump should succeed"), lf);
+    }
+
+
+    #[test]
+    fn quote_style_normalizes_every_scalar() {
+        let input = "_naay_version: \"1.0\"\nmixed: 'it''s \"x\"'\n";
+        let parsed = parse_naay(input).expect("parse should succeed");
+        let dump = |quote_style| {
+            let options = DumpOptions {
+                quote_style,
+                ..DumpOptions::default()
+            };
+            dump_naay_with(&parsed, &options).expect("dump should succeed")
+        };
+
+        assert!(dump(QuoteStyle::Double).contains("mixed: \"it's \\\"x\\\"\"\n"));
+        assert!(dump(QuoteStyle::Single).contains("mixed: 'it''s \"x\"'\n"));
+        assert!(dump(QuoteStyle::Plain).contains("mixed: it's \"x\"\n"));
+        assert_eq!(dump(QuoteStyle::Preserve), input);
+        for style in [QuoteStyle::Single, QuoteStyle::Plain, QuoteStyle::Preserve] {
+            let reparsed = parse_naay(&dump(style)).expect("reparse should succeed");
+            assert_eq!(canonicalize(&reparsed), canonicalize(&parsed));
+        }
+    }
+
+
+    #[test]
+    fn strips_leading_byte_order_mark() {
+        let input = "\u{feff}_naay_version: \"1.0\"\nname: \"demo\"\n";
+        let parsed = parse_naay(input).expect("parse should succeed");
+        let YamlValue::Map(root) = &parsed else {
+            panic!("root should be a map");
+        };
+        assert!(root.contains_key("_naay_version"));
+        assert_eq!(root["name"].value, YamlValue::Str("demo".to_string()));
+
+        let err = parse_naay("_naay_version: \"1.0\"\n\u{feff}name: \"demo\"\n")
+            .expect_err("mid-file BOM should error");
+        assert!(err.to_string().contains("line 2, column 1"));
+        assert!(err.to_string().contains("byte order mark"));
+    }
+
+
+    #[test]
+    fn rejects_nesting_beyond_max_depth() {
+        let mut input = String::from("_naay_version: \"1.0\"\n");
+        for level in 0..1000 {
+            input.push_str(&" ".repeat(level * 2));
+            input.push_str("k:\n");
+        }
+        input.push_str(&" ".repeat(2000));
+        input.push_str("leaf: \"x\"\n");
+
+        let options = ParseOptions {
+            max_depth: 256,
+            ..ParseOptions::default()
+        };
+        let err = parse_naay_with(&input, &options).expect_err("deep nesting should error");
+        assert!(err.to_string().contains("maximum nesting depth exceeded"));
+        assert!(err.to_string().contains("line 258, column 513"));
+
+        let parsed = parse_naay(&input).expect("parse should succeed");
+        assert_eq!(inspect::max_depth(&parsed), 1001);
+    }
+
+
+    #[test]
+    fn comment_rewriter_applies_to_every_comment() {
+        let input = r#"_naay_version: "1.0"
+# docs: http://old.example.com/a
+server:
+  hosts: # http://old.example.com/c
+    - "a"
+    # trailing http://old.example.com/d
+  port: "80" # see http://old.example.com/b
+"#;
+        let parsed = parse_naay(input).expect("parse should succeed");
+        let options = DumpOptions {
+            comment_rewriter: Some(Box::new(|c: &str| {
+                c.replace("old.example.com", "new.example.com")
+            })),
+            ..DumpOptions::default()
+        };
+        let dumped = dump_naay_with(&parsed, &options).expect("dump should succeed");
+        assert!(!dumped.contains("old.example.com"));
+        assert_eq!(dumped.matches("http://new.example.com/").count(), 4);
+        assert_eq!(dumped, input.replace("old.example.com", "new.example.com"));
+    }
+
+
+    #[test]
+    fn enforces_node_and_input_limits() {
+        let input = r#"_naay_version: "1.0"
+base: &base
+  a: "1"
+  b: "2"
+list:
+  - "x"
+  - *base
+"#;
+        // Entries are counted as they complete: _naay_version, a, b, base,
+        // then "x", the aliased copy of base and finally list.
+        let with = |max_nodes, max_input_bytes| ParseOptions {
+            max_nodes,
+            max_input_bytes,
+            ..ParseOptions::default()
+        };
+        let err = parse_naay_with(input, &with(4, usize::MAX)).expect_err("limit should trip");
+        assert_eq!(
+            err.to_string(),
+            "parse error at line 6, column 3: document exceeds maximum of 4 nodes"
+        );
+        let err = parse_naay_with(input, &with(6, usize::MAX)).expect_err("limit should trip");
+        assert!(err.to_string().starts_with("parse error at line 7, column 5"));
+        let err = parse_naay_with(input, &with(8, usize::MAX)).expect_err("limit should trip");
+        assert!(err.to_string().starts_with("parse error at line 5, column 1"));
+        assert!(parse_naay_with(input, &with(9, usize::MAX)).is_ok());
+
+        let err = parse_naay_with(input, &with(usize::MAX, 30)).expect_err("limit should trip");
+        assert_eq!(
+            err.to_string(),
+            "parse error at line 2, column 10: input exceeds maximum size of 30 bytes"
+        );
+    }
+
+
+    #[test]
+    fn next_line_values_are_opt_in() {
+        let input = r#"_naay_version: "1.0"
+title:
+value line # note
+nested:
+  inner: "x"
+"#;
+        assert!(parse_naay(input).is_err());
+        let options = ParseOptions {
+            next_line_values: true,
+            ..ParseOptions::default()
+        };
+        let parsed = parse_naay_with(input, &options).expect("parse should succeed");
+        let root = parsed.as_map().expect("root should be a map");
+        assert_eq!(root["title"].value, YamlValue::Str("value line".to_string()));
+        assert_eq!(root["title"].inline_comment.as_deref(), Some("# note"));
+        let nested = root["nested"].value.as_map().expect("nested should be a map");
+        assert_eq!(nested["inner"].value, YamlValue::Str("x".to_string()));
+    }
+
+
+    #[test]
+    fn anchor_report_counts_alias_uses() {
+        let input = r#"_naay_version: "1.0"
+defaults: &defaults
+  retries: "3"
+first:
+  <<: *defaults
+second: *defaults
+items:
+  - &item "x"
+"#;
+        let report = anchor_report(input).expect("parse should succeed");
+        assert_eq!(
+            report,
+            vec![
+                AnchorInfo {
+                    name: "defaults".to_string(),
+                    line: 2,
+                    column: 1,
+                    uses: 2,
+                },
+                AnchorInfo {
+                    name: "item".to_string(),
+                    line: 8,
+                    column: 5,
+                    uses: 0,
+                },
+            ]
+        );
+    }
+
+
+    #[test]
+    fn sort_keys_orders_output_regardless_of_source() {
+        let input = r#"_naay_version: "1.0"
+zeta: "1"
+alpha:
+  tags:
+    - "b"
+    - "a"
+  name: "x"
+"#;
+        let parsed = parse_naay(input).expect("parse should succeed");
+        assert_eq!(dump_naay(&parsed).expect("dump should succeed"), input);
+
+        let options = DumpOptions {
+            sort_keys: true,
+            sort_scalar_seqs: true,
+            ..DumpOptions::default()
+        };
+        let sorted = dump_naay_with(&parsed, &options).expect("dump should succeed");
+        assert_eq!(
+            sorted,
+            r#"_naay_version: "1.0"
+alpha:
+  name: "x"
+  tags:
+    - "a"
+    - "b"
+zeta: "1"
+"#
+        );
+    }
+
+
+    #[test]
+    fn emit_toc_lists_top_level_keys() {
+        let input = r#"_naay_version: "1.0"
+server:
+  port: "80"
+logging: "info"
+"#;
+        let parsed = parse_naay(input).expect("parse should succeed");
+        let options = DumpOptions {
+            emit_toc: true,
+            ..DumpOptions::default()
+        };
+        let dumped = dump_naay_with(&parsed, &options).expect("dump should succeed");
+        assert_eq!(
+            dumped,
+            r#"_naay_version: "1.0"
+# Contents:
+#   server
+#   logging
+server:
+  port: "80"
+logging: "info"
+"#
+        );
+        let reparsed = parse_naay(&dumped).expect("reparse should succeed");
+        assert!(reparsed.semantic_eq(&parsed));
+    }
+
+
+    #[test]
+    fn comment_groups_split_on_blank_lines() {
+        let input = r#"_naay_version: "1.0"
+# Database
+# ========
+
+# Connection string for the primary.
+url: "db://primary"
+"#;
+        let options = ParseOptions {
+            comment_groups: true,
+            ..ParseOptions::default()
+        };
+        let parsed = parse_naay_with(input, &options).expect("parse should succeed");
+        let root = parsed.as_map().expect("root should be a map");
+        let groups = root["url"].comment_groups();
+        assert_eq!(groups.len(), 2);
+        assert_eq!(groups[0].len(), 2);
+        assert_eq!(groups[0][0].text, "# Database");
+        assert_eq!(groups[1][0].text, "# Connection string for the primary.");
+        assert_eq!(dump_naay(&parsed).expect("dump should succeed"), input);
+
+        let flat = parse_naay(input).expect("parse should succeed");
+        let root = flat.as_map().expect("root should be a map");
+        assert_eq!(root["url"].comment_groups().len(), 1);
+        assert_eq!(root["url"].leading_comments.len(), 3);
+    }
+
+
+    #[test]
+    fn key_with_only_nested_comments_is_null() {
+        let input = r#"_naay_version: "1.0"
+key:
+  # only a comment
+next: "x"
+"#;
+        let parsed = parse_naay(input).expect("parse should succeed");
+        let root = parsed.as_map().expect("root should be a map");
+        assert_eq!(root["key"].value, YamlValue::Null);
+        assert_eq!(root["next"].leading_comments[0].text, "# only a comment");
+        assert_eq!(root["next"].leading_comments[0].indent, 2);
+
+        let dumped = dump_naay(&parsed).expect("dump should succeed");
+        assert!(dumped.contains("key:\n  # only a comment\nnext:"));
+        let reparsed = parse_naay(&dumped).expect("reparse should succeed");
+        assert_eq!(dump_naay(&reparsed).expect("dump should succeed"), dumped);
+    }
+
+
+    #[test]
+    fn parse_naay_versioned_returns_the_version() {
+        let input = "_naay_version: \" 1.0 \"\nname: \"demo\"\n";
+        let (version, value) = parse_naay_versioned(input).expect("parse should succeed");
+        assert_eq!(version, "1.0");
+        assert_eq!(value, parse_naay(input).expect("parse should succeed"));
+        assert!(parse_naay_versioned("_naay_version: \"2.0\"\n").is_err());
+    }
+
+
+    #[test]
+    fn alias_keys_resolve_to_anchored_scalars() {
+        let input = r#"_naay_version: "1.0"
+names:
+  - &primary "web"
+*primary: "10.0.0.1"
+"#;
+        let literal = parse_naay(input).expect("parse should succeed");
+        assert!(literal.as_map().expect("root should be a map").contains_key("*primary"));
+
+        let options = ParseOptions {
+            alias_keys: true,
+            ..ParseOptions::default()
+        };
+        let parsed = parse_naay_with(input, &options).expect("parse should succeed");
+        let root = parsed.as_map().expect("root should be a map");
+        assert_eq!(root["web"].value, YamlValue::Str("10.0.0.1".to_string()));
+        assert!(!root.contains_key("*primary"));
+
+        let err = parse_naay_with("_naay_version: \"1.0\"\nmap: &m\n  a: \"b\"\n*m: \"x\"\n", &options)
+            .expect_err("map alias as key should error");
+        assert!(err.to_string().contains("line 4, column 1"));
+        assert!(err.to_string().contains("must name a scalar"));
+    }
+
+
+    #[test]
+    fn version_first_keeps_naay_version_on_line_one() {
+        let input = "Zeta: \"z\"\n_naay_version: \"1.0\"\nalpha: \"a\"\n";
+        let value = parse_naay(input).expect("parse should succeed");
+        for sort_keys in [false, true] {
+            let options = DumpOptions {
+                sort_keys,
+                ..DumpOptions::default()
+            };
+            let dumped = dump_naay_with(&value, &options).expect("dump should succeed");
+            assert_eq!(dumped.lines().next(), Some("_naay_version: \"1.0\""));
+        }
+
+        let options = DumpOptions {
+            sort_keys: true,
+            version_first: false,
+            ..DumpOptions::default()
+        };
+        let dumped = dump_naay_with(&value, &options).expect("dump should succeed");
+        assert_eq!(dumped.lines().next(), Some("Zeta: \"z\""));
+    }
+
+    #[test]
+    fn double_quoted_scalars_decode_escapes() {
+        let input = "_naay_version: \"1.0\"\n\"k\\\"ey\": \"a\\\"b\\\\c\\td\\u00e9\"\n";
+        let options = ParseOptions {
+            decode_escapes: true,
+            ..ParseOptions::default()
+        };
+        let value = parse_naay_with(input, &options).expect("parse should succeed");
+        let YamlValue::Map(map) = &value else {
+            panic!("root should be a map");
+        };
+        assert_eq!(map["k\"ey"].value, YamlValue::Str("a\"b\\c\td\u{e9}".to_string()));
+        let dumped = dump_naay(&value).expect("dump should succeed");
+        let reparsed = parse_naay_with(&dumped, &options).expect("reparse should succeed");
+        assert!(reparsed.semantic_eq(&value));
+    }
+
+    #[test]
+    fn double_quoted_scalars_are_verbatim_by_default() {
+        let input = "_naay_version: \"1.0\"\npath: \"C:\\Users\\me\"\n";
+        let value = parse_naay(input).expect("parse should succeed");
+        let YamlValue::Map(map) = &value else {
+            panic!("root should be a map");
+        };
+        assert_eq!(map["path"].value, YamlValue::Str("C:\\Users\\me".to_string()));
+    }
+
+    #[test]
+    fn unknown_escape_policies() {
+        let input = "_naay_version: \"1.0\"\nvalue: \"a\\qb\"\n";
+        let decode = ParseOptions {
+            decode_escapes: true,
+            ..ParseOptions::default()
+        };
+        let err = parse_naay_with(input, &decode).expect_err("unknown escape should error");
+        assert_eq!(
+            err.to_string(),
+            "parse error at line 2, column 10: unknown escape sequence '\\q'"
+        );
+
+        for (policy, expected) in [(UnknownEscape::Literal, "a\\qb"), (UnknownEscape::Strip, "aqb")] {
+            let options = ParseOptions {
+                decode_escapes: true,
+                unknown_escape: policy,
+                ..ParseOptions::default()
+            };
+            let value = parse_naay_with(input, &options).expect("parse should succeed");
+            let YamlValue::Map(map) = &value else {
+                panic!("root should be a map");
+            };
+            assert_eq!(map["value"].value, YamlValue::Str(expected.to_string()));
+        }
+    }
+
+
+    #[test]
+    fn empty_values_parse_as_null_and_round_trip() {
+        let input = r#"_naay_version: "1.0"
+empty:
+blank: ""
+items:
+  -
+  - ""
+"#;
+        let value = parse_naay(input).expect("parse should succeed");
+        let YamlValue::Map(map) = &value else {
+            panic!("root should be a map");
+        };
+        assert_eq!(map["empty"].value, YamlValue::Null);
+        assert_eq!(map["blank"].value, YamlValue::Str(String::new()));
+        let items = map["items"].value.as_seq().expect("items should be a seq");
+        assert!(items[0].value.is_null());
+        assert_eq!(items[1].value, YamlValue::Str(String::new()));
+
+        let dumped = dump_naay(&value).expect("dump should succeed");
+        assert_eq!(dumped, input);
+        assert_eq!(parse_naay(&dumped).expect("reparse should succeed"), value);
+    }
+
+
+    #[test]
+    fn annotate_changes_marks_only_set_values() {
+        let input = r#"_naay_version: "1.0"
+server:
+  host: "localhost"
+  port: "80" # default
+name: "demo"
+"#;
+        let mut value = parse_naay(input).expect("parse should succeed");
+        let YamlValue::Map(root) = &mut value else {
+            panic!("root should be a map");
+        };
+        let YamlValue::Map(server) = &mut root.get_mut("server").expect("server exists").value else {
+            panic!("server should be a map");
+        };
+        let port = server.get_mut("port").expect("port exists");
+        let old = port.set_value(YamlValue::Str("8080".to_string()));
+        assert_eq!(old, YamlValue::Str("80".to_string()));
+
+        let options = DumpOptions {
+            annotate_changes: true,
+            ..DumpOptions::default()
+        };
+        let dumped = dump_naay_with(&value, &options).expect("dump should succeed");
+        assert_eq!(
+            dumped,
+            "_naay_version: \"1.0\"\nserver:\n  host: \"localhost\"\n  port: \"8080\" # default # changed\nname: \"demo\"\n"
+        );
+        assert!(!dump_naay(&value).expect("dump should succeed").contains("# changed"));
+    }
+
+
+    #[test]
+    fn inline_map_entries_keep_their_comments() {
+        let input = r#"_naay_version: "1.0"
+items:
+  - key: "val" # note
+    other: "x" # two
+"#;
+        let parsed = parse_naay(input).expect("parse should succeed");
+        let items = parsed.as_map().expect("root should be a map")["items"]
+            .value
+            .as_seq()
+            .expect("items should be a seq");
+        assert_eq!(items[0].inline_comment, None);
+        let entry = &items[0].value.as_map().expect("item should be a map")["key"];
+        assert_eq!(entry.inline_comment.as_deref(), Some("# note"));
+
+        let dumped = dump_naay(&parsed).expect("dump should succeed");
+        assert!(dumped.contains("    key: \"val\" # note\n    other: \"x\" # two\n"));
+        let reparsed = parse_naay(&dumped).expect("reparse should succeed");
+        assert_eq!(dump_naay(&reparsed).expect("dump should succeed"), dumped);
+    }
+
+
+    #[test]
+    fn collection_openers_keep_inline_comments() {
+        let input = r#"_naay_version: "1.0"
+items: [] # none yet
+empty: {} # nothing
+deploy: # production
+  host: "a"
+list: # hosts
+  - "a"
+  - [] # empty item
+  - # nested
+    x: "y"
+  - # deeper
+    - "z"
+"#;
+        let parsed = parse_naay(input).expect("parse should succeed");
+        let root = parsed.as_map().expect("root should be a map");
+        assert_eq!(root["items"].inline_comment.as_deref(), Some("# none yet"));
+        assert_eq!(root["deploy"].inline_comment.as_deref(), Some("# production"));
+        assert_eq!(dump_naay(&parsed).expect("dump should succeed"), input);
+    }
+
+
+    #[test]
+    fn from_str_and_display_round_trip() {
+        let input = "_naay_version: \"1.0\"\nname: \"demo\" # label\n";
+        let value: YamlValue = input.parse().expect("parse should succeed");
+        assert_eq!(value, parse_naay(input).expect("parse should succeed"));
+        assert_eq!(value.to_string(), input);
+        assert_eq!(format!("{value}"), dump_naay(&value).expect("dump should succeed"));
+
+        let err = "name: \"demo\"\n".parse::<YamlValue>().expect_err("version is required");
+        assert!(err.to_string().contains("_naay_version"));
+    }
+
+
+    #[test]
+    fn parse_naay_with_anchors_returns_the_table() {
+        let input = r#"_naay_version: "1.0"
+base: &base
+  host: "localhost"
+names:
+  - &name "demo"
+copy: *base
+"#;
+        let (value, anchors) = parse_naay_with_anchors(input).expect("parse should succeed");
+        assert_eq!(value, parse_naay(input).expect("parse should succeed"));
+        assert_eq!(anchors.len(), 2);
+        assert_eq!(anchors["name"], YamlValue::Str("demo".to_string()));
+        let base = anchors["base"].as_map().expect("base should be a map");
+        assert_eq!(base["host"].value, YamlValue::Str("localhost".to_string()));
+    }
+
+
+    #[test]
+    fn group_blank_lines_separates_groups() {
+        let input = r#"_naay_version: "1.0"
+db_host: "localhost"
+db_port: "5432"
+web_port: "80"
+web_root: "/srv"
+"#;
+        let value = parse_naay(input).expect("parse should succeed");
+        let options = DumpOptions {
+            group_blank_lines: Some(Box::new(|path: &str| {
+                let key = path.trim_start_matches('/');
+                key.split_once('_')
+                    .filter(|_| key != "_naay_version")
+                    .map(|(prefix, _)| format!("{prefix} settings"))
+            })),
+            ..DumpOptions::default()
+        };
+        let dumped = dump_naay_with(&value, &options).expect("dump should succeed");
+        assert_eq!(
+            dumped,
+            "_naay_version: \"1.0\"\n\n# db settings\ndb_host: \"localhost\"\ndb_port: \"5432\"\n\n# web settings\nweb_port: \"80\"\nweb_root: \"/srv\"\n"
+        );
+        assert!(parse_naay(&dumped).expect("reparse should succeed").semantic_eq(&value));
+    }
+
+
+    #[test]
+    fn unused_anchors_lists_unreferenced_definitions() {
+        let input = r#"_naay_version: "1.0"
+base: &base
+  host: "localhost"
+spare: &spare
+  host: "backup"
+server:
+  <<: *base
+  port: "80"
+"#;
+        let unused = unused_anchors(input).expect("parse should succeed");
+        assert_eq!(unused, [("spare".to_string(), Span { line: 4, column: 1 })]);
+    }
+
+
+    #[test]
+    fn bare_dash_items_nest_block_sequences() {
+        let input = "_naay_version: \"1.0\"\nlist:\n  - \n    - \n      - a\n      - b\n    - c\n  -\n      - d\n";
+        let value = parse_naay(input).expect("parse should succeed");
+        let list = value.as_map().expect("root should be a map")["list"]
+            .value
+            .as_seq()
+            .expect("list should be a seq");
+        let scalars = |node: &YamlNode| -> Vec<YamlValue> {
+            node.value.as_seq().expect("item should be a seq").iter().map(|n| n.value.clone()).collect()
+        };
+        assert_eq!(list.len(), 2);
+        let first = list[0].value.as_seq().expect("item should be a seq");
+        assert_eq!(
+            scalars(&first[0]),
+            [YamlValue::Str("a".to_string()), YamlValue::Str("b".to_string())]
+        );
+        assert_eq!(first[1].value, YamlValue::Str("c".to_string()));
+        assert_eq!(scalars(&list[1]), [YamlValue::Str("d".to_string())]);
+
+        let dumped = dump_naay(&value).expect("dump should succeed");
+        assert!(parse_naay(&dumped).expect("reparse should succeed").semantic_eq(&value));
+    }
+
+
+    #[test]
+    fn semicolon_comment_prefix_round_trips() {
+        let input = r#"; generated file
+_naay_version: "1.0"
+name: demo ; the label
+url: "http://x#y"
+path: a;b
+"#;
+        let parse_options = ParseOptions {
+            comment_prefixes: vec![CommentPrefix::Semicolon],
+            ..ParseOptions::default()
+        };
+        let value = parse_naay_with(input, &parse_options).expect("parse should succeed");
+        let root = value.as_map().expect("root should be a map");
+        assert_eq!(root["name"].value, YamlValue::Str("demo".to_string()));
+        assert_eq!(root["name"].inline_comment.as_deref(), Some("; the label"));
+        assert_eq!(root["_naay_version"].leading_comments[0].text, "; generated file");
+        assert_eq!(root["path"].value, YamlValue::Str("a;b".to_string()));
+
+        let dump_options = DumpOptions {
+            comment_prefix: CommentPrefix::Semicolon,
+            header_comment: Some("do not edit".to_string()),
+            quote_style: QuoteStyle::Plain,
+            ..DumpOptions::default()
+        };
+        let dumped = dump_naay_with(&value, &dump_options).expect("dump should succeed");
+        assert!(dumped.starts_with("; do not edit\n; generated file\n_naay_version: 1.0\n"));
+        assert!(dumped.contains("name: demo ; the label\n"));
+        let reparsed = parse_naay_with(&dumped, &parse_options).expect("reparse should succeed");
+        assert!(reparsed.semantic_eq(&value));
+        assert!(parse_naay(input).is_err());
+    }
+
+
+    #[test]
+    fn compact_nested_sequences_on_one_line() {
+        let input = r#"_naay_version: "1.0"
+deep:
+  - - - "deep" # note
+  - - "a"
+    - "b"
+  - "c"
+"#;
+        let value = parse_naay(input).expect("parse should succeed");
+        let deep = value.as_map().expect("root should be a map")["deep"]
+            .value
+            .as_seq()
+            .expect("deep should be a seq");
+        assert_eq!(deep.len(), 3);
+        let level2 = deep[0].value.as_seq().expect("item should be a seq");
+        let level3 = level2[0].value.as_seq().expect("item should be a seq");
+        assert_eq!(level3[0].value, YamlValue::Str("deep".to_string()));
+        assert_eq!(level3[0].inline_comment.as_deref(), Some("# note"));
+        let pair = deep[1].value.as_seq().expect("item should be a seq");
+        assert_eq!(pair.len(), 2);
+        assert_eq!(pair[1].value, YamlValue::Str("b".to_string()));
+        assert_eq!(deep[2].value, YamlValue::Str("c".to_string()));
+
+        let dumped = dump_naay(&value).expect("dump should succeed");
+        assert!(parse_naay(&dumped).expect("reparse should succeed").semantic_eq(&value));
+    }
+
+
+    #[test]
+    fn misaligned_sibling_is_an_indentation_error() {
+        let deeper = "_naay_version: \"1.0\"\nm:\n  a: \"1\"\n   b: \"2\"\n";
+        let err = parse_naay(deeper).expect_err("misaligned key should error");
+        assert_eq!(
+            err.to_string(),
+            "parse error at line 4, column 4: inconsistent indentation"
+        );
+
+        let partial_dedent = "_naay_version: \"1.0\"\nm:\n    a: \"1\"\n  b: \"2\"\nc: \"3\"\n";
+        let err = parse_naay(partial_dedent).expect_err("partial dedent should error");
+        assert_eq!(
+            err.to_string(),
+            "parse error at line 4, column 3: inconsistent indentation"
+        );
+
+        let seq = "_naay_version: \"1.0\"\nlist:\n  - \"a\"\n   - \"b\"\n";
+        assert!(parse_naay(seq).is_err());
+    }
+
+
+    #[test]
+    fn hazardous_keys_are_quoted_and_round_trip() {
+        let keys = ["<<", "-dash", "*star", "&amp", "!bang", "[open", "{brace", "\"q\"", "'s'", "", "a:b"];
+        let mut builder = MapBuilder::new().str("_naay_version", "1.0");
+        for (i, key) in keys.iter().enumerate() {
+            builder = builder.str(*key, i.to_string());
+        }
+        let value = builder.build();
+        let dumped = dump_naay(&value).expect("dump should succeed");
+        assert!(dumped.contains("\"<<\": \"0\"\n"));
+        let reparsed = parse_naay(&dumped).expect("reparse should succeed");
+        assert_eq!(first_diff(&value, &reparsed), None, "dumped:\n{dumped}");
+
+        let merge = "_naay_version: \"1.0\"\nitems:\n  - \"<<\": \"x\"\n";
+        let parsed = parse_naay(merge).expect("quoted << in an inline map is a key");
+        let item = &parsed.as_map().expect("root should be a map")["items"].value.as_seq().expect("seq")[0];
+        assert_eq!(item.value.as_map().expect("map")["<<"].value, YamlValue::Str("x".to_string()));
+
+        let colons = "_naay_version: \"1.0\"\nitems:\n  - \"a:b\": \"x\"\n    'c:d': \"y\"\nflow: {\"e:f\": g}\n";
+        let parsed = parse_naay(colons).expect("colons inside quoted keys are part of the key");
+        let root = parsed.as_map().expect("root should be a map");
+        let item = root["items"].value.as_seq().expect("seq")[0].value.as_map().expect("map");
+        assert_eq!(item["a:b"].value, YamlValue::Str("x".to_string()));
+        assert_eq!(item["c:d"].value, YamlValue::Str("y".to_string()));
+        assert_eq!(root["flow"].value.as_map().expect("map")["e:f"].value, YamlValue::Str("g".to_string()));
+    }
+
+
+    #[test]
+    fn plain_styles_quote_scalars_that_look_like_comments() {
+        for text in ["a # b", "a\t#b", "a#b"] {
+            let value = MapBuilder::new()
+                .str("_naay_version", "1.0")
+                .str("note", text)
+                .build();
+            for quote_style in [QuoteStyle::Minimal, QuoteStyle::Plain] {
+                let options = DumpOptions {
+                    quote_style,
+                    ..DumpOptions::default()
+                };
+                let dumped = dump_naay_with(&value, &options).expect("dump should succeed");
+                let reparsed = parse_naay(&dumped).expect("reparse should succeed");
+                let root = reparsed.as_map().expect("root should be a map");
+                assert_eq!(root["note"].value, YamlValue::Str(text.to_string()), "{dumped}");
+                assert_eq!(root["note"].inline_comment, None);
+            }
+        }
+    }
+
+
+    #[test]
+    fn inline_anchors_in_sequence_inline_map() {
+        let input = r#"_naay_version: "1.0"
+items:
+  - a: &first "one"
+    b: &second "two"
+    c: *first
+use: *second
+"#;
+        let value = parse_naay(input).expect("parse should succeed");
+        let item = value.view().pointer("/items/0").expect("item exists");
+        assert_eq!(item.get("a").and_then(YamlView::as_str), Some("one"));
+        assert_eq!(item.get("b").and_then(YamlView::as_str), Some("two"));
+        assert_eq!(item.get("c").and_then(YamlView::as_str), Some("one"));
+        assert_eq!(value.view().get("use").and_then(YamlView::as_str), Some("two"));
+    }
+
+
+    #[test]
+    fn flow_threshold_writes_small_scalar_collections_inline() {
+        let input = r#"_naay_version: "1.0"
+tags:
+  - a
+  - "b, c"
+limits:
+  cpu: "2"
+servers:
+  - name: web
+  - name: db
+many:
+  - "1"
+  - "2"
+  - "3"
+"#;
+        let value = parse_naay(input).expect("parse should succeed");
+        let options = DumpOptions {
+            flow_threshold: Some(2),
+            ..DumpOptions::default()
+        };
+        let dumped = dump_naay_with(&value, &options).expect("dump should succeed");
+        let expected = r#"_naay_version: "1.0"
+tags: [a, "b, c"]
+limits: {cpu: "2"}
+servers:
+  - {name: web}
+  - {name: db}
+many:
+  - "1"
+  - "2"
+  - "3"
+"#;
+        assert_eq!(dumped, expected);
+        let reparsed = parse_naay(&dumped).expect("flow output should parse");
+        assert_eq!(reparsed.view().pointer("/tags/1").and_then(YamlView::as_str), Some("b, c"));
+        assert_eq!(reparsed.view().pointer("/servers/1/name").and_then(YamlView::as_str), Some("db"));
+        assert_eq!(dump_naay(&reparsed).expect("dump should succeed"), dump_naay(&value).expect("dump should succeed"));
+    }
+
+
+    #[test]
+    fn comments_between_key_and_block_stay_with_the_key() {
+        let input = r#"_naay_version: "1.0"
+deploy:
+# rollout settings
+  # first child note
+  zone: "b"
+  region: "a"
+items:
+ # list note
+  - "x"
+"#;
+        let parsed = parse_naay(input).expect("parse should succeed");
+        let root = parsed.as_map().expect("root should be a map");
+        let deploy = &root["deploy"];
+        assert_eq!(deploy.inner_leading_comments.len(), 1);
+        assert_eq!(deploy.inner_leading_comments[0].text, "# rollout settings");
+        let zone = &deploy.value.as_map().expect("deploy should be a map")["zone"];
+        assert_eq!(zone.leading_comments[0].text, "# first child note");
+        assert_eq!(root["items"].inner_leading_comments[0].indent, 1);
+        assert_eq!(dump_naay(&parsed).expect("dump should succeed"), input);
+
+        // Reordering the block leaves the comment under its key.
+        let options = DumpOptions {
+            sort_keys: true,
+            ..DumpOptions::default()
+        };
+        let sorted = dump_naay_with(&parsed, &options).expect("dump should succeed");
+        assert!(sorted.contains("deploy:\n# rollout settings\n  region: \"a\"\n"));
+    }
+
+
+    #[test]
+    fn parse_naay_reader_matches_parse_naay() {
+        let input = "\u{feff}_naay_version: \"1.0\"\r\n# note\r\nserver:\r\n\r\n  host: localhost\r\n  text: |\r\n    one\r\n\r\n    two\r\nitems:\r\n  - a\r\n  - b";
+        let from_reader =
+            parse_naay_reader(std::io::Cursor::new(input.as_bytes())).expect("reader should parse");
+        assert_eq!(from_reader, parse_naay(input).expect("parse should succeed"));
+
+        let invalid = b"_naay_version: \"1.0\"\nname: \xff\n";
+        match parse_naay_reader(&invalid[..]) {
+            Err(ParseError::Generic { line, message, .. }) => {
+                assert_eq!(line, 2);
+                assert!(message.starts_with("failed to read input"));
+            }
+            other => panic!("expected a read error, got {other:?}"),
+        }
+
+        let tabbed = "_naay_version: \"1.0\"\nname: a\tb\n";
+        let options = ParseOptions {
+            tab_policy: TabPolicy::ConvertToSpaces(2),
+            ..ParseOptions::default()
+        };
+        assert_eq!(
+            parse_naay_reader_with(tabbed.as_bytes(), &options).expect("reader should parse"),
+            parse_naay_with(tabbed, &options).expect("parse should succeed")
+        );
+        let small = ParseOptions {
+            max_input_bytes: 10,
+            ..ParseOptions::default()
+        };
+        assert!(parse_naay_reader_with(tabbed.as_bytes(), &small).is_err());
+    }
+
+    #[test]
+    fn parse_naay_reader_keeps_only_a_window_of_lines() {
+        let mut input = String::from("_naay_version: \"1.0\"\nitems:\n");
+        for i in 0..10_000 {
+            input.push_str(&format!("  - key: {i}\n    # note {i}\n    text: |\n      line {i}\n"));
+        }
+        let options = ParseOptions::default();
+        let lines = ReaderLines {
+            reader: input.as_bytes(),
+            options: &options,
+            buf: String::new(),
+            line_no: 0,
+            total: 0,
+        };
+        let mut machine =
+            ParseMachine::streaming(Box::new(lines), &options).expect("machine should start");
+        let mut most = 0;
+        let value = loop {
+            most = most.max(machine.env.lines.len());
+            if let Some(value) = machine.step_once().expect("step should succeed") {
+                break value;
+            }
+        };
+        assert!(most < 100, "buffered {most} lines");
+        assert_eq!(value, parse_naay(&input).expect("parse should succeed"));
+    }
+
+
+    #[test]
+    fn several_comment_prefixes_keep_their_own_marker() {
+        let input = r#"// legacy note
+_naay_version: "1.0"
+# regular note
+name: demo // the label
+url: http://example.com
+"#;
+        let options = ParseOptions {
+            comment_prefixes: vec![CommentPrefix::Hash, CommentPrefix::DoubleSlash],
+            ..ParseOptions::default()
+        };
+        let value = parse_naay_with(input, &options).expect("parse should succeed");
+        let root = value.as_map().expect("root should be a map");
+        assert_eq!(root["_naay_version"].leading_comments[0].text, "// legacy note");
+        assert_eq!(root["name"].leading_comments[0].text, "# regular note");
+        assert_eq!(root["name"].value, YamlValue::Str("demo".to_string()));
+        assert_eq!(root["name"].inline_comment.as_deref(), Some("// the label"));
+        assert_eq!(root["url"].value, YamlValue::Str("http://example.com".to_string()));
+
+        let dumped = dump_naay(&value).expect("dump should succeed");
+        assert!(dumped.starts_with("// legacy note\n_naay_version:"));
+        assert!(dumped.contains("# regular note\nname: demo // the label\n"));
+        assert!(parse_naay(input).is_err());
+    }
+
+
+    #[test]
+    fn reindent_comments_follows_a_moved_subtree() {
+        let input = r#"_naay_version: "1.0"
+db:
+  # primary
+  host: "a"
+  port: "5432"
+  # end of db
+"#;
+        let mut value = parse_naay(input).expect("parse should succeed");
+        let YamlValue::Map(root) = &mut value else {
+            panic!("root should be a map");
+        };
+        let db = root.remove("db").expect("db exists");
+        let mut services = YamlNode::new(YamlValue::Map(BTreeMap::new()));
+        let YamlValue::Map(inner) = &mut services.value else {
+            unreachable!();
+        };
+        inner.insert("db".to_string(), db);
+        root.insert("services".to_string(), services);
+
+        let kept = dump_naay(&value).expect("dump should succeed");
+        assert!(kept.contains("\n  # primary\n    host:"));
+        let options = DumpOptions {
+            reindent_comments: true,
+            ..DumpOptions::default()
+        };
+        let dumped = dump_naay_with(&value, &options).expect("dump should succeed");
+        let expected = r#"_naay_version: "1.0"
+services:
+  db:
+    # primary
+    host: "a"
+    port: "5432"
+    # end of db
+"#;
+        assert_eq!(dumped, expected);
+    }
+
+
+    #[test]
+    fn strict_scalars_rejects_colon_space_in_plain_values() {
+        let input = "_naay_version: \"1.0\"\nkey: value extra: stuff\n";
+        let lenient = parse_naay(input).expect("parse should succeed");
+        assert_eq!(
+            lenient.view().get("key").and_then(YamlView::as_str),
+            Some("value extra: stuff")
+        );
+
+        let options = ParseOptions {
+            strict_scalars: true,
+            ..ParseOptions::default()
+        };
+        match parse_naay_with(input, &options) {
+            Err(ParseError::Generic { line, column, message }) => {
+                assert_eq!((line, column), (2, 17));
+                assert!(message.contains("unexpected ': '"));
+            }
+            other => panic!("expected strict scalar error, got {other:?}"),
+        }
+        let seq_input = "_naay_version: \"1.0\"\nitems:\n  - - x: y z: w\n";
+        assert!(parse_naay_with(seq_input, &options).is_err());
+
+        let fine = r#"_naay_version: "1.0"
+quoted: "value extra: stuff"
+url: http://example.com
+time: 12:30
+"#;
+        let value = parse_naay_with(fine, &options).expect("quoted and bare colons are fine");
+        assert_eq!(
+            value.view().get("quoted").and_then(YamlView::as_str),
+            Some("value extra: stuff")
+        );
+    }
+
+
+    #[test]
+    fn dump_naay_indented_shifts_every_line() {
+        let input = r#"_naay_version: "1.0"
+server:
+  # listener
+  host: "localhost"
+  text: |
+    one
+
+    two
+  ports:
+    - "80"
+"#;
+        let value = parse_naay(input).expect("parse should succeed");
+        let server = &value.as_map().expect("root should be a map")["server"].value;
+        let dumped = dump_naay_indented(server, 4).expect("dump should succeed");
+        let options = DumpOptions {
+            reindent_comments: true,
+            ..DumpOptions::default()
+        };
+        let plain = dump_naay_with(server, &options).expect("dump should succeed");
+        assert_eq!(dumped.lines().count(), plain.lines().count());
+        for (shifted, line) in dumped.lines().zip(plain.lines()) {
+            match line.is_empty() {
+                true => assert!(shifted.is_empty()),
+                false => assert_eq!(shifted, format!("    {line}")),
+            }
+        }
+        assert!(dumped.starts_with("    # listener\n    host: \"localhost\"\n"));
+    }
+
+
+    #[test]
+    fn tilde_is_null_but_quoted_tilde_and_null_are_strings() {
+        let input = r#"_naay_version: "1.0"
+tilde: ~
+quoted: "~"
+word: null
+items:
+  - ~
+  - "~"
+flow: [~, a]
+"#;
+        let value = parse_naay(input).expect("parse should succeed");
+        let view = value.view();
+        assert!(view.get("tilde").is_some_and(YamlView::is_null));
+        assert_eq!(view.get("quoted").and_then(YamlView::as_str), Some("~"));
+        assert_eq!(view.get("word").and_then(YamlView::as_str), Some("null"));
+        assert!(view.pointer("/items/0").is_some_and(YamlView::is_null));
+        assert_eq!(view.pointer("/items/1").and_then(YamlView::as_str), Some("~"));
+        assert!(view.pointer("/flow/0").is_some_and(YamlView::is_null));
+
+        let dumped = dump_naay(&value).expect("dump should succeed");
+        assert!(dumped.contains("tilde:\n"));
+        assert!(dumped.contains("  -\n  - \"~\"\n"));
+        let options = DumpOptions {
+            null_style: NullStyle::Tilde,
+            quote_style: QuoteStyle::Plain,
+            ..DumpOptions::default()
+        };
+        let tilde = dump_naay_with(&value, &options).expect("dump should succeed");
+        assert!(tilde.contains("tilde: ~\nquoted: \"~\"\n"));
+        assert!(tilde.contains("  - ~\n  - \"~\"\n"));
+        let reparsed = parse_naay(&tilde).expect("reparse should succeed");
+        assert!(reparsed.semantic_eq(&value));
+    }
+
+
+    #[test]
+    fn block_scalar_keeps_relative_indentation_and_blank_lines() {
+        let text = "def main():\n    if ok:\n        run()\n\n    return 0\n  # dedented note";
+        let mut map = BTreeMap::new();
+        map.insert("_naay_version".to_string(), YamlNode::new(YamlValue::Str("1.0".to_string())));
+        map.insert("code".to_string(), YamlNode::new(YamlValue::Str(text.to_string())));
+        let value = YamlValue::Map(map);
+        let dumped = dump_naay(&value).expect("dump should succeed");
+        assert!(dumped.contains("code: |\n  def main():\n      if ok:\n          run()\n\n      return 0\n"));
+        let reparsed = parse_naay(&dumped).expect("reparse should succeed");
+        assert_eq!(reparsed.view().get("code").and_then(YamlView::as_str), Some(text));
+
+        // The least indented line, wherever it is, sets the block's margin.
+        let input = "_naay_version: \"1.0\"\ncode: |\n      deep\n    shallow\n        deeper\n";
+        let parsed = parse_naay(input).expect("parse should succeed");
+        assert_eq!(
+            parsed.view().get("code").and_then(YamlView::as_str),
+            Some("  deep\nshallow\n    deeper")
+        );
+        let folded = "_naay_version: \"1.0\"\ntext: >\n  one\n  two\n\n  three\n";
+        let parsed = parse_naay(folded).expect("parse should succeed");
+        assert_eq!(parsed.view().get("text").and_then(YamlView::as_str), Some("one two\nthree"));
+    }
+
+
+    #[test]
+    fn multi_line_flow_sequence_keeps_interleaved_comments() {
+        let input = r#"_naay_version: "1.0"
+tags: [a, # first
+  # before b
+  b, "c, d",
+  e] # last
+limits: {cpu: "2", # cores
+  mem: 1Gi}
+after: "x"
+title: [draft] notes
+"#;
+        let value = parse_naay(input).expect("parse should succeed");
+        let root = value.as_map().expect("root should be a map");
+        let tags = root["tags"].value.as_seq().expect("tags should be a sequence");
+        let texts: Vec<_> = tags.iter().filter_map(|node| node.value.as_str()).collect();
+        assert_eq!(texts, ["a", "b", "c, d", "e"]);
+        assert_eq!(tags[0].inline_comment.as_deref(), Some("# first"));
+        assert_eq!(tags[1].leading_comments[0].text, "# before b");
+        assert_eq!(tags[3].inline_comment.as_deref(), Some("# last"));
+        let limits = root["limits"].value.as_map().expect("limits should be a map");
+        assert_eq!(limits["cpu"].inline_comment.as_deref(), Some("# cores"));
+        assert_eq!(limits["mem"].value.as_str(), Some("1Gi"));
+        assert_eq!(root["after"].value.as_str(), Some("x"));
+        assert_eq!(root["title"].value.as_str(), Some("[draft] notes"));
+
+        let dumped = dump_naay(&value).expect("dump should succeed");
+        assert!(dumped.contains("  - a # first\n  # before b\n  - b\n"));
+        assert!(parse_naay(&dumped).expect("reparse should succeed").semantic_eq(&value));
+
+        let unterminated = "_naay_version: \"1.0\"\ntags: [a,\n  b\n";
+        match parse_naay(unterminated) {
+            Err(ParseError::Generic { line, message, .. }) => {
+                assert_eq!(line, 2);
+                assert!(message.contains("unterminated flow collection"));
+            }
+            other => panic!("expected unterminated error, got {other:?}"),
+        }
+    }
+
+
+    #[test]
+    fn comments_only_document_is_its_own_error() {
+        let err = parse_naay("# one\n\n  # two\n").unwrap_err();
+        let ParseError::Generic { line, message, .. } = err;
+        assert_eq!(line, 3);
+        assert_eq!(message, "document contains no data, only comments");
+    }
+
+
+    #[test]
+    fn parse_fragment_accepts_any_root_shape() {
+        let seq = parse_fragment("# items\n- a\n- b: c\n").unwrap();
+        let items = seq.as_seq().unwrap();
+        assert_eq!(items.len(), 2);
+        assert_eq!(items[0].value, YamlValue::Str("a".into()));
+        assert!(items[1].value.as_map().unwrap().contains_key("b"));
+
+        let map = parse_fragment("name: demo\n").unwrap();
+        assert!(map.as_map().unwrap().contains_key("name"));
+
+        assert_eq!(
+            parse_fragment("\"a: b\"  # note\n").unwrap(),
+            YamlValue::Str("a: b".into())
+        );
+        assert_eq!(parse_fragment("plain words").unwrap(), YamlValue::Str("plain words".into()));
+        assert_eq!(parse_fragment("~").unwrap(), YamlValue::Null);
+        assert_eq!(parse_fragment("|\n  one\n  two\n").unwrap(), YamlValue::Str("one\ntwo".into()));
+        let flow = parse_fragment("[x, y]").unwrap();
+        assert_eq!(flow.as_seq().unwrap().len(), 2);
+
+        let ParseError::Generic { line, .. } = parse_fragment("one\ntwo\n").unwrap_err();
+        assert_eq!(line, 2);
+        assert!(parse_naay("- a\n").is_err());
+    }
+
+
+    #[test]
+    fn merge_lines_survive_a_round_trip() {
+        let src = "_naay_version: \"1.0\"\nbase: &base\n  host: localhost\n  port: \"80\"\nextra: &extra {tls: \"on\"}\ndev:\n  name: dev\n  <<: *base\n  port: \"8080\"\nitems:\n  - &item\n    a: b\n  - c: d\n    <<: [*extra, *item]\n";
+        let parsed = parse_naay(src).unwrap();
+        let dumped = dump_naay(&parsed).unwrap();
+        assert!(dumped.contains("base: &base\n"), "{dumped}");
+        assert!(dumped.contains("  name: dev\n  <<: *base\n  port: \"8080\"\n"), "{dumped}");
+        assert!(dumped.contains("  - &item\n"), "{dumped}");
+        assert!(dumped.contains("    <<: [*extra, *item]\n"), "{dumped}");
+        assert_eq!(dumped.matches("host: localhost").count(), 1, "{dumped}");
+        assert!(parse_naay(&dumped).unwrap().semantic_eq(&parsed));
+
+        // A merged entry that was edited is written out in full.
+        let mut edited = parsed.clone();
+        let YamlValue::Map(root) = &mut edited else { unreachable!() };
+        let YamlValue::Map(dev) = &mut root.get_mut("dev").unwrap().value else { unreachable!() };
+        dev.get_mut("host").unwrap().set_value(YamlValue::Str("example.org".into()));
+        let dumped = dump_naay(&edited).unwrap();
+        assert!(dumped.contains("  name: dev\n  host: example.org\n"), "{dumped}");
+        assert!(parse_naay(&dumped).unwrap().semantic_eq(&edited));
+    }
+
+
+    #[test]
+    fn norway_problem_words_stay_strings() {
+        let parsed = parse_naay("_naay_version: \"1.0\"\ncountry: no\nswitch: on\n").unwrap();
+        let map = parsed.as_map().unwrap();
+        assert_eq!(map["country"].value, YamlValue::Str("no".into()));
+        assert_eq!(map["country"].value.as_bool(), None);
+        assert_eq!(map["switch"].value.as_bool(), None);
+        let words = ParseOptions::default().bool_words;
+        assert_eq!(words, BoolWords::CoreOnly);
+        assert_eq!(map["country"].value.as_bool_with(words), None);
+        assert_eq!(parsed.view().get("switch").and_then(YamlView::as_bool), None);
+        let dumped = dump_naay(&parsed).unwrap();
+        assert!(dumped.contains("country: no\n"), "{dumped}");
+
+        let extended = BoolWords::Extended;
+        assert_eq!(map["country"].value.as_bool_with(extended), Some(false));
+        assert_eq!(map["switch"].value.as_bool_with(extended), Some(true));
+        let view = parsed.view().with_bool_words(extended);
+        assert_eq!(view.get("switch").and_then(YamlView::as_bool), Some(true));
+        assert_eq!(YamlValue::Str("true".into()).as_bool_with(extended), Some(true));
+        assert_eq!(YamlValue::Str("maybe".into()).as_bool_with(extended), None);
+    }
+
+
+    #[test]
+    fn len_counts_items_and_entries_only() {
+        let parsed = parse_naay("_naay_version: \"1.0\"\nlist:\n  - a\n  - b\nnone: []\nmap: {}\nname: demo\nnothing:\n").unwrap();
+        let map = parsed.as_map().unwrap();
+        assert_eq!(parsed.len(), 6);
+        assert_eq!(map["list"].value.len(), 2);
+        assert!(!map["list"].value.is_empty());
+        assert!(map["none"].value.is_empty());
+        assert!(map["map"].value.is_empty());
+        assert_eq!(map["name"].value.len(), 0);
+        assert!(map["name"].value.is_empty());
+        assert_eq!(map["nothing"].value.len(), 0);
+        assert!(map["nothing"].value.is_empty());
+    }
+
+
+    #[test]
+    fn dump_naay_to_writes_a_trailing_root_merge() {
+        let src = "_naay_version: \"1.0\"\nbase: &base\n  host: localhost\n  port: \"80\"\nname: dev\n<<: *base\n";
+        let parsed = parse_naay(src).unwrap();
+        let mut streamed = Vec::new();
+        dump_naay_to(&parsed, &mut streamed).unwrap();
+        let streamed = String::from_utf8(streamed).unwrap();
+        assert_eq!(streamed, dump_naay(&parsed).unwrap());
+        assert!(streamed.ends_with("name: dev\n<<: *base\n"), "{streamed}");
+    }
+
+
+    #[test]
+    fn merge_line_is_expanded_when_it_no_longer_matches() {
+        let src = "_naay_version: \"1.0\"\nbase: &base\n  host: localhost\n  port: \"80\"\ndev:\n  name: dev\n  <<: *base\n";
+        let parsed = parse_naay(src).unwrap();
+        fn entries<'v>(value: &'v mut YamlValue, key: &str) -> &'v mut BTreeMap<String, YamlNode> {
+            let YamlValue::Map(root) = value else { unreachable!() };
+            let YamlValue::Map(map) = &mut root.get_mut(key).unwrap().value else { unreachable!() };
+            map
+        }
+
+        // The anchor changed under the merge: `dev` keeps its own values.
+        let mut edited = parsed.clone();
+        entries(&mut edited, "base")
+            .get_mut("host").unwrap().set_value(YamlValue::Str("example.org".into()));
+        let dumped = dump_naay(&edited).unwrap();
+        assert!(!dumped.contains("<<"), "{dumped}");
+        assert!(parse_naay(&dumped).unwrap().semantic_eq(&edited));
+
+        // A merged key was removed from `dev`: `<<` would bring it back.
+        let mut edited = parsed.clone();
+        entries(&mut edited, "dev").remove("host");
+        let dumped = dump_naay(&edited).unwrap();
+        assert!(!dumped.contains("<<"), "{dumped}");
+        assert!(dumped.contains("  name: dev\n  port: \"80\"\n"), "{dumped}");
+        assert!(parse_naay(&dumped).unwrap().semantic_eq(&edited));
+    }
+
+
+    #[test]
+    fn unclosed_bracket_without_continuation_is_a_plain_scalar() {
+        let parsed = parse_naay("_naay_version: \"1.0\"\ntitle: [draft\nnext: x\nitems:\n  - [wip\n").unwrap();
+        let map = parsed.as_map().unwrap();
+        assert_eq!(map["title"].value, YamlValue::Str("[draft".into()));
+        assert_eq!(map["next"].value, YamlValue::Str("x".into()));
+        assert_eq!(map["items"].value.as_seq().unwrap()[0].value, YamlValue::Str("[wip".into()));
+    }
+
+
+    #[test]
+    fn parses_the_deeply_nested_stress_fixture() {
+        let input = include_str!("../../examples/stress_test1.yaml");
+        let parsed = parse_naay(input).expect("fixture should parse");
+        assert!(inspect::max_depth(&parsed) > 256);
+    }
+
+
+    #[test]
+    fn comments_after_the_last_root_entry_round_trip() {
+        let input = "_naay_version: \"1.0\"\nname: demo # inline\n# end of file\n\n# really the end\n";
+        let parsed = parse_naay(input).expect("parse should succeed");
+        let root = parsed.as_map().unwrap();
+        let texts: Vec<_> = root["name"].trailing_comments.iter().map(|c| c.text.as_str()).collect();
+        assert_eq!(texts, ["# end of file", "# really the end"]);
+        assert_eq!(dump_naay(&parsed).unwrap(), input.replace("\n\n", "\n"));
+
+        let nested = "_naay_version: \"1.0\"\nserver:\n  port: \"80\"\n  # end of server\n# end of file\n";
+        let parsed = parse_naay(nested).expect("parse should succeed");
+        assert_eq!(dump_naay(&parsed).unwrap(), nested);
+        let mut streamed = Vec::new();
+        dump_naay_to(&parsed, &mut streamed).unwrap();
+        assert_eq!(String::from_utf8(streamed).unwrap(), nested);
+    }
+
+
+    #[test]
+    fn comment_lines_do_not_decide_block_boundaries() {
+        let input = r#"_naay_version: "1.0"
+items:
+  # above the first item
+  - "a"
+empty:
+  # nothing nested here
+next: "n"
+server:
+  port: "80"
+    # still inside server
+# about after
+after: "x"
+"#;
+        let parsed = parse_naay(input).expect("parse should succeed");
+        let root = parsed.as_map().expect("root is a map");
+
+        // The comment does not make `items` a mapping.
+        let items = root["items"].value.as_seq().expect("items stay a sequence");
+        assert_eq!(items[0].leading_comments[0].text, "# above the first item");
+
+        // Comment lines alone are no nested block.
+        assert_eq!(root["empty"].value, YamlValue::Null);
+        assert_eq!(root["next"].leading_comments[0].text, "# nothing nested here");
+
+        // Below a block, a comment at the block's depth or deeper ends it;
+        // a shallower one leads the next entry.
+        assert_eq!(root["server"].trailing_comments[0].text, "# still inside server");
+        assert_eq!(root["after"].leading_comments[0].text, "# about after");
+    }
+
+
+    #[test]
+    fn unspanned_entries_dump_in_their_order() {
+        let mut entries = BTreeMap::new();
+        for (i, key) in ["_naay_version", "zeta", "alpha"].into_iter().enumerate() {
+            let mut node = YamlNode::new(YamlValue::Str("1.0".to_string()));
+            node.order = Some(i);
+            entries.insert(key.to_string(), node);
+        }
+        entries.insert("beta".to_string(), YamlNode::new(YamlValue::Str("1.0".to_string())));
+        let dumped = dump_naay(&YamlValue::Map(entries)).expect("dump should succeed");
+        let keys: Vec<&str> = dumped.lines().map(|l| l.split(':').next().unwrap_or("")).collect();
+        assert_eq!(keys, ["_naay_version", "zeta", "alpha", "beta"]);
     }
 }
@@ -0,0 +1,225 @@
+use std::collections::BTreeMap;
+
+use crate::{push_pointer_segment, YamlValue};
+
+/// Expected shape of a value. Maps only check the keys they list; other
+/// keys are allowed.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Schema {
+    Any,
+    Str,
+    /// A sequence whose items all match the inner schema.
+    Seq(Box<Schema>),
+    Map(BTreeMap<String, Field>),
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Field {
+    pub schema: Schema,
+    pub required: bool,
+}
+
+/// One mismatch, located by its pointer path (e.g. `/server/port`).
+#[derive(Debug, Clone, PartialEq, thiserror::Error)]
+#[error("{path}: {message}")]
+pub struct ValidationError {
+    pub path: String,
+    pub message: String,
+}
+
+impl Field {
+    pub fn required(schema: Schema) -> Self {
+        Self {
+            schema,
+            required: true,
+        }
+    }
+
+    pub fn optional(schema: Schema) -> Self {
+        Self {
+            schema,
+            required: false,
+        }
+    }
+}
+
+impl Schema {
+    pub fn seq(items: Schema) -> Self {
+        Schema::Seq(Box::new(items))
+    }
+
+    pub fn map<'k>(fields: impl IntoIterator<Item = (&'k str, Field)>) -> Self {
+        Schema::Map(
+            fields
+                .into_iter()
+                .map(|(key, field)| (key.to_string(), field))
+                .collect(),
+        )
+    }
+
+    /// Checks `value` against this schema and reports every mismatch, not
+    /// just the first. Missing keys of a map come before errors inside it.
+    pub fn validate(&self, value: &YamlValue) -> Result<(), Vec<ValidationError>> {
+        let mut errors = Vec::new();
+        let mut stack = vec![(self, value, String::new())];
+        while let Some((schema, value, path)) = stack.pop() {
+            let mut children = Vec::new();
+            match (schema, value) {
+                (Schema::Any, _) | (Schema::Str, YamlValue::Str(_)) => {}
+                (Schema::Seq(items), YamlValue::Seq(seq)) => {
+                    for (i, node) in seq.iter().enumerate() {
+                        children.push((&**items, &node.value, child_path(&path, &i.to_string())));
+                    }
+                }
+                (Schema::Map(fields), YamlValue::Map(map)) => {
+                    for (key, field) in fields {
+                        let key_path = child_path(&path, key);
+                        match map.get(key) {
+                            Some(node) => children.push((&field.schema, &node.value, key_path)),
+                            None if field.required => errors.push(ValidationError {
+                                path: key_path,
+                                message: "missing required key".to_string(),
+                            }),
+                            None => {}
+                        }
+                    }
+                }
+                (schema, value) => errors.push(ValidationError {
+                    message: format!(
+                        "expected {}, found {}",
+                        schema.type_name(),
+                        value.type_name()
+                    ),
+                    path: if path.is_empty() {
+                        "/".to_string()
+                    } else {
+                        path
+                    },
+                }),
+            }
+            stack.extend(children.into_iter().rev());
+        }
+        if errors.is_empty() {
+            Ok(())
+        } else {
+            Err(errors)
+        }
+    }
+
+    fn type_name(&self) -> &'static str {
+        match self {
+            Schema::Any => "any",
+            Schema::Str => "str",
+            Schema::Seq(_) => "seq",
+            Schema::Map(_) => "map",
+        }
+    }
+}
+
+/// Checks the top-level keys of a mapping: every `required` key must be
+/// present and every other key must be in `required` or `optional`.
+/// `_naay_version` is always allowed. Missing keys are reported first.
+pub fn require_keys(
+    value: &YamlValue,
+    required: &[&str],
+    optional: &[&str],
+) -> Result<(), Vec<String>> {
+    let Some(map) = value.as_map() else {
+        return Err(vec![format!("expected map, found {}", value.type_name())]);
+    };
+    let mut errors: Vec<String> = required
+        .iter()
+        .filter(|key| !map.contains_key(**key))
+        .map(|key| format!("missing required key '{key}'"))
+        .collect();
+    errors.extend(
+        map.keys()
+            .filter(|key| {
+                let key = key.as_str();
+                key != "_naay_version" && !required.contains(&key) && !optional.contains(&key)
+            })
+            .map(|key| format!("unexpected key '{key}'")),
+    );
+    if errors.is_empty() {
+        Ok(())
+    } else {
+        Err(errors)
+    }
+}
+
+fn child_path(path: &str, segment: &str) -> String {
+    let mut child = path.to_string();
+    push_pointer_segment(&mut child, segment);
+    child
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::parse_naay;
+
+    fn config_schema() -> Schema {
+        Schema::map([
+            ("_naay_version", Field::required(Schema::Str)),
+            (
+                "server",
+                Field::required(Schema::map([
+                    ("host", Field::required(Schema::Str)),
+                    ("port", Field::required(Schema::Str)),
+                    ("aliases", Field::optional(Schema::seq(Schema::Str))),
+                ])),
+            ),
+        ])
+    }
+
+    #[test]
+    fn accepts_matching_document() {
+        let input = r#"_naay_version: "1.0"
+server:
+  host: "localhost"
+  port: "80"
+  aliases:
+    - "www"
+extra: "ignored"
+"#;
+        let parsed = parse_naay(input).expect("parse should succeed");
+        assert_eq!(config_schema().validate(&parsed), Ok(()));
+    }
+
+    #[test]
+    fn reports_missing_keys_and_type_mismatches() {
+        let input = r#"_naay_version: "1.0"
+server:
+  port:
+    - "80"
+  aliases:
+    - "www"
+    - nested: "map"
+"#;
+        let parsed = parse_naay(input).expect("parse should succeed");
+        let errors = config_schema()
+            .validate(&parsed)
+            .expect_err("validation should fail");
+        let messages: Vec<String> = errors.iter().map(ToString::to_string).collect();
+        assert_eq!(
+            messages,
+            vec![
+                "/server/host: missing required key",
+                "/server/aliases/1: expected str, found map",
+                "/server/port: expected str, found seq",
+            ]
+        );
+    }
+
+    #[test]
+    fn require_keys_reports_missing_and_unexpected() {
+        let input = "_naay_version: \"1.0\"\nname: \"demo\"\ncolour: \"red\"\n";
+        let value = parse_naay(input).expect("parse should succeed");
+        let errors = require_keys(&value, &["name", "port"], &["debug"]).expect_err("keys differ");
+        assert_eq!(
+            errors,
+            ["missing required key 'port'", "unexpected key 'colour'"]
+        );
+        assert!(require_keys(&value, &["name"], &["colour", "debug"]).is_ok());
+    }
+}
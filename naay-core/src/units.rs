@@ -0,0 +1,196 @@
+use std::time::Duration;
+
+use crate::{ParseOptions, QuoteHint, YamlValue};
+
+/// A plain scalar read as a duration or a size by
+/// `ParseOptions::parse_durations` / `parse_sizes`. The node keeps its
+/// original text, so dumping is unaffected.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Quantity {
+    Duration(Duration),
+    Bytes(u64),
+}
+
+impl YamlValue {
+    /// Reads a duration such as `30s`, `250ms` or `1h30m`. Units are `ns`,
+    /// `us`, `ms`, `s`, `m`, `h` and `d`; numbers may have a fraction.
+    pub fn as_duration(&self) -> Option<Duration> {
+        parse_duration(self.as_str()?.trim())
+    }
+
+    /// Reads a size such as `512`, `10KB` or `4Gi` as bytes. `K`/`KB`
+    /// style units are powers of 1000, `Ki`/`KiB` powers of 1024.
+    pub fn as_size(&self) -> Option<u64> {
+        parse_size(self.as_str()?.trim())
+    }
+}
+
+pub(crate) fn annotate_quantities(value: &mut YamlValue, options: &ParseOptions) {
+    if !(options.parse_durations || options.parse_sizes) {
+        return;
+    }
+    let mut stack = vec![value];
+    while let Some(value) = stack.pop() {
+        let children: Vec<_> = match value {
+            YamlValue::Seq(seq) => seq.iter_mut().collect(),
+            YamlValue::Map(map) => map.values_mut().collect(),
+            YamlValue::Null | YamlValue::Str(_) => continue,
+        };
+        for node in children {
+            let YamlValue::Str(text) = &node.value else {
+                stack.push(&mut node.value);
+                continue;
+            };
+            if !matches!(node.quote, QuoteHint::Plain | QuoteHint::None) || node.tag.is_some() {
+                continue;
+            }
+            let text = text.trim();
+            let duration = options
+                .parse_durations
+                .then(|| parse_duration(text).map(Quantity::Duration))
+                .flatten();
+            // A bare number is not worth annotating as a size.
+            let has_unit = text.ends_with(char::is_alphabetic);
+            let size = (options.parse_sizes && has_unit)
+                .then(|| parse_size(text).map(Quantity::Bytes))
+                .flatten();
+            node.quantity = duration.or(size);
+        }
+    }
+}
+
+// Splits `12.5rest` into the number and what follows it.
+fn split_number(s: &str) -> Option<(f64, &str)> {
+    let end = s
+        .find(|c: char| !(c.is_ascii_digit() || c == '.'))
+        .unwrap_or(s.len());
+    let number = &s[..end];
+    if number.is_empty() || number.starts_with('.') || number.ends_with('.') {
+        return None;
+    }
+    Some((number.parse().ok()?, &s[end..]))
+}
+
+fn parse_duration(s: &str) -> Option<Duration> {
+    if s.is_empty() {
+        return None;
+    }
+    let mut rest = s;
+    let mut total = 0.0;
+    while !rest.is_empty() {
+        let (number, after) = split_number(rest)?;
+        let unit_len = after
+            .find(|c: char| c.is_ascii_digit())
+            .unwrap_or(after.len());
+        let seconds = match &after[..unit_len] {
+            "ns" => 1e-9,
+            "us" | "µs" => 1e-6,
+            "ms" => 1e-3,
+            "s" => 1.0,
+            "m" => 60.0,
+            "h" => 3600.0,
+            "d" => 86400.0,
+            _ => return None,
+        };
+        total += number * seconds;
+        rest = &after[unit_len..];
+    }
+    Duration::try_from_secs_f64(total).ok()
+}
+
+fn parse_size(s: &str) -> Option<u64> {
+    let (number, unit) = split_number(s)?;
+    let multiplier: f64 = match unit {
+        "" | "B" => 1.0,
+        "K" | "KB" | "k" | "kB" => 1e3,
+        "M" | "MB" => 1e6,
+        "G" | "GB" => 1e9,
+        "T" | "TB" => 1e12,
+        "P" | "PB" => 1e15,
+        "Ki" | "KiB" => 1024.0,
+        "Mi" | "MiB" => 1024f64.powi(2),
+        "Gi" | "GiB" => 1024f64.powi(3),
+        "Ti" | "TiB" => 1024f64.powi(4),
+        "Pi" | "PiB" => 1024f64.powi(5),
+        _ => return None,
+    };
+    let bytes = (number * multiplier).round();
+    (bytes <= u64::MAX as f64).then_some(bytes as u64)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{dump_naay, parse_naay, parse_naay_with};
+
+    const INPUT: &str = r#"_naay_version: "1.0"
+timeout: 30s
+retry: 1h30m
+size: 4Gi
+disk: 1.5GB
+quoted: "30s"
+name: demo
+"#;
+
+    #[test]
+    fn parse_durations_annotates_plain_scalars() {
+        let options = ParseOptions {
+            parse_durations: true,
+            ..ParseOptions::default()
+        };
+        let value = parse_naay_with(INPUT, &options).expect("parse should succeed");
+        let root = value.as_map().expect("root should be a map");
+        assert_eq!(
+            root["timeout"].quantity,
+            Some(Quantity::Duration(Duration::from_secs(30)))
+        );
+        assert_eq!(
+            root["retry"].quantity,
+            Some(Quantity::Duration(Duration::from_secs(5400)))
+        );
+        assert_eq!(root["size"].quantity, None);
+        assert_eq!(root["quoted"].quantity, None);
+        assert_eq!(root["name"].quantity, None);
+        assert_eq!(root["timeout"].value, YamlValue::Str("30s".to_string()));
+        assert_eq!(dump_naay(&value).expect("dump should succeed"), INPUT);
+    }
+
+    #[test]
+    fn parse_sizes_annotates_plain_scalars() {
+        let options = ParseOptions {
+            parse_sizes: true,
+            ..ParseOptions::default()
+        };
+        let value = parse_naay_with(INPUT, &options).expect("parse should succeed");
+        let root = value.as_map().expect("root should be a map");
+        assert_eq!(root["size"].quantity, Some(Quantity::Bytes(4 << 30)));
+        assert_eq!(root["disk"].quantity, Some(Quantity::Bytes(1_500_000_000)));
+        assert_eq!(root["timeout"].quantity, None);
+
+        let plain = parse_naay(INPUT).expect("parse should succeed");
+        assert_eq!(
+            plain.as_map().expect("root should be a map")["size"].quantity,
+            None
+        );
+        assert_eq!(root["quoted"].value.as_size(), None);
+        assert_eq!(
+            root["quoted"].value.as_duration(),
+            Some(Duration::from_secs(30))
+        );
+    }
+
+    #[test]
+    fn set_value_clears_the_quantity() {
+        let options = ParseOptions {
+            parse_durations: true,
+            ..ParseOptions::default()
+        };
+        let mut value = parse_naay_with(INPUT, &options).expect("parse should succeed");
+        let YamlValue::Map(root) = &mut value else {
+            panic!("root should be a map");
+        };
+        let timeout = root.get_mut("timeout").expect("timeout should exist");
+        timeout.set_value(YamlValue::Str("forever".to_string()));
+        assert_eq!(timeout.quantity, None);
+    }
+}
@@ -0,0 +1,182 @@
+use std::collections::btree_map;
+
+use crate::inspect::lookup_pointer;
+use crate::{BoolWords, YamlValue};
+
+/// Read-only handle on a parsed value. It hands out shared references only,
+/// so passing a view around makes it explicit that the tree is not edited.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct YamlView<'a> {
+    value: &'a YamlValue,
+    bool_words: BoolWords,
+}
+
+impl<'a> YamlView<'a> {
+    pub fn new(value: &'a YamlValue) -> Self {
+        Self {
+            value,
+            bool_words: BoolWords::default(),
+        }
+    }
+
+    /// The same view with `as_bool` reading `words`, here and below.
+    pub fn with_bool_words(self, words: BoolWords) -> Self {
+        Self {
+            bool_words: words,
+            ..self
+        }
+    }
+
+    fn child(self, value: &'a YamlValue) -> Self {
+        Self { value, ..self }
+    }
+
+    pub fn value(self) -> &'a YamlValue {
+        self.value
+    }
+
+    /// Entry `key` of a mapping.
+    pub fn get(self, key: &str) -> Option<YamlView<'a>> {
+        self.value
+            .as_map()?
+            .get(key)
+            .map(|node| self.child(&node.value))
+    }
+
+    /// Item `index` of a sequence.
+    pub fn at(self, index: usize) -> Option<YamlView<'a>> {
+        self.value
+            .as_seq()?
+            .get(index)
+            .map(|node| self.child(&node.value))
+    }
+
+    /// JSON-pointer lookup (`/server/hosts/0`) below this mapping; `""` is
+    /// the view itself.
+    pub fn pointer(self, path: &str) -> Option<YamlView<'a>> {
+        if path.is_empty() {
+            return Some(self);
+        }
+        lookup_pointer(self.value, path).map(|node| self.child(&node.value))
+    }
+
+    pub fn as_str(self) -> Option<&'a str> {
+        self.value.as_str()
+    }
+
+    pub fn as_i64(self) -> Option<i64> {
+        self.value.as_i64()
+    }
+
+    pub fn as_f64(self) -> Option<f64> {
+        self.value.as_f64()
+    }
+
+    pub fn as_bool(self) -> Option<bool> {
+        self.value.as_bool_with(self.bool_words)
+    }
+
+    pub fn is_null(self) -> bool {
+        self.value.is_null()
+    }
+
+    pub fn is_str(self) -> bool {
+        self.value.is_str()
+    }
+
+    pub fn is_seq(self) -> bool {
+        self.value.is_seq()
+    }
+
+    pub fn is_map(self) -> bool {
+        self.value.is_map()
+    }
+
+    pub fn type_name(self) -> &'static str {
+        self.value.type_name()
+    }
+
+    /// Number of items or entries; 0 for scalars.
+    pub fn len(self) -> usize {
+        self.value.len()
+    }
+
+    pub fn is_empty(self) -> bool {
+        self.value.is_empty()
+    }
+
+    /// Items of a sequence; empty for anything else.
+    pub fn items(self) -> impl Iterator<Item = YamlView<'a>> {
+        self.value
+            .as_seq()
+            .unwrap_or_default()
+            .iter()
+            .map(move |node| self.child(&node.value))
+    }
+
+    /// Entries of a mapping in key order; empty for anything else.
+    pub fn entries(self) -> impl Iterator<Item = (&'a str, YamlView<'a>)> {
+        self.value
+            .as_map()
+            .map(btree_map::BTreeMap::iter)
+            .into_iter()
+            .flatten()
+            .map(move |(key, node)| (key.as_str(), self.child(&node.value)))
+    }
+}
+
+impl<'a> From<&'a YamlValue> for YamlView<'a> {
+    fn from(value: &'a YamlValue) -> Self {
+        Self::new(value)
+    }
+}
+
+impl YamlValue {
+    pub fn view(&self) -> YamlView<'_> {
+        YamlView::new(self)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::parse_naay;
+
+    #[test]
+    fn view_reads_without_mutable_access() {
+        let input = r#"_naay_version: "1.0"
+server:
+  port: "8080"
+  hosts:
+    - "a"
+    - "b"
+debug: "true"
+"#;
+        let value = parse_naay(input).expect("parse should succeed");
+        let view = value.view();
+        // Views are `Copy` and only ever borrow the tree immutably.
+        let shared = std::thread::scope(|s| s.spawn(move || view.get("debug")).join());
+        assert_eq!(
+            shared
+                .expect("thread should finish")
+                .and_then(YamlView::as_bool),
+            Some(true)
+        );
+
+        let server = view.get("server").expect("server exists");
+        assert_eq!(server.get("port").and_then(YamlView::as_i64), Some(8080));
+        assert_eq!(
+            view.pointer("/server/hosts/1").and_then(YamlView::as_str),
+            Some("b")
+        );
+        let hosts = server.get("hosts").expect("hosts exist");
+        assert_eq!(hosts.len(), 2);
+        assert_eq!(hosts.at(0).and_then(YamlView::as_str), Some("a"));
+        let names: Vec<_> = hosts.items().filter_map(YamlView::as_str).collect();
+        assert_eq!(names, ["a", "b"]);
+        let keys: Vec<_> = server.entries().map(|(key, _)| key).collect();
+        assert_eq!(keys, ["hosts", "port"]);
+        assert!(view.get("missing").is_none());
+        assert!(std::ptr::eq(view.value(), &value));
+    }
+}
@@ -0,0 +1,98 @@
+use std::collections::BTreeMap;
+
+use crate::{YamlNode, YamlValue};
+
+/// In-place rewrite of a value tree, driven by [`walk_mut`]. The default
+/// methods walk into children; override one to change what it sees.
+pub trait Visitor {
+    fn visit_scalar(&mut self, _value: &mut String) {}
+
+    fn visit_seq(&mut self, seq: &mut Vec<YamlNode>) {
+        for node in seq {
+            walk_mut(&mut node.value, self);
+        }
+    }
+
+    fn visit_map(&mut self, map: &mut BTreeMap<String, YamlNode>) {
+        for node in map.values_mut() {
+            walk_mut(&mut node.value, self);
+        }
+    }
+}
+
+/// Hands `value` to the matching `Visitor` method. Nulls are left alone.
+pub fn walk_mut<V: Visitor + ?Sized>(value: &mut YamlValue, visitor: &mut V) {
+    match value {
+        YamlValue::Str(s) => visitor.visit_scalar(s),
+        YamlValue::Seq(seq) => visitor.visit_seq(seq),
+        YamlValue::Map(map) => visitor.visit_map(map),
+        YamlValue::Null => {}
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{dump_naay, parse_naay};
+
+    struct Upper;
+
+    impl Visitor for Upper {
+        fn visit_scalar(&mut self, value: &mut String) {
+            *value = value.to_uppercase();
+        }
+    }
+
+    struct Redact;
+
+    impl Visitor for Redact {
+        fn visit_map(&mut self, map: &mut BTreeMap<String, YamlNode>) {
+            for (key, node) in map.iter_mut() {
+                match key.as_str() {
+                    "secrets" => walk_mut(&mut node.value, &mut Upper),
+                    _ => walk_mut(&mut node.value, self),
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn visitor_uppercases_every_scalar() {
+        let input = r#"_naay_version: "1.0"
+name: demo
+tags:
+  - a
+  - b
+nested:
+  key: value
+"#;
+        let mut value = parse_naay(input).expect("parse should succeed");
+        walk_mut(&mut value, &mut Upper);
+        let expected = r#"_naay_version: "1.0"
+name: DEMO
+tags:
+  - A
+  - B
+nested:
+  key: VALUE
+"#;
+        assert_eq!(dump_naay(&value).expect("dump should succeed"), expected);
+    }
+
+    #[test]
+    fn overriding_visit_map_limits_the_rewrite() {
+        let input = r#"_naay_version: "1.0"
+name: demo
+secrets:
+  token: abc
+"#;
+        let mut value = parse_naay(input).expect("parse should succeed");
+        walk_mut(&mut value, &mut Redact);
+        let view = value.view();
+        assert_eq!(view.get("name").and_then(|v| v.as_str()), Some("demo"));
+        assert_eq!(
+            view.pointer("/secrets/token").and_then(|v| v.as_str()),
+            Some("ABC")
+        );
+    }
+}
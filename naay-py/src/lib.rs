@@ -1,70 +1,165 @@
 use std::collections::BTreeMap;
 
 use pyo3::prelude::*;
-use pyo3::types::{PyAny, PyDict, PyList, PyModule, PyString};
+use pyo3::types::{
+    PyAny, PyBool, PyDict, PyFrozenSet, PyInt, PyList, PyModule, PySet, PyString, PyTuple,
+};
 
-use naay_core::{dump_naay, parse_naay, YamlNode, YamlValue};
+use naay_core::{
+    dump_naay, dump_naay_with, parse_naay, DumpOptions, ParseError, QuoteStyle, YamlNode, YamlValue,
+};
 
-fn yaml_to_py(py: Python<'_>, v: &YamlValue) -> PyResult<Py<PyAny>> {
+pyo3::create_exception!(_naay_native, NaayParseError, pyo3::exceptions::PyValueError);
+
+// Raised with `.line`, `.column` and `.message` so callers need not parse
+// the text.
+fn parse_error(py: Python<'_>, err: ParseError) -> PyErr {
+    let ParseError::Generic {
+        line,
+        column,
+        message,
+    } = &err;
+    let exc = NaayParseError::new_err(err.to_string());
+    let value = exc.value(py);
+    let attrs = value
+        .setattr("line", line)
+        .and_then(|_| value.setattr("column", column))
+        .and_then(|_| value.setattr("message", message));
+    match attrs {
+        Ok(()) => exc,
+        Err(setattr_err) => setattr_err,
+    }
+}
+
+// Dicts keep document order unless `sort_keys` asks for sorted keys.
+fn yaml_to_py(py: Python<'_>, v: &YamlValue, sort_keys: bool) -> PyResult<Py<PyAny>> {
     match v {
+        YamlValue::Null => Ok(py.None()),
         YamlValue::Str(s) => Ok(PyString::new(py, s).unbind().into()),
         YamlValue::Seq(seq) => {
             let list = PyList::empty(py);
             for item in seq {
-                list.append(yaml_to_py(py, &item.value)?)?;
+                list.append(yaml_to_py(py, &item.value, sort_keys)?)?;
             }
             Ok(list.unbind().into())
         }
         YamlValue::Map(map) => {
+            let mut entries: Vec<_> = map.iter().collect();
+            if !sort_keys {
+                entries.sort_by_key(|(_, node)| {
+                    node.span.map_or((usize::MAX, 0), |s| (s.line, s.column))
+                });
+            }
             let dict = PyDict::new(py);
-            for (k, v2) in map {
-                dict.set_item(k, yaml_to_py(py, &v2.value)?)?;
+            for (k, v2) in entries {
+                dict.set_item(k, yaml_to_py(py, &v2.value, sort_keys)?)?;
             }
             Ok(dict.unbind().into())
         }
     }
 }
 
+// Keys are strings in naay; ints and bools (common in JSON-origin data)
+// are written as the scalar they would load back as.
+fn py_to_key(key: &Bound<'_, PyAny>) -> PyResult<String> {
+    if let Ok(s) = key.cast::<PyString>() {
+        Ok(s.to_str()?.to_owned())
+    } else if let Ok(b) = key.cast::<PyBool>() {
+        Ok(if b.is_true() { "true" } else { "false" }.to_owned())
+    } else if key.is_instance_of::<PyInt>() {
+        Ok(key.str()?.to_str()?.to_owned())
+    } else {
+        Err(pyo3::exceptions::PyTypeError::new_err(format!(
+            "Unsupported dict key type for naay: {} (expected str, int or bool)",
+            key.get_type().name()?
+        )))
+    }
+}
+
 fn py_to_yaml(value: &Bound<'_, PyAny>) -> PyResult<YamlValue> {
-    if let Ok(s) = value.cast::<PyString>() {
+    if value.is_none() {
+        Ok(YamlValue::Null)
+    } else if let Ok(s) = value.cast::<PyString>() {
         Ok(YamlValue::Str(s.to_str()?.to_owned()))
-    } else if let Ok(seq) = value.cast::<PyList>() {
+    } else if value.is_instance_of::<PyList>() || value.is_instance_of::<PyTuple>() {
         let mut out = Vec::new();
-        for item in seq.iter() {
-            out.push(YamlNode::new(py_to_yaml(&item)?));
+        for item in value.try_iter()? {
+            out.push(YamlNode::new(py_to_yaml(&item?)?));
         }
         Ok(YamlValue::Seq(out))
+    } else if value.is_instance_of::<PySet>() || value.is_instance_of::<PyFrozenSet>() {
+        // Set iteration order varies between runs; sort for stable output.
+        let sorted = value.py().import("builtins")?.getattr("sorted")?.call1((value,))?;
+        py_to_yaml(&sorted)
     } else if let Ok(dict) = value.cast::<PyDict>() {
         let mut map = BTreeMap::new();
-        for (k, v2) in dict.iter() {
-            let key = k.cast::<PyString>()?.to_str()?.to_owned();
-            map.insert(key, YamlNode::new(py_to_yaml(&v2)?));
+        for (i, (k, v2)) in dict.iter().enumerate() {
+            let key = py_to_key(&k)?;
+            if map.contains_key(&key) {
+                return Err(pyo3::exceptions::PyValueError::new_err(format!(
+                    "duplicate dict key {key:?} after converting keys to strings"
+                )));
+            }
+            let mut node = YamlNode::new(py_to_yaml(&v2)?);
+            // Keeps Python's insertion order when keys are not sorted.
+            node.order = Some(i);
+            map.insert(key, node);
         }
         Ok(YamlValue::Map(map))
     } else {
         Err(pyo3::exceptions::PyTypeError::new_err(
-            "Unsupported Python type for naay (expected str, list, or dict)",
+            "Unsupported Python type for naay (expected None, str, list, tuple, set, frozenset, or dict)",
         ))
     }
 }
 
 #[pyfunction]
-fn loads(py: Python<'_>, s: &str) -> PyResult<Py<PyAny>> {
-    let value = parse_naay(s)
-        .map_err(|e| pyo3::exceptions::PyValueError::new_err(format!("parse error: {e}")))?;
-    yaml_to_py(py, &value)
+#[pyo3(signature = (s, /, *, sort_keys = false))]
+fn loads(py: Python<'_>, s: &str, sort_keys: bool) -> PyResult<Py<PyAny>> {
+    let value = parse_naay(s).map_err(|e| parse_error(py, e))?;
+    yaml_to_py(py, &value, sort_keys)
 }
 
 #[pyfunction]
-fn dumps(obj: Bound<'_, PyAny>) -> PyResult<String> {
+#[pyo3(signature = (obj, /, *, indent = 2, sort_keys = false, quote_style = "double"))]
+fn dumps(obj: Bound<'_, PyAny>, indent: usize, sort_keys: bool, quote_style: &str) -> PyResult<String> {
+    let quote_style = match quote_style {
+        "double" => QuoteStyle::Double,
+        "single" => QuoteStyle::Single,
+        "plain" => QuoteStyle::Plain,
+        "minimal" => QuoteStyle::Minimal,
+        "preserve" => QuoteStyle::Preserve,
+        other => {
+            return Err(pyo3::exceptions::PyValueError::new_err(format!(
+                "unknown quote_style {other:?} (expected 'double', 'single', 'plain', 'minimal' or 'preserve')"
+            )))
+        }
+    };
     let value = py_to_yaml(&obj)?;
-    dump_naay(&value)
+    let options = DumpOptions {
+        indent,
+        sort_keys,
+        quote_style,
+        ..DumpOptions::default()
+    };
+    dump_naay_with(&value, &options)
         .map_err(|e| pyo3::exceptions::PyValueError::new_err(format!("dump error: {e}")))
 }
 
+/// Whether `dumps(loads(s))` parses back to the same data, comments aside.
+#[pyfunction]
+fn check_stable(py: Python<'_>, s: &str) -> PyResult<bool> {
+    let value = parse_naay(s).map_err(|e| parse_error(py, e))?;
+    let dumped = dump_naay(&value)
+        .map_err(|e| pyo3::exceptions::PyValueError::new_err(format!("dump error: {e}")))?;
+    Ok(parse_naay(&dumped).is_ok_and(|reparsed| reparsed.semantic_eq(&value)))
+}
+
 #[pymodule]
-fn _naay_native(_py: Python<'_>, m: Bound<'_, PyModule>) -> PyResult<()> {
+fn _naay_native(py: Python<'_>, m: Bound<'_, PyModule>) -> PyResult<()> {
+    m.add("NaayParseError", py.get_type::<NaayParseError>())?;
     m.add_function(wrap_pyfunction!(loads, &m)?)?;
     m.add_function(wrap_pyfunction!(dumps, &m)?)?;
+    m.add_function(wrap_pyfunction!(check_stable, &m)?)?;
     Ok(())
 }
@@ -7,6 +7,7 @@ from typing import TYPE_CHECKING
 from typing import Any
 from typing import Final
 from typing import Literal
+from typing import cast
 
 from naay import REQUIRED_VERSION
 
@@ -20,7 +21,7 @@ try:
 except ModuleNotFoundError:  # pragma: no cover - optional dependency
     pass
 
-YamlValue = str | list["YamlValue"] | dict[str, "YamlValue"]
+YamlValue = str | list["YamlValue"] | dict[str, "YamlValue"] | None
 
 
 class NaayParseError(ValueError):
@@ -65,26 +66,54 @@ class _Context:
     parent: _ParentRef | None = None
 
 
-def loads(text: str, /) -> YamlValue:
+def loads(text: str, /, *, sort_keys: bool = False) -> YamlValue:
     """Parse naay text into nested dict/list/scalar structures.
 
+    Mappings keep document order unless ``sort_keys`` is set.
+
     Returns:
         The parsed YAML value as nested dict/list/scalar structures.
     """
     parser = _Parser(text)
-    return parser.parse()
+    value = parser.parse()
+    return _sort_keys(value) if sort_keys else value
 
 
-def dumps(data: YamlValue, /) -> str:
+def _sort_keys(value: YamlValue) -> YamlValue:
+    if isinstance(value, dict):
+        return {k: _sort_keys(value[k]) for k in sorted(value)}
+    if isinstance(value, list):
+        return [_sort_keys(v) for v in value]
+    return value
+
+
+def dumps(
+    data: YamlValue,
+    /,
+    *,
+    indent: int = 2,
+    sort_keys: bool = False,
+    quote_style: str = "double",
+) -> str:
     """Serialize a naay-compatible tree back into text.
 
+    ``quote_style`` follows the native dumper; plain Python data carries no
+    source quoting, so ``"preserve"`` writes double quotes.
+
     Returns:
         The serialized YAML text representation.
 
     Raises:
-        NaayDumpError: If serialization fails due to unsupported types.
+        NaayDumpError: If serialization fails due to unsupported types or
+            an unknown quote style.
     """
-    dumper = _Dumper()
+    if quote_style not in _QUOTE_STYLES:
+        msg = (
+            f"unknown quote_style {quote_style!r} "
+            "(expected 'double', 'single', 'plain', 'minimal' or 'preserve')"
+        )
+        raise NaayDumpError(msg)
+    dumper = _Dumper(indent=indent, sort_keys=sort_keys, quote_style=quote_style)
     try:
         dumper.write_value(data, 0)
     except TypeError as exc:  # pragma: no cover - defensive guard
@@ -92,6 +121,19 @@ def dumps(data: YamlValue, /) -> str:
     return dumper.render()
 
 
+def check_stable(text: str, /) -> bool:
+    """Report whether dumping parsed text and parsing it again keeps the data.
+
+    Returns:
+        True when the re-parsed data equals the original parse.
+    """
+    value = loads(text)
+    try:
+        return loads(dumps(value)) == value
+    except NaayParseError:
+        return False
+
+
 class _Parser:
     def __init__(self, text: str) -> None:
         super().__init__()
@@ -191,6 +233,17 @@ class _Parser:
         body, _ = _split_inline_comment(line.content)
         after_dash = body[1:].lstrip()
         self.index += 1
+        indent = line.indent
+        # `- - a`: each further dash opens a nested sequence at its own
+        # column, whose first item is the rest of the line.
+        while after_dash == "-" or (after_dash[:1] == "-" and after_dash[1:2].isspace()):
+            indent += len(body) - len(after_dash)
+            nested: list[YamlValue] = []
+            cast("list[YamlValue]", context.container).append(nested)
+            context = _Context(kind="seq", indent=indent, container=nested)
+            stack.append(context)
+            body = after_dash
+            after_dash = body[1:].lstrip()
         self._assign_seq_value(context, stack, line, after_dash)
         return True
 
@@ -200,7 +253,7 @@ class _Parser:
             self._finalize_context(stack)
             return False
         stripped, _ = _split_inline_comment(line.content)
-        colon_pos = stripped.find(":")
+        colon_pos = _key_colon(stripped)
         if colon_pos == -1:
             msg = f"expected ':' in mapping entry (line {line.line_no})"
             raise NaayParseError(msg)
@@ -229,7 +282,7 @@ class _Parser:
         items: list[YamlValue] = context.container  # type: ignore[assignment]
         if not token:
             if not self._start_sequence_child(context, stack, line, required=False):
-                items.append("")
+                items.append(None)
             return
         if token == "|":  # noqa: S105
             items.append(self._parse_block_scalar(context.indent + 1))
@@ -258,7 +311,7 @@ class _Parser:
             inline_map = self._parse_inline_map(token, context.indent, line, stack)
             items.append(inline_map)
             return
-        items.append(_strip_quotes(token))
+        items.append(_scalar_value(token))
 
     def _assign_map_value(
         self,
@@ -274,7 +327,7 @@ class _Parser:
         mapping: dict[str, YamlValue] = context.container
         if not value_raw:
             if not self._start_map_child(context, stack, line, key, required=False):
-                mapping[key] = ""
+                mapping[key] = None
             return
         if value_raw == "|":
             mapping[key] = self._parse_block_scalar(context.indent + 1)
@@ -302,7 +355,7 @@ class _Parser:
         if literal is not None:
             mapping[key] = literal
             return
-        mapping[key] = _strip_quotes(value_raw)
+        mapping[key] = _scalar_value(value_raw)
 
     def _start_sequence_child(
         self,
@@ -446,7 +499,7 @@ class _Parser:
         line: Line,
         stack: list[_Context],
     ) -> dict[str, YamlValue]:
-        colon_pos = payload.find(":")
+        colon_pos = _key_colon(payload)
         if colon_pos == -1:
             msg = f"expected ':' inside inline map (line {line.line_no})"
             raise NaayParseError(msg)
@@ -526,7 +579,7 @@ class _Parser:
         literal = _empty_literal(vpart)
         if literal is not None:
             return literal
-        return vpart
+        return _scalar_value(vpart)
 
     # Low-level helpers -------------------------------------------------------
     def _merge_into(
@@ -549,20 +602,23 @@ class _Parser:
             target.setdefault(mk, _clone_value(mv))
 
     def _parse_block_scalar(self, min_indent: int) -> str:
-        result: list[tuple[str, int]] = []
+        result: list[Line] = []
         while self.index < len(self.lines):
             line = self.lines[self.index]
             if line.indent <= min_indent:
                 break
-            result.append((line.content, line.indent))
+            result.append(line)
             self.index += 1
         if not result:
             return ""
-        min_seen = min(indent for _, indent in result)
+        # Deeper lines keep their extra indentation; blank lines were dropped
+        # while reading, so gaps in line numbers restore them.
+        min_seen = min(line.indent for line in result)
         lines: list[str] = []
-        for content, indent in result:
-            cut = max(indent - min_seen, 0)
-            lines.append(content[cut:] if cut < len(content) else "")
+        for prev, line in zip([None, *result], result, strict=False):
+            if prev is not None:
+                lines.extend([""] * (line.line_no - prev.line_no - 1))
+            lines.append(" " * (line.indent - min_seen) + line.content)
         return "\n".join(lines)
 
     def _resolve_alias(self, name: str, line: Line) -> YamlValue:
@@ -616,9 +672,27 @@ class _Parser:
         return lines
 
 
+def _as_sequence(value: object) -> Sequence[Any] | None:
+    """Lists and tuples dump as-is; sets and frozensets dump sorted."""
+    if isinstance(value, (list, tuple)):
+        return cast("Sequence[Any]", value)
+    if isinstance(value, (set, frozenset)):
+        return sorted(cast("set[Any]", value))
+    return None
+
+
 class _Dumper:
-    def __init__(self) -> None:
+    def __init__(
+        self,
+        *,
+        indent: int = 2,
+        sort_keys: bool = False,
+        quote_style: str = "double",
+    ) -> None:
         super().__init__()
+        self._indent = max(indent, 1)
+        self._sort_keys = sort_keys
+        self._quote_style = quote_style
         self._parts: list[str] = []
         self._tasks: list[tuple[str, tuple[Any, ...]]] = []
 
@@ -639,21 +713,36 @@ class _Dumper:
     def render(self) -> str:
         return "".join(self._parts)
 
+    def _items(self, value: dict[str, YamlValue]) -> list[tuple[str, YamlValue]]:
+        items: list[tuple[str, YamlValue]] = []
+        seen: set[str] = set()
+        for key, item in cast("dict[object, YamlValue]", value).items():
+            text = _key_text(key)
+            if text in seen:
+                msg = f"duplicate dict key {text!r} after converting keys to strings"
+                raise NaayDumpError(msg)
+            seen.add(text)
+            items.append((text, item))
+        return sorted(items) if self._sort_keys else items
+
     # Writers ----------------------------------------------------------------
     def _process_value(self, indent: int, value: YamlValue) -> None:
+        if value is None:
+            return
         if isinstance(value, str):
             self._write_scalar(value, indent)
             return
-        if isinstance(value, list):
-            if not value:
+        seq = _as_sequence(value)
+        if seq is not None:
+            if not seq:
                 self._parts.append(" " * indent + "[]\n")
                 return
-            self._tasks.append(("seq", (indent, value, 0)))
+            self._tasks.append(("seq", (indent, seq, 0)))
             return
         if not value:
             self._parts.append(" " * indent + "{}\n")
             return
-        items = list(value.items())
+        items = self._items(value)
         self._tasks.append(("map", (indent, items, 0)))
 
     def _process_seq(
@@ -665,29 +754,34 @@ class _Dumper:
         if index >= len(seq):
             return
         item = seq[index]
+        if item is None:
+            self._parts.append(" " * indent + "-\n")
+            self._tasks.append(("seq", (indent, seq, index + 1)))
+            return
         prefix = " " * indent + "- "
         self._parts.append(prefix)
         if isinstance(item, str):
             self._write_scalar(item, indent)
             self._tasks.append(("seq", (indent, seq, index + 1)))
             return
-        if isinstance(item, list):
-            if not item:
+        child = _as_sequence(item)
+        if child is not None:
+            if not child:
                 self._parts.append("[]\n")
                 self._tasks.append(("seq", (indent, seq, index + 1)))
                 return
-            self._parts.append("\n")
+            self._parts[-1] = prefix.rstrip() + "\n"
             self._tasks.append(("seq", (indent, seq, index + 1)))
-            self._tasks.append(("seq", (indent + 2, item, 0)))
+            self._tasks.append(("seq", (indent + self._indent, child, 0)))
             return
         if not item:
             self._parts.append("{}\n")
             self._tasks.append(("seq", (indent, seq, index + 1)))
             return
-        self._parts.append("\n")
-        items = list(item.items())
+        self._parts[-1] = prefix.rstrip() + "\n"
+        items = self._items(item)
         self._tasks.append(("seq", (indent, seq, index + 1)))
-        self._tasks.append(("map", (indent + 2, items, 0)))
+        self._tasks.append(("map", (indent + self._indent, items, 0)))
 
     def _process_map(
         self,
@@ -700,47 +794,105 @@ class _Dumper:
         key, value = items[index]
         formatted_key = self._format_key(key)
         prefix = " " * indent + formatted_key + ":"
+        if value is None:
+            self._parts.append(prefix + "\n")
+            self._tasks.append(("map", (indent, items, index + 1)))
+            return
         if isinstance(value, str):
             self._parts.append(prefix + " ")
             self._write_scalar(value, indent)
             self._tasks.append(("map", (indent, items, index + 1)))
             return
-        if isinstance(value, list):
-            if not value:
+        seq = _as_sequence(value)
+        if seq is not None:
+            if not seq:
                 self._parts.append(prefix + " []\n")
                 self._tasks.append(("map", (indent, items, index + 1)))
                 return
             self._parts.append(prefix + "\n")
             self._tasks.append(("map", (indent, items, index + 1)))
-            self._tasks.append(("seq", (indent + 2, value, 0)))
+            self._tasks.append(("seq", (indent + self._indent, seq, 0)))
             return
         if not value:
             self._parts.append(prefix + " {}\n")
             self._tasks.append(("map", (indent, items, index + 1)))
             return
         self._parts.append(prefix + "\n")
-        nested_items = list(value.items())
+        nested_items = self._items(value)
         self._tasks.append(("map", (indent, items, index + 1)))
-        self._tasks.append(("map", (indent + 2, nested_items, 0)))
+        self._tasks.append(("map", (indent + self._indent, nested_items, 0)))
 
     def _write_scalar(self, value: str, indent: int) -> None:
         if "\n" in value:
             self._parts.append("|")
             self._parts.append("\n")
             for line in value.split("\n"):
-                self._parts.append(" " * (indent + 2) + line + "\n")
+                self._parts.append(" " * (indent + self._indent) + line + "\n")
+            return
+        style = self._quote_style
+        if style == "single":
+            doubled = value.replace("'", "''")
+            self._parts.append(f"'{doubled}'\n")
+            return
+        if (style == "plain" and _plain_round_trips(value)) or (
+            style == "minimal" and _plain_round_trips(value) and not _looks_typed(value)
+        ):
+            self._parts.append(value + "\n")
             return
         escaped = value.replace("\\", "\\\\").replace('"', '\\"')
         self._parts.append(f'"{escaped}"\n')
 
     @staticmethod
     def _format_key(key: str) -> str:
-        if not key or any(c.isspace() or c in ":#?" for c in key):
+        if (
+            not key
+            or key == "<<"
+            or key[0] in "-*&![{|>\"'%@`"
+            or any(c.isspace() or c in ":#?" for c in key)
+        ):
             escaped = key.replace("\\", "\\\\").replace('"', '\\"')
             return f'"{escaped}"'
         return key
 
 
+_QUOTE_STYLES: Final = frozenset({"double", "single", "plain", "minimal", "preserve"})
+_PLAIN_HAZARDS: Final = frozenset("-?:,[]{}#&*!|>'\"%@`")
+_TYPED_WORDS: Final = frozenset({
+    "true", "false", "yes", "no", "on", "off", "y", "n", "null", "~",
+    ".nan", ".inf", "-.inf", "+.inf",
+})
+
+
+def _plain_round_trips(value: str) -> bool:
+    """Whether ``value`` written bare parses back to the same string."""
+    if not value or value.strip() != value or "\t" in value:
+        return False
+    if value[0] in _PLAIN_HAZARDS or value == "~" or ":" in value:
+        return False
+    return not any(
+        ch.isspace() and value[idx + 1 :].startswith("#") for idx, ch in enumerate(value)
+    )
+
+
+def _looks_typed(value: str) -> bool:
+    """Whether a YAML 1.1 reader would take ``value`` for a bool, null or number."""
+    lower = value.lower()
+    if lower in _TYPED_WORDS:
+        return True
+    digits = lower.removeprefix("+").removeprefix("-")
+    for prefix, alphabet in (("0x", "0123456789abcdef"), ("0o", "01234567")):
+        if digits.startswith(prefix):
+            rest = digits[len(prefix) :]
+            return bool(rest) and all(c in alphabet for c in rest)
+    if "_" in lower:
+        return False
+    try:
+        float(lower)
+    except ValueError:
+        return False
+    return True
+
+
 def _split_inline_comment(line: str) -> tuple[str, str | None]:
     in_single = False
     in_double = False
@@ -767,10 +919,25 @@ def _split_inline_comment(line: str) -> tuple[str, str | None]:
     return line.rstrip(), None
 
 
+def _key_colon(text: str) -> int:
+    """Index of the ``:`` ending a key, looking past a quoted key's own colons."""
+    start = 0
+    if text[:1] in {'"', "'"}:
+        start = text.find(text[0], 1) + 1
+    return text.find(":", start)
+
+
 def _parse_key(raw: str) -> str:
     return _strip_quotes(raw)
 
 
+def _scalar_value(raw: str) -> YamlValue:
+    """A plain ``~`` is null; quoted ``"~"`` stays a string."""
+    if raw == "~":
+        return None
+    return _strip_quotes(raw)
+
+
 def _strip_quotes(value: str) -> str:
     min_quote_len: Final = 2
     is_double_quoted = (
@@ -779,11 +946,27 @@ def _strip_quotes(value: str) -> str:
     is_single_quoted = (
         value.startswith("'") and value.endswith("'") and len(value) >= min_quote_len
     )
-    if is_double_quoted or is_single_quoted:
+    if is_single_quoted:
+        # Single-quoted scalars escape an apostrophe by doubling it.
+        return value[1:-1].replace("''", "'")
+    if is_double_quoted:
         return value[1:-1]
     return value
 
 
+def _key_text(key: object) -> str:
+    """Ints and bools (common in JSON-origin data) become string keys."""
+    if isinstance(key, str):
+        return key
+    if isinstance(key, bool):
+        return "true" if key else "false"
+    if isinstance(key, int):
+        return str(key)
+    name = type(key).__name__
+    msg = f"Unsupported dict key type for naay: {name} (expected str, int or bool)"
+    raise NaayDumpError(msg)
+
+
 def _clone_value(value: YamlValue) -> YamlValue:
     if isinstance(value, dict):
         return {k: _clone_value(v) for k, v in value.items()}
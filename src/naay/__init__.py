@@ -17,30 +17,77 @@ except ImportError:  # pragma: no cover - fallback exercised in dedicated tests
 
     USING_PURE_PYTHON = True  # pyright: ignore[reportConstantRedefinition]
 
-type YamlValue = str | list[YamlValue] | dict[str, YamlValue]
+type YamlValue = str | list[YamlValue] | dict[str, YamlValue] | None
 
 
 class _NativeModule(Protocol):
-    def loads(self, text: str, /) -> YamlValue: ...
-    def dumps(self, data: YamlValue, /) -> str: ...
+    def loads(self, text: str, /, *, sort_keys: bool = False) -> YamlValue: ...
+    def dumps(
+        self,
+        data: YamlValue,
+        /,
+        *,
+        indent: int = 2,
+        sort_keys: bool = False,
+        quote_style: str = "double",
+    ) -> str: ...
+    def check_stable(self, text: str, /) -> bool: ...
 
 
 _native_typed = cast("_NativeModule", _native)
 
+NaayParseError = cast("type[ValueError]", _native.NaayParseError)
+"""Raised by `loads` on invalid input.
 
-def loads(text: str, /) -> YamlValue:
+The native parser sets ``line``, ``column`` and ``message`` attributes.
+"""
+
+
+def loads(text: str, /, *, sort_keys: bool = False) -> YamlValue:
     """Parse naay YAML text into nested dict/list structures.
 
+    Dict keys follow document order; pass ``sort_keys=True`` to sort them.
+
     Returns:
         Parsed YAML data as nested dict/list structures.
     """
-    return _native_typed.loads(text)
+    return _native_typed.loads(text, sort_keys=sort_keys)
 
 
-def dumps(data: YamlValue, /) -> str:
+def dumps(
+    data: YamlValue,
+    /,
+    *,
+    indent: int = 2,
+    sort_keys: bool = False,
+    quote_style: str = "double",
+) -> str:
     """Serialize naay-supported objects back to YAML text.
 
+    Args:
+        data: Value to serialize.
+        indent: Spaces per nesting level.
+        sort_keys: Emit dict keys sorted instead of in insertion order.
+        quote_style: One of ``"double"``, ``"single"``, ``"plain"``,
+            ``"minimal"`` or ``"preserve"``.
+
     Returns:
         YAML text representation of the data.
     """
-    return _native_typed.dumps(data)
+    return _native_typed.dumps(
+        data,
+        indent=indent,
+        sort_keys=sort_keys,
+        quote_style=quote_style,
+    )
+
+
+def check_stable(text: str, /) -> bool:
+    """Check that dumping parsed text and parsing it again keeps the data.
+
+    Comments and quoting are ignored; only the values are compared.
+
+    Returns:
+        True when the round-trip is data-stable.
+    """
+    return _native_typed.check_stable(text)
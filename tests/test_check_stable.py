@@ -0,0 +1,34 @@
+from __future__ import annotations
+
+import textwrap
+
+import naay
+
+
+def test_normal_document_is_stable() -> None:
+    yaml_text = textwrap.dedent(
+        """
+        _naay_version: "1.0"
+        # server settings
+        server:
+          host: localhost # inline
+          port: '8080'
+        items:
+          - "a"
+          - b
+        """,
+    ).lstrip()
+
+    assert naay.check_stable(yaml_text) is True
+
+
+def test_escaped_quote_is_reported() -> None:
+    # The native parser keeps backslash escapes verbatim while the dumper
+    # escapes them again, so this value grows on every round-trip.
+    yaml_text = '_naay_version: "1.0"\nq: "a\\"b"\n'
+
+    result = naay.check_stable(yaml_text)
+
+    assert isinstance(result, bool)
+    if not naay.USING_PURE_PYTHON:
+        assert result is False
@@ -0,0 +1,76 @@
+from __future__ import annotations
+
+import pytest
+
+import naay
+
+
+def test_dumps_with_four_space_indent() -> None:
+    data: naay.YamlValue = {
+        "_naay_version": "1.0",
+        "server": {"hosts": ["a", "b"], "port": "80"},
+    }
+
+    dumped = naay.dumps(data, indent=4)
+
+    assert dumped == (
+        '_naay_version: "1.0"\n'
+        "server:\n"
+        "    hosts:\n"
+        '        - "a"\n'
+        '        - "b"\n'
+        '    port: "80"\n'
+    )
+    assert naay.loads(dumped) == data
+
+
+def test_dumps_sort_keys_and_insertion_order() -> None:
+    data: naay.YamlValue = {"_naay_version": "1.0", "zeta": "1", "alpha": "2"}
+
+    assert list(naay.loads(naay.dumps(data))) == ["_naay_version", "zeta", "alpha"]  # type: ignore[arg-type]
+    assert list(naay.loads(naay.dumps(data, sort_keys=True))) == [  # type: ignore[arg-type]
+        "_naay_version",
+        "alpha",
+        "zeta",
+    ]
+
+
+def test_dumps_quote_styles() -> None:
+    data: naay.YamlValue = {
+        "_naay_version": "1.0",
+        "name": "demo",
+        "quote": "it's",
+        "flag": "yes",
+        "hazard": "a: b",
+    }
+
+    single = naay.dumps(data, quote_style="single")
+    assert "name: 'demo'" in single
+    assert "quote: 'it''s'" in single
+    plain = naay.dumps(data, quote_style="plain")
+    assert "name: demo\n" in plain
+    assert "flag: yes\n" in plain
+    assert 'hazard: "a: b"' in plain
+    minimal = naay.dumps(data, quote_style="minimal")
+    assert "name: demo\n" in minimal
+    assert 'flag: "yes"' in minimal
+    for style in ("double", "single", "plain", "minimal", "preserve"):
+        assert naay.loads(naay.dumps(data, quote_style=style)) == data
+    with pytest.raises(ValueError, match="unknown quote_style"):
+        naay.dumps(data, quote_style="fancy")
+
+
+def test_dumps_int_and_bool_keys_as_strings() -> None:
+    data = {"_naay_version": "1.0", "ports": {80: "http", 443: "https"}, True: "yes"}
+
+    dumped = naay.dumps(data)  # type: ignore[arg-type]
+
+    assert naay.loads(dumped) == {
+        "_naay_version": "1.0",
+        "ports": {"80": "http", "443": "https"},
+        "true": "yes",
+    }
+    with pytest.raises(ValueError, match="duplicate dict key"):
+        naay.dumps({"_naay_version": "1.0", 1: "a", "1": "b"})  # type: ignore[dict-item]
+    with pytest.raises((TypeError, ValueError), match="dict key type"):
+        naay.dumps({"_naay_version": "1.0", 1.5: "a"})  # type: ignore[dict-item]
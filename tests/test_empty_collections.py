@@ -46,3 +46,48 @@ def test_empty_lists_and_maps_round_trip() -> None:
 
     reparsed = naay.loads(dumped)
     assert reparsed == expected
+
+
+def test_empty_value_is_none_not_empty_string() -> None:
+    yaml_text = textwrap.dedent(
+        """
+        _naay_version: "1.0"
+        empty:
+        blank: ""
+        items:
+          -
+          - ""
+        """,
+    ).lstrip()
+    parsed = naay.loads(yaml_text)
+
+    assert parsed == {
+        "_naay_version": "1.0",
+        "empty": None,
+        "blank": "",
+        "items": [None, ""],
+    }
+    dumped = naay.dumps(parsed)
+    assert dumped == yaml_text
+    assert naay.loads(dumped) == parsed
+
+
+def test_tilde_loads_as_none_unless_quoted() -> None:
+    yaml_text = textwrap.dedent(
+        """
+        _naay_version: "1.0"
+        tilde: ~
+        quoted: "~"
+        word: null
+        items:
+          - ~
+        """,
+    ).lstrip()
+
+    assert naay.loads(yaml_text) == {
+        "_naay_version": "1.0",
+        "tilde": None,
+        "quoted": "~",
+        "word": "null",
+        "items": [None],
+    }
@@ -0,0 +1,26 @@
+from __future__ import annotations
+
+import textwrap
+
+import naay
+
+
+def test_loads_keeps_document_key_order() -> None:
+    yaml_text = textwrap.dedent(
+        """
+        _naay_version: "1.0"
+        zeta: "1"
+        alpha: "2"
+        mid: "3"
+        """,
+    ).lstrip()
+
+    parsed = naay.loads(yaml_text)
+
+    assert isinstance(parsed, dict)
+    assert list(parsed) == ["_naay_version", "zeta", "alpha", "mid"]
+
+    sorted_parsed = naay.loads(yaml_text, sort_keys=True)
+
+    assert isinstance(sorted_parsed, dict)
+    assert list(sorted_parsed) == ["_naay_version", "alpha", "mid", "zeta"]
@@ -70,6 +70,26 @@ def test_pure_and_native_match_on_fixture() -> None:
     assert pure_parser.loads(native_dump) == pure_data
 
 
+@pytest.mark.skipif(native_module is None, reason="native extension not available")
+def test_pure_and_native_match_on_compact_sequences() -> None:
+    assert native_module is not None
+    text = (
+        '_naay_version: "1.0"\n'
+        "deep:\n"
+        '  - - - "deep" # note\n'
+        '  - - "a"\n'
+        '    - "b"\n'
+        "  - - name: x\n"
+        "      port: y\n"
+        '  - "c"\n'
+    )
+    native_data = native_module.loads(text)
+
+    assert pure_parser.loads(text) == native_data
+    assert native_data["deep"][0] == [["deep"]]
+    assert native_data["deep"][2] == [{"name": "x", "port": "y"}]
+
+
 def test_naay_and_ruamel_round_trip_functional_parity() -> None:
     text = _fixture_text("stress_test0.yaml")
 
@@ -0,0 +1,28 @@
+from __future__ import annotations
+
+import textwrap
+
+import pytest
+
+import naay
+
+
+@pytest.mark.skipif(naay.USING_PURE_PYTHON, reason="positions come from the native parser")
+def test_parse_error_exposes_position() -> None:
+    yaml_text = textwrap.dedent(
+        """
+        _naay_version: "1.0"
+        server:
+          host: "localhost"
+          this line has no colon
+        """,
+    ).lstrip()
+
+    with pytest.raises(naay.NaayParseError) as excinfo:
+        naay.loads(yaml_text)
+
+    err = excinfo.value
+    assert isinstance(err, ValueError)
+    assert getattr(err, "line") == 4  # noqa: B009
+    assert getattr(err, "column") == 24  # noqa: B009
+    assert getattr(err, "message") == "expected ':' in mapping entry"  # noqa: B009
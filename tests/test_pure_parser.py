@@ -105,6 +105,36 @@ def test_inline_map_in_sequence() -> None:
     ]
 
 
+def test_quoted_keys_may_contain_colons() -> None:
+    yaml_text = textwrap.dedent(
+        """
+                _naay_version: "1.0"
+                "a:b": x
+                seq:
+                    - "c:d": y
+                """,
+    ).strip()
+    data = _load_yaml(yaml_text)
+    assert data["a:b"] == "x"
+    assert data["seq"] == [{"c:d": "y"}]
+    assert parser.loads(parser.dumps(data)) == data
+
+
+def test_compact_nested_sequences() -> None:
+    yaml_text = textwrap.dedent(
+        """
+                _naay_version: "1.0"
+                deep:
+                    - - - "deep"
+                    - - "a"
+                      - "b"
+                    - "c"
+                """,
+    ).strip()
+    data = _load_yaml(yaml_text)
+    assert data["deep"] == [[["deep"]], ["a", "b"], "c"]
+
+
 def test_anchor_without_nested_value_errors() -> None:
     yaml_text = textwrap.dedent(
         """
@@ -126,3 +156,12 @@ def test_unknown_anchor_reference_errors() -> None:
     ).strip()
     with pytest.raises(parser.NaayParseError, match="unknown anchor"):
         parser.loads(yaml_text)
+
+
+def test_block_scalar_keeps_relative_indentation() -> None:
+    text = '_naay_version: "1.0"\ncode: |\n  if ok:\n      run()\n\n  done\n'
+
+    assert parser.loads(text) == {
+        "_naay_version": "1.0",
+        "code": "if ok:\n    run()\n\ndone",
+    }
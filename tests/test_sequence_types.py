@@ -0,0 +1,21 @@
+from __future__ import annotations
+
+import naay
+
+
+def test_tuples_and_sets_dump_as_sequences() -> None:
+    data = {
+        "_naay_version": "1.0",
+        "pair": ("a", "b"),
+        "tags": frozenset({"beta", "alpha"}),
+        "nested": [("x",), {"z", "y"}],
+    }
+
+    dumped = naay.dumps(data)  # type: ignore[arg-type]
+
+    assert naay.loads(dumped) == {
+        "_naay_version": "1.0",
+        "pair": ["a", "b"],
+        "tags": ["alpha", "beta"],
+        "nested": [["x"], ["y", "z"]],
+    }